@@ -1,7 +1,7 @@
 use crate::engine::primitives::quaternion::Quaternion;
 use crate::engine::primitives::transformation::Transformation3D;
 use crate::engine::primitives::vector::Vector3;
-use crate::engine::primitives::vertex::{Vertex2D, Vertex3D};
+use crate::engine::primitives::vertex::Vertex3D;
 use crate::engine::primitives::{transformation::Transformation2D, vector::Vector2};
 use crate::engine::traits::update_entity::UpdateEntity;
 use rand::Rng;
@@ -89,12 +89,9 @@ impl Entity3D {
         self.transformation.set_rotation(rotation);
     }
 
-    pub fn set_angle(&mut self, angle: f32) {
-        self.transformation.set_angle(angle);
-    }
-
-    pub fn set_axis(&mut self, axis: Vector3<f32>) {
-        self.transformation.set_axis(axis);
+    /// Turn by `rotation` on top of the current rotation, see Transformation3D::rotate.
+    pub fn rotate(&mut self, rotation: Quaternion<f32>) {
+        self.transformation.rotate(rotation);
     }
 
     pub fn position(&self) -> Vector3<f32> {
@@ -159,6 +156,10 @@ pub struct RawEntity2D {
     rotation: [[f32; 2]; 2],
     scale: [[f32; 2]; 2],
     origin: [u32; 2],
+    shade: f32,
+    // the region of the texture drawn on the quad, in UV coordinates
+    tex_offset: [f32; 2],
+    tex_size: [f32; 2],
 }
 
 impl RawEntity2D {
@@ -202,6 +203,24 @@ impl RawEntity2D {
                     shader_location: 7,
                     format: wgpu::VertexFormat::Uint32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[u32; 4]>() + mem::size_of::<[f32; 8]>())
+                        as wgpu::BufferAddress,
+                    shader_location: 8,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[u32; 4]>() + mem::size_of::<[f32; 9]>())
+                        as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[u32; 4]>() + mem::size_of::<[f32; 11]>())
+                        as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
             ],
         }
     }
@@ -212,10 +231,16 @@ pub struct Entity2D {
     position: Vector2<u32>,
     rotation: f32,
     scale: f32,
+    // scale can be set per axis, eg. for wall strips which are one pixel wide but much taller
+    scale_xy: Vector2<f32>,
     transformation: Transformation2D,
     origin: Vector2<u32>,
     tex_id: u32,
-    vertices: [Vertex2D; 4], // vertices and texture coordinates change the shapes formed whenever the camera moves...
+    // brightness multiplier applied to the texture colour, used for distance shading
+    shade: f32,
+    // the region of the texture drawn on the entity's quad, see set_tex_region
+    tex_offset: Vector2<f32>,
+    tex_size: Vector2<f32>,
 }
 
 impl Entity2D {
@@ -227,33 +252,18 @@ impl Entity2D {
         origin: Vector2<u32>,
     ) -> Self {
         let id = unsafe { Entity2D::create_id() };
-        let vertices = [
-            Vertex2D {
-                position: [1.0, 1.0],
-                tex_pos: [1.0, 1.0],
-            },
-            Vertex2D {
-                position: [0.0, 1.0],
-                tex_pos: [0.0, 1.0],
-            },
-            Vertex2D {
-                position: [0.0, 0.0],
-                tex_pos: [0.0, 0.0],
-            },
-            Vertex2D {
-                position: [1.0, 0.0],
-                tex_pos: [1.0, 0.0],
-            },
-        ];
         Self {
             id,
             position,
             rotation,
             scale,
+            scale_xy: Vector2 { x: scale, y: scale },
             transformation: Transformation2D::new(rotation, scale),
             origin,
             tex_id,
-            vertices,
+            shade: 1.0,
+            tex_offset: Vector2 { x: 0.0, y: 0.0 },
+            tex_size: Vector2 { x: 1.0, y: 1.0 },
         }
     }
 
@@ -275,6 +285,35 @@ impl Entity2D {
 
     pub fn set_scale(&mut self, scale: f32) {
         self.scale = scale;
+        self.scale_xy = Vector2 { x: scale, y: scale };
+    }
+
+    pub fn scale_xy(&self) -> Vector2<f32> {
+        self.scale_xy
+    }
+
+    /// Scale the width and height separately, this overrides the uniform scale.
+    pub fn set_scale_xy(&mut self, x: f32, y: f32) {
+        self.scale_xy = Vector2 { x, y };
+    }
+
+    pub fn shade(&self) -> f32 {
+        self.shade
+    }
+
+    pub fn set_shade(&mut self, shade: f32) {
+        self.shade = shade;
+    }
+
+    /// Map the entity's quad onto a region of its texture, offset and size are in UV coordinates.
+    pub fn set_tex_region(&mut self, offset: Vector2<f32>, size: Vector2<f32>) {
+        self.tex_offset = offset;
+        self.tex_size = size;
+    }
+
+    /// The offset and size of the region of the texture drawn, see set_tex_region.
+    pub fn tex_region(&self) -> (Vector2<f32>, Vector2<f32>) {
+        (self.tex_offset, self.tex_size)
     }
 
     pub fn set_position(&mut self, x: u32, y: u32) {
@@ -288,6 +327,9 @@ impl Entity2D {
             rotation: self.transformation.rotation(),
             scale: self.transformation.scale(),
             origin: self.origin.to_raw(),
+            shade: self.shade,
+            tex_offset: self.tex_offset.to_raw(),
+            tex_size: self.tex_size.to_raw(),
         }
     }
 
@@ -295,10 +337,6 @@ impl Entity2D {
         self.tex_id
     }
 
-    pub fn vertices(&self) -> &[Vertex2D; 4] {
-        &self.vertices
-    }
-
     unsafe fn create_id() -> u32 {
         let mut num = rand::thread_rng().gen_range(0..u32::MAX);
         let mut entity_ids = ENTITY_IDS.lock().unwrap();
@@ -329,6 +367,7 @@ impl Drop for Entity2D {
 
 impl UpdateEntity for Entity2D {
     fn update(&mut self) {
-        self.transformation.update(self.rotation, self.scale);
+        self.transformation
+            .update(self.rotation, self.scale_xy.x, self.scale_xy.y);
     }
 }
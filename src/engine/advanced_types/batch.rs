@@ -1,11 +1,34 @@
 use crate::engine::actors::entity::{Entity2D, RawEntity2D};
 use crate::engine::primitives::vertex::Vertex2D;
 use crate::engine::texture::Texture2D;
-use num_traits::abs;
 use rand::Rng;
+use std::ops::Range;
+use std::rc::Rc;
 use std::sync::Mutex;
 use wgpu::util::DeviceExt;
 
+// Every entity is drawn as an instance of this unit quad, scaled, rotated, positioned and
+// textured by its RawEntity2D
+const QUAD_VERTICES: [Vertex2D; 4] = [
+    Vertex2D {
+        position: [1.0, 1.0],
+        tex_pos: [1.0, 1.0],
+    },
+    Vertex2D {
+        position: [0.0, 1.0],
+        tex_pos: [0.0, 1.0],
+    },
+    Vertex2D {
+        position: [0.0, 0.0],
+        tex_pos: [0.0, 0.0],
+    },
+    Vertex2D {
+        position: [1.0, 0.0],
+        tex_pos: [1.0, 0.0],
+    },
+];
+const QUAD_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
+
 pub static mut BATCH_IDS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
 
 // TODO: Implement Batch3D
@@ -24,9 +47,7 @@ pub static mut BATCH_IDS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
 pub struct Batch2D {
     id: u32,
     entity_data: Vec<RawEntity2D>,
-    vertex_data: Vec<Vertex2D>,
-    indices: Vec<u16>,
-    texture: Texture2D,
+    texture: Rc<Texture2D>,
     entity_buffer: Option<wgpu::Buffer>,
     entity_count: usize,
     vertex_buffer: Option<wgpu::Buffer>,
@@ -42,12 +63,20 @@ impl Batch2D {
         device: &wgpu::Device,
         bind_group_layout: &wgpu::BindGroupLayout,
     ) -> Self {
-        let id = unsafe { Batch2D::create_id() };
         let texture = Texture2D::new(texture_path, queue, device, bind_group_layout)
             .expect(format!("Could not find image {}", texture_path).as_str());
+        Batch2D::with_texture(texture)
+    }
+
+    /// Create a batch from an already loaded texture
+    pub fn with_texture(texture: Texture2D) -> Self {
+        Batch2D::with_shared_texture(Rc::new(texture))
+    }
+
+    /// Create a batch drawing with a texture that other batches use too, see TextureStore.
+    pub fn with_shared_texture(texture: Rc<Texture2D>) -> Self {
+        let id = unsafe { Batch2D::create_id() };
         let entity_data = Vec::new();
-        let vertex_data = Vec::new();
-        let indices = Vec::new();
         let entity_buffer = None;
         let entity_count: usize = 0;
         let vertex_buffer = None;
@@ -56,8 +85,6 @@ impl Batch2D {
         Self {
             id,
             entity_data,
-            vertex_data,
-            indices,
             texture,
             entity_buffer,
             entity_count,
@@ -67,21 +94,31 @@ impl Batch2D {
         }
     }
 
-    pub fn update(&mut self, entities: &Vec<Entity2D>, device: &wgpu::Device, queue: &wgpu::Queue) {
-        let mut recreate_index = false;
-        if entities.len() != self.entity_count {
-            recreate_index = true;
-        }
+    pub fn update(&mut self, entities: &[Entity2D], device: &wgpu::Device, queue: &wgpu::Queue) {
         self.entity_data.clear();
-        self.vertex_data.clear();
-        for entity in entities {
-            self.entity_data.push(entity.to_raw());
-            for vertex in entity.vertices() {
-                self.vertex_data.push(*vertex);
-            }
+        self.entity_data
+            .extend(entities.iter().map(|entity| entity.to_raw()));
+
+        // the quad is the same for every batch and never changes
+        if self.vertex_buffer.is_none() {
+            self.vertex_buffer = Some(device.create_buffer_init(
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some("Vertex Buffer"),
+                    contents: bytemuck::cast_slice(&QUAD_VERTICES),
+                    usage: wgpu::BufferUsages::VERTEX,
+                },
+            ));
+            self.index_buffer = Some(device.create_buffer_init(
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some("Index Buffer"),
+                    contents: bytemuck::cast_slice(&QUAD_INDICES),
+                    usage: wgpu::BufferUsages::INDEX,
+                },
+            ));
         }
 
-        if !self.updated {
+        // The buffer can only be written in place while the number of entities stays the same
+        if !self.updated || entities.len() != self.entity_count {
             self.entity_buffer = Some(device.create_buffer_init(
                 &wgpu::util::BufferInitDescriptor {
                     label: Some("Entity Buffer"),
@@ -89,13 +126,6 @@ impl Batch2D {
                     usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
                 },
             ));
-            self.vertex_buffer = Some(device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: Some("Vertex Buffer"),
-                    contents: bytemuck::cast_slice(&self.vertex_data),
-                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                },
-            ));
             self.updated = true;
         } else {
             queue.write_buffer(
@@ -103,46 +133,39 @@ impl Batch2D {
                 0,
                 bytemuck::cast_slice(&self.entity_data),
             );
-            queue.write_buffer(
-                &self.vertex_buffer.as_ref().unwrap(),
-                0,
-                bytemuck::cast_slice(&self.vertex_data),
-            );
         }
+        self.entity_count = entities.len();
+    }
 
-        if recreate_index {
-            let mut difference = entities.len() as i32 - self.entity_count as i32;
-            self.entity_count += difference as usize;
-            if difference < 0 {
-                difference = abs(difference);
-                for _ in 0..difference {
-                    for _ in 0..6 {
-                        self.indices
-                            .pop()
-                            .expect("Tried to pop more entities off batch than exists");
-                    }
-                }
-            } else if difference > 0 {
-                for _ in 0..difference {
-                    self.indices.push(0);
-                    self.indices.push(1);
-                    self.indices.push(2);
-                    self.indices.push(0);
-                    self.indices.push(2);
-                    self.indices.push(3);
-                }
-            } else {
-                panic!("number of entities didn't change but still entered update index");
-            }
-            self.index_buffer = Some(device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: Some("Index Buffer"),
-                    contents: bytemuck::cast_slice(&self.indices),
-                    usage: wgpu::BufferUsages::INDEX,
-                },
-            ));
-            self.entity_count = entities.len();
+    /// Draw every entity in the batch as instances of one quad.
+    /// The pipeline and any bind groups other than the texture (group 0) must already be set.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.draw_range(render_pass, 0..self.entity_count as u32);
+    }
+
+    /// Draw only the entities in `entities`, in order. Used when batches have to be interleaved
+    /// to keep a draw order, eg. sprites sorted by distance.
+    pub fn draw_range<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, entities: Range<u32>) {
+        let (Some(vertex_buffer), Some(entity_buffer), Some(index_buffer)) = (
+            self.vertex_buffer.as_ref(),
+            self.entity_buffer.as_ref(),
+            self.index_buffer.as_ref(),
+        ) else {
+            return;
+        };
+        let entities = entities.start..entities.end.min(self.entity_count as u32);
+        if entities.is_empty() {
+            return;
         }
+        render_pass.set_bind_group(0, self.texture.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, entity_buffer.slice(..));
+        render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..QUAD_INDICES.len() as u32, 0, entities);
+    }
+
+    pub fn texture(&self) -> &Texture2D {
+        &self.texture
     }
 
     pub fn bind_group(&self) -> &wgpu::BindGroup {
@@ -5,7 +5,8 @@ use wgpu::util::DeviceExt;
 pub struct Camera3D {
     position: Vector3<f32>,
     target: Vector3<f32>,
-    // the angle between the top and bottom of the near plane relative to the position.
+    // the angle between the top and bottom of the near plane relative to the position, in
+    // degrees.
     fov: f32,
     // width / height of viewport
     aspect_ratio: f32,
@@ -24,6 +25,7 @@ pub struct Camera3D {
 // Implement Camera position
 // Cleanup
 impl Camera3D {
+    /// `fov` is the vertical field of view in degrees.
     pub fn new(fov: f32, screen_width: u32, screen_height: u32, device: &wgpu::Device) -> Self {
         let position = Vector3 {
             x: 0.0,
@@ -38,14 +40,7 @@ impl Camera3D {
         let aspect_ratio = screen_width as f32 / screen_height as f32;
         let z_near: f32 = 0.1;
         let z_far: f32 = 100.0;
-        // IMPLEMENT ASPECT RATIO
-        let matrix = [
-            [1.0 / (aspect_ratio * (fov / 2.0).tan()), 0.0, 0.0, 0.0],
-            [0.0, 1.0 / (fov / 2.0).tan(), 0.0, 0.0],
-            [0.0, 0.0, z_far / (z_far - z_near), 1.0],
-            [0.0, 0.0, (-z_far * z_near) / (z_far - z_near), 0.0],
-        ];
-        let projection = Matrix4::new(matrix);
+        let projection = Matrix4::perspective(fov.to_radians(), aspect_ratio, z_near, z_far);
         let matrix = projection.to_raw();
 
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -116,27 +111,29 @@ impl Camera3D {
         });
     }
 
+    /// Change the aspect ratio of the projection to match a viewport of a new size, takes effect
+    /// from the next call to look.
+    pub fn resize(&mut self, screen_width: u32, screen_height: u32) {
+        self.aspect_ratio = screen_width as f32 / screen_height as f32;
+        self.projection = Matrix4::new(self.create_projection_matrix());
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
     pub fn look(&mut self, transformation: &Matrix4<f32>) {
         self.matrix = (&self.projection * transformation).to_raw();
     }
 
     // column major
     pub fn create_projection_matrix(&self) -> [[f32; 4]; 4] {
-        [
-            [
-                1.0 / (self.aspect_ratio * (self.fov / 2.0).tan()),
-                0.0,
-                0.0,
-                0.0,
-            ],
-            [0.0, 1.0 / (self.fov / 2.0).tan(), 0.0, 0.0],
-            [0.0, 0.0, self.z_far / (self.z_far - self.z_near), 1.0],
-            [
-                0.0,
-                0.0,
-                (-self.z_far * self.z_near) / (self.z_far - self.z_near),
-                0.0,
-            ],
-        ]
+        Matrix4::perspective(
+            self.fov.to_radians(),
+            self.aspect_ratio,
+            self.z_near,
+            self.z_far,
+        )
+        .to_raw()
     }
 }
@@ -1,37 +1,21 @@
 use crate::engine::primitives::{
+    angle::Degrees,
     matrix::Matrix4,
-    quaternion::{self, Quaternion},
+    quaternion::Quaternion,
     vector::{Vector2, Vector3},
 };
 
 pub struct CameraController3D {
     position: Matrix4<f32>,
+    // yaw (x) and pitch (y) in degrees
     rotation: Vector2<f32>,
-    quat: Quaternion<f32>,
 }
 
 impl CameraController3D {
     pub fn new() -> Self {
-        let position = Matrix4::new([
-            [1.0, 0.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ]);
-        let quat = Quaternion::new(
-            Vector3 {
-                x: 1.0,
-                y: 1.0,
-                z: 1.0,
-            },
-            0.0,
-        );
+        let position = Matrix4::identity();
         let rotation = Vector2 { x: 1.0, y: 1.0 };
-        Self {
-            position,
-            rotation,
-            quat,
-        }
+        Self { position, rotation }
     }
 
     pub fn process_keyboard(&mut self, dx: f32, dz: f32) {
@@ -39,6 +23,18 @@ impl CameraController3D {
         self.position.set(3, 2, self.position.get(3, 2) + dz);
     }
 
+    /// Set the view translation directly rather than accumulating keyboard input.
+    /// This is the translation applied to the world, so it is the inverse of the camera position.
+    pub fn set_translation(&mut self, translation: Vector3<f32>) {
+        self.position
+            .set_column(3, [translation.x, translation.y, translation.z, 1.0]);
+    }
+
+    /// Set the accumulated mouse rotation directly, x being yaw and y being pitch, in degrees.
+    pub fn set_rotation(&mut self, rotation: Vector2<f32>) {
+        self.rotation = rotation;
+    }
+
     pub fn process_camera(&mut self, dx: f32, dy: f32) {
         self.rotation.x += dx;
         self.rotation.y += dy;
@@ -57,16 +53,14 @@ impl CameraController3D {
         println!("x rot: {}", self.rotation.x);
     }
 
-    pub fn build_transformation(&mut self) -> Matrix4<f32> {
-        let magnitude = self.rotation.magnitude();
-        let mut axis = Vector3 {
-            x: -self.rotation.y,
-            y: -self.rotation.x,
-            z: 0.0,
-        };
-        axis.normalise();
-        self.quat.set_axis(axis);
-        self.quat.set_angle(magnitude);
-        &self.quat.to_matrix() * &self.position
+    pub fn build_transformation(&self) -> Matrix4<f32> {
+        // the view turns the world the opposite way to the camera, undoing the yaw and then the
+        // pitch
+        let camera = Quaternion::from_euler(
+            Degrees(self.rotation.y),
+            Degrees(self.rotation.x),
+            Degrees(0.0),
+        );
+        &camera.conjugate().to_matrix() * &self.position
     }
 }
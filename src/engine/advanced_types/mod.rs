@@ -1,3 +1,5 @@
 pub mod batch;
 pub mod camera;
 pub mod camera_controller;
+pub mod screen;
+pub mod sprite_sheet;
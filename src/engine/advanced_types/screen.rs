@@ -0,0 +1,113 @@
+use crate::engine::actors::entity::RawEntity2D;
+use crate::engine::config::DepthConfig;
+use crate::engine::pipeline::{PipelineBuilder, PipelineCache, PipelineHandle};
+use crate::engine::primitives::vertex::Vertex2D;
+use anyhow::Result;
+use wgpu::util::DeviceExt;
+
+// Batch2D entities are positioned in pixels from the top left of the screen, shader_2d.wgsl
+// converts them to clip space using the screen size held in this uniform.
+pub struct Screen2D {
+    width: u32,
+    height: u32,
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Screen2D {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Screen buffer"),
+            contents: bytemuck::cast_slice(&Screen2D::raw(width, height)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Screen bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Screen bind group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Self {
+            width,
+            height,
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Change the size entities are positioned against, after the window is resized.
+    pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&Screen2D::raw(width, height)),
+        );
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    // the last two components are padding
+    fn raw(width: u32, height: u32) -> [f32; 4] {
+        [width as f32, height as f32, 0.0, 0.0]
+    }
+}
+
+/// The pipeline for drawing screen space Batch2D entities, see shader_2d.wgsl. `depth` has to
+/// match the depth buffer of the pass it is drawn in, if there is one.
+pub(crate) fn create_pipeline_2d(
+    device: &wgpu::Device,
+    pipelines: &mut PipelineCache,
+    format: wgpu::TextureFormat,
+    texture_bind_group_layout: &wgpu::BindGroupLayout,
+    screen_bind_group_layout: &wgpu::BindGroupLayout,
+    depth: Option<&DepthConfig>,
+) -> Result<PipelineHandle> {
+    pipelines.get(
+        device,
+        &PipelineBuilder::new("Render Pipeline 2D", "shader_2d", format)
+            .bind_group_layout(texture_bind_group_layout)
+            .bind_group_layout(screen_bind_group_layout)
+            .vertex_layout(Vertex2D::descriptor())
+            .vertex_layout(RawEntity2D::descriptor())
+            // entities can be mirrored by their scale, so both faces are drawn
+            .cull_mode(None)
+            .depth(depth),
+    )
+}
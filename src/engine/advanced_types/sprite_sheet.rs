@@ -0,0 +1,183 @@
+use crate::engine::primitives::vector::Vector2;
+use crate::engine::texture::Texture2D;
+use anyhow::{bail, Result};
+
+// A sprite sheet splits a single Texture2D into a grid of equally sized frames. Each column
+// shows the sprite from a different angle and each row is a frame of its animation, so
+// an 8 angle walk cycle of 4 frames is an 8x4 grid:
+//
+//          front  front-left ...  back  ...  front-right
+// frame 0  [    ] [    ]          [    ]     [    ]
+// frame 1  [    ] [    ]          [    ]     [    ]
+//
+// Column 0 is the sprite seen from the front, then each column after it is seen from
+// 360 / angles degrees further round, turning the same way as PlayerPose angles.
+// Frames are picked by working out UV offsets into the texture, rather than having separate
+// textures, so every frame of a sprite can be drawn from the same batch.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteSheet {
+    angles: u32,
+    frames: u32,
+}
+
+impl SpriteSheet {
+    /// A sheet with `angles` columns and `frames` rows, both must be at least 1.
+    pub fn new(angles: u32, frames: u32) -> Result<Self> {
+        if angles == 0 || frames == 0 {
+            bail!(
+                "A sprite sheet needs at least one angle and one frame, found {} angles and {} frames",
+                angles,
+                frames
+            );
+        }
+        Ok(Self { angles, frames })
+    }
+
+    /// Work out the grid from the size of a single frame in pixels. The texture must be an exact
+    /// number of frames wide and high.
+    pub fn from_texture(texture: &Texture2D, frame_width: u32, frame_height: u32) -> Result<Self> {
+        let dimensions = texture.dimensions();
+        SpriteSheet::from_size(
+            dimensions.width,
+            dimensions.height,
+            frame_width,
+            frame_height,
+        )
+    }
+
+    /// The same as from_texture, for a texture `width` by `height` pixels.
+    pub fn from_size(width: u32, height: u32, frame_width: u32, frame_height: u32) -> Result<Self> {
+        if frame_width == 0
+            || frame_height == 0
+            || !width.is_multiple_of(frame_width)
+            || !height.is_multiple_of(frame_height)
+        {
+            bail!(
+                "A {}x{} texture can't be split into {}x{} frames",
+                width,
+                height,
+                frame_width,
+                frame_height
+            );
+        }
+        SpriteSheet::new(width / frame_width, height / frame_height)
+    }
+
+    pub fn angles(&self) -> u32 {
+        self.angles
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Which angle column to show for a sprite at `position` facing `facing` degrees,
+    /// seen from `viewer`. The nearest column is picked, so column 0 covers the sprite's
+    /// facing +- half a step.
+    pub fn angle_index(&self, position: Vector2<f32>, facing: f32, viewer: Vector2<f32>) -> u32 {
+        let to_viewer = (viewer.y - position.y).atan2(viewer.x - position.x);
+        let step = 360.0 / self.angles as f32;
+        let relative = (to_viewer.to_degrees() - facing).rem_euclid(360.0);
+        (relative / step).round() as u32 % self.angles
+    }
+
+    /// The UV offset and size of one frame within the texture. Frames past the end of the
+    /// animation wrap around.
+    pub fn frame_region(&self, angle: u32, frame: u32) -> (Vector2<f32>, Vector2<f32>) {
+        let size = Vector2 {
+            x: 1.0 / self.angles as f32,
+            y: 1.0 / self.frames as f32,
+        };
+        let offset = Vector2 {
+            x: (angle % self.angles) as f32 * size.x,
+            y: (frame % self.frames) as f32 * size.y,
+        };
+        (offset, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: Vector2<f32> = Vector2 { x: 0.0, y: 0.0 };
+
+    // a viewer 5 tiles from the origin, `degrees` round from +x
+    fn viewer(degrees: f32) -> Vector2<f32> {
+        let radians = degrees.to_radians();
+        Vector2 {
+            x: radians.cos() * 5.0,
+            y: radians.sin() * 5.0,
+        }
+    }
+
+    fn eight_angles() -> SpriteSheet {
+        SpriteSheet::new(8, 4).unwrap()
+    }
+
+    #[test]
+    fn viewer_in_front_or_behind() {
+        let sheet = eight_angles();
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(0.0)), 0);
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(180.0)), 4);
+        // the sprite turns with its facing
+        assert_eq!(sheet.angle_index(ORIGIN, 90.0, viewer(90.0)), 0);
+        assert_eq!(sheet.angle_index(ORIGIN, 90.0, viewer(270.0)), 4);
+        assert_eq!(sheet.angle_index(ORIGIN, -90.0, viewer(270.0)), 0);
+        // only the direction to the viewer matters, not where the sprite is
+        let position = Vector2 { x: 10.0, y: -3.0 };
+        let behind = Vector2 { x: 4.0, y: -3.0 };
+        assert_eq!(sheet.angle_index(position, 0.0, behind), 4);
+    }
+
+    #[test]
+    fn angles_round_to_the_nearest_column() {
+        let sheet = eight_angles();
+        // columns are 45 degrees apart, each covering 22.5 degrees either side
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(22.4)), 0);
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(22.6)), 1);
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(67.4)), 1);
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(67.6)), 2);
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(-22.4)), 0);
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(-22.6)), 7);
+    }
+
+    #[test]
+    fn angles_wrap_around_at_360() {
+        let sheet = eight_angles();
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(350.0)), 0);
+        assert_eq!(sheet.angle_index(ORIGIN, 0.0, viewer(330.0)), 7);
+        assert_eq!(sheet.angle_index(ORIGIN, 350.0, viewer(10.0)), 0);
+        assert_eq!(sheet.angle_index(ORIGIN, 10.0, viewer(350.0)), 0);
+        assert_eq!(sheet.angle_index(ORIGIN, 720.0, viewer(45.0)), 1);
+        // a single angle always shows the same column
+        let single = SpriteSheet::new(1, 1).unwrap();
+        assert_eq!(single.angle_index(ORIGIN, 0.0, viewer(200.0)), 0);
+    }
+
+    #[test]
+    fn frame_region_picks_a_cell() {
+        let sheet = eight_angles();
+        let (offset, size) = sheet.frame_region(3, 2);
+        assert_eq!(size, Vector2 { x: 0.125, y: 0.25 });
+        assert_eq!(offset, Vector2 { x: 0.375, y: 0.5 });
+        assert_eq!(sheet.frame_region(0, 0).0, ORIGIN);
+        // frames past the end of the animation wrap around
+        assert_eq!(sheet.frame_region(9, 5), sheet.frame_region(1, 1));
+    }
+
+    #[test]
+    fn sheets_need_whole_frames() {
+        let sheet = SpriteSheet::from_size(256, 128, 32, 32).unwrap();
+        assert_eq!((sheet.angles(), sheet.frames()), (8, 4));
+        let error = SpriteSheet::from_size(100, 64, 32, 32).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "A 100x64 texture can't be split into 32x32 frames"
+        );
+        assert!(SpriteSheet::from_size(64, 64, 0, 32).is_err());
+        assert!(SpriteSheet::new(0, 1).is_err());
+        assert!(SpriteSheet::new(1, 0).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::engine::config::{CursorGrab, EngineConfig};
+use crate::engine::render_data::RenderData;
+use crate::engine::time::GameLoop;
+use anyhow::{Context, Result};
+use std::time::Instant;
+use winit::{
+    dpi::PhysicalSize,
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::{CursorGrabMode, Fullscreen, WindowBuilder},
+};
+
+// Games are written as an App and started with run_app, which owns the window and the event loop.
+// Each frame the engine runs as many fixed updates as are due (see GameLoop), calling the
+// engine's own update (map, doors, player movement) and then the app's, then lets the app
+// prepare the frame before drawing it.
+
+pub trait App: Sized {
+    /// Called once the window and renderer are ready, before the first frame.
+    fn init(render_data: &mut RenderData) -> Result<Self>;
+
+    /// Called once per fixed timestep of `dt` seconds, see RenderData::time for the rest of the
+    /// timing information.
+    fn update(&mut self, render_data: &mut RenderData, dt: f32);
+
+    /// Called before each frame is drawn, after the frame's updates. Anything which only affects
+    /// how the frame looks goes here.
+    fn render(&mut self, _render_data: &mut RenderData) {}
+
+    /// Called for every event from the window before the engine handles it.
+    /// Return true to stop the engine handling the event itself.
+    fn on_event(&mut self, _render_data: &mut RenderData, _event: &Event<()>) -> bool {
+        false
+    }
+
+    /// Called when a frame can't be drawn, see SurfaceErrorPolicy for which surface errors end
+    /// up here. By default the error is logged and the engine exits with a failure code.
+    fn on_error(&mut self, render_data: &mut RenderData, error: anyhow::Error) {
+        log::error!("Could not render the frame: {:?}", error);
+        render_data.request_exit();
+    }
+}
+
+/// Create a window from `config` and run `G` until the window is closed or the app asks to exit,
+/// see RenderData::request_exit. Only returns if the engine fails to start.
+pub fn run_app<G: App + 'static>(config: EngineConfig) -> Result<()> {
+    config.validate()?;
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_inner_size(PhysicalSize::new(config.width, config.height))
+        .with_title(&config.title)
+        .with_resizable(config.resizable)
+        .with_fullscreen(config.fullscreen.then_some(Fullscreen::Borderless(None)))
+        .build(&event_loop)
+        .context("Could not create the window")?;
+    // not every platform supports both grab modes, so fall back to the other one. Mouse look
+    // still works without a grab, so failing to grab the cursor isn't fatal
+    let grab = match config.cursor_grab {
+        CursorGrab::None => None,
+        CursorGrab::Confined => Some((CursorGrabMode::Confined, CursorGrabMode::Locked)),
+        CursorGrab::Locked => Some((CursorGrabMode::Locked, CursorGrabMode::Confined)),
+    };
+    if let Some((mode, fallback)) = grab {
+        match window
+            .set_cursor_grab(mode)
+            .or_else(|_| window.set_cursor_grab(fallback))
+        {
+            Ok(()) => window.set_cursor_visible(false),
+            Err(error) => log::warn!("Could not grab the cursor, leaving it free: {}", error),
+        }
+    }
+    let main_window_id = window.id();
+    let mut render_data = pollster::block_on(RenderData::new(window, &config))?;
+    let mut app = G::init(&mut render_data)?;
+    let mut game_loop = GameLoop::new(config.updates_per_second);
+    let mut failed = false;
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        if app.on_event(&mut render_data, &event) {
+            if render_data.exit_requested() {
+                exit(control_flow, failed);
+            }
+            return;
+        }
+        match event {
+            Event::WindowEvent {
+                window_id,
+                ref event,
+            } if window_id == main_window_id => {
+                if let WindowEvent::CloseRequested = event {
+                    render_data.request_exit();
+                }
+                render_data.process_inputs(event);
+            }
+            Event::DeviceEvent { ref event, .. } => render_data.device_event(event),
+            Event::RedrawRequested(window_id) if window_id == main_window_id => {
+                game_loop.begin_frame(Instant::now());
+                while game_loop.update() {
+                    render_data.update(game_loop.time());
+                    app.update(&mut render_data, game_loop.time().delta());
+                }
+                render_data.set_time(*game_loop.time());
+                app.render(&mut render_data);
+                if let Err(error) = render_data.render() {
+                    app.on_error(&mut render_data, error);
+                    // the app may have decided to carry on instead
+                    failed = render_data.exit_requested();
+                }
+            }
+            Event::RedrawEventsCleared => {
+                if let Some(window) = render_data.window() {
+                    window.request_redraw();
+                }
+            }
+            _ => (),
+        }
+        if render_data.exit_requested() {
+            exit(control_flow, failed);
+        }
+    })
+}
+
+fn exit(control_flow: &mut ControlFlow, failed: bool) {
+    if failed {
+        control_flow.set_exit_with_code(1);
+    } else {
+        control_flow.set_exit();
+    }
+}
@@ -0,0 +1,447 @@
+use crate::engine::time::DEFAULT_UPDATES_PER_SECOND;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// The engine's settings can be built in code, starting from EngineConfig::default(), or loaded
+// from a TOML or RON file. Any setting missing from a file keeps its default, so a file only
+// needs the settings it changes:
+//
+// title = "My Game"
+// width = 1920
+// height = 1080
+// cursor_grab = "None"
+// present_mode = "AutoVsync"
+//
+// [surface_errors]
+// timeout = "Fail"
+
+/// How the cursor is held while the window has focus.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CursorGrab {
+    // the cursor is free and visible
+    None,
+    // the cursor is kept inside the window and hidden, for mouse look
+    #[default]
+    Confined,
+    // the cursor is kept in place and hidden, for mouse look
+    Locked,
+}
+
+/// When frames are shown, see wgpu::PresentMode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresentMode {
+    AutoVsync,
+    #[default]
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(present_mode: PresentMode) -> Self {
+        match present_mode {
+            PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+/// Which GPU to ask for, see wgpu::PowerPreference.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerPreference {
+    LowPower,
+    #[default]
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(power_preference: PowerPreference) -> Self {
+        match power_preference {
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+/// Formats the depth buffer can use, see wgpu::TextureFormat.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DepthFormat {
+    Depth16Unorm,
+    Depth24Plus,
+    Depth24PlusStencil8,
+    #[default]
+    Depth32Float,
+}
+
+impl From<DepthFormat> for wgpu::TextureFormat {
+    fn from(depth_format: DepthFormat) -> Self {
+        match depth_format {
+            DepthFormat::Depth16Unorm => wgpu::TextureFormat::Depth16Unorm,
+            DepthFormat::Depth24Plus => wgpu::TextureFormat::Depth24Plus,
+            DepthFormat::Depth24PlusStencil8 => wgpu::TextureFormat::Depth24PlusStencil8,
+            DepthFormat::Depth32Float => wgpu::TextureFormat::Depth32Float,
+        }
+    }
+}
+
+/// When a fragment passes the depth test, comparing its depth against the depth buffer's,
+/// see wgpu::CompareFunction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareFunction {
+    Never,
+    #[default]
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl From<CompareFunction> for wgpu::CompareFunction {
+    fn from(compare: CompareFunction) -> Self {
+        match compare {
+            CompareFunction::Never => wgpu::CompareFunction::Never,
+            CompareFunction::Less => wgpu::CompareFunction::Less,
+            CompareFunction::Equal => wgpu::CompareFunction::Equal,
+            CompareFunction::LessEqual => wgpu::CompareFunction::LessEqual,
+            CompareFunction::Greater => wgpu::CompareFunction::Greater,
+            CompareFunction::NotEqual => wgpu::CompareFunction::NotEqual,
+            CompareFunction::GreaterEqual => wgpu::CompareFunction::GreaterEqual,
+            CompareFunction::Always => wgpu::CompareFunction::Always,
+        }
+    }
+}
+
+/// How a pipeline uses the depth buffer. Every pipeline drawn in a pass has to use the format of
+/// the pass's depth buffer, the pass clears the buffer to the clear value of the 3D pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DepthConfig {
+    pub format: DepthFormat,
+    pub compare: CompareFunction,
+    // whether fragments which pass the test replace the depth already in the buffer
+    pub write_enabled: bool,
+    // the depth the buffer is cleared to each frame, from 0 (near) to 1 (far)
+    pub clear_value: f32,
+}
+
+impl DepthConfig {
+    /// The same format, but always drawn and never written, for screen space drawing that goes
+    /// over the top of whatever is already there.
+    pub fn overlay(&self) -> Self {
+        Self {
+            compare: CompareFunction::Always,
+            write_enabled: false,
+            ..*self
+        }
+    }
+
+    pub fn depth_stencil_state(&self) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: self.format.into(),
+            depth_write_enabled: self.write_enabled,
+            depth_compare: self.compare.into(),
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        Self {
+            format: DepthFormat::Depth32Float,
+            compare: CompareFunction::Less,
+            write_enabled: true,
+            clear_value: 1.0,
+        }
+    }
+}
+
+/// What render does when the surface can't give it a texture to draw the frame into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SurfaceErrorAction {
+    // reconfigure the surface and try once more, skipping the frame if that fails too
+    Reconfigure,
+    // don't draw this frame and try again with the next one
+    SkipFrame,
+    // return the error from render, see App::on_error
+    Fail,
+}
+
+/// The action taken for each kind of wgpu::SurfaceError.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SurfaceErrorPolicy {
+    // the surface has to be recreated, eg. the window was minimised on some platforms
+    pub lost: SurfaceErrorAction,
+    // the surface no longer matches the window, eg. mid resize
+    pub outdated: SurfaceErrorAction,
+    // the frame took too long to become available
+    pub timeout: SurfaceErrorAction,
+    pub out_of_memory: SurfaceErrorAction,
+}
+
+impl SurfaceErrorPolicy {
+    pub fn action(&self, error: &wgpu::SurfaceError) -> SurfaceErrorAction {
+        match error {
+            wgpu::SurfaceError::Lost => self.lost,
+            wgpu::SurfaceError::Outdated => self.outdated,
+            wgpu::SurfaceError::Timeout => self.timeout,
+            wgpu::SurfaceError::OutOfMemory => self.out_of_memory,
+        }
+    }
+}
+
+impl Default for SurfaceErrorPolicy {
+    fn default() -> Self {
+        Self {
+            lost: SurfaceErrorAction::Reconfigure,
+            outdated: SurfaceErrorAction::Reconfigure,
+            timeout: SurfaceErrorAction::SkipFrame,
+            out_of_memory: SurfaceErrorAction::Fail,
+        }
+    }
+}
+
+/// Settings the engine is started with, see run_app.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+// misspelt settings are an error rather than silently left at their default
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    pub title: String,
+    // inner size of the window in physical pixels
+    pub width: u32,
+    pub height: u32,
+    pub resizable: bool,
+    // borderless fullscreen on the current monitor, F11 switches back and forth
+    pub fullscreen: bool,
+    pub cursor_grab: CursorGrab,
+    pub present_mode: PresentMode,
+    pub power_preference: PowerPreference,
+    // vertical field of view of the 3D camera, in degrees, see Camera3D::new
+    pub camera_fov: f32,
+    // horizontal field of view of the ray caster, in degrees
+    pub player_fov: f32,
+    // fixed updates per second, see GameLoop
+    pub updates_per_second: u32,
+    pub surface_errors: SurfaceErrorPolicy,
+    // depth testing of the 3D pipeline, see DepthConfig
+    pub depth: DepthConfig,
+    // where the engine's WGSL shaders are loaded from, see ShaderRegistry
+    pub shader_directory: String,
+    // reload shaders when their files change, on by default in debug builds
+    pub hot_reload_shaders: bool,
+}
+
+impl EngineConfig {
+    /// Load a config from a file, `.ron` files are read as RON and anything else as TOML.
+    /// The config is validated before it is returned.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read config {}", path.display()))?;
+        let config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("ron") => EngineConfig::from_ron(&source),
+            _ => EngineConfig::from_toml(&source),
+        };
+        config.with_context(|| format!("Could not load config {}", path.display()))
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self> {
+        let config: EngineConfig = toml::from_str(source)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self> {
+        let config: EngineConfig = ron::from_str(source)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    /// Check the settings can be used to start the engine, run_app does this before creating
+    /// the window.
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            bail!(
+                "Window size {}x{} must be at least 1x1",
+                self.width,
+                self.height
+            );
+        }
+        if !(self.camera_fov > 0.0 && self.camera_fov < 180.0) {
+            bail!(
+                "Camera field of view {} must be between 0 and 180 degrees",
+                self.camera_fov
+            );
+        }
+        if !(self.player_fov > 0.0 && self.player_fov < 180.0) {
+            bail!(
+                "Player field of view {} must be between 0 and 180 degrees",
+                self.player_fov
+            );
+        }
+        if !(0.0..=1.0).contains(&self.depth.clear_value) {
+            bail!(
+                "Depth clear value {} must be between 0 and 1",
+                self.depth.clear_value
+            );
+        }
+        if self.updates_per_second == 0 {
+            bail!("Updates per second must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            title: String::from("Strifve Caster"),
+            width: 1280,
+            height: 720,
+            resizable: true,
+            fullscreen: false,
+            cursor_grab: CursorGrab::Confined,
+            present_mode: PresentMode::AutoNoVsync,
+            power_preference: PowerPreference::HighPerformance,
+            camera_fov: 45.0,
+            player_fov: 66.0,
+            updates_per_second: DEFAULT_UPDATES_PER_SECOND,
+            surface_errors: SurfaceErrorPolicy::default(),
+            depth: DepthConfig::default(),
+            shader_directory: String::from("src/shaders"),
+            hot_reload_shaders: cfg!(debug_assertions),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the example from the top of this file
+    const PARTIAL_TOML: &str = r#"
+title = "My Game"
+width = 1920
+height = 1080
+cursor_grab = "None"
+present_mode = "AutoVsync"
+
+[surface_errors]
+timeout = "Fail"
+"#;
+
+    const PARTIAL_RON: &str = r#"(
+    title: "My Game",
+    width: 1920,
+    height: 1080,
+    cursor_grab: None,
+    present_mode: AutoVsync,
+    surface_errors: (timeout: Fail),
+)"#;
+
+    fn partial_config() -> EngineConfig {
+        EngineConfig {
+            title: String::from("My Game"),
+            width: 1920,
+            height: 1080,
+            cursor_grab: CursorGrab::None,
+            present_mode: PresentMode::AutoVsync,
+            surface_errors: SurfaceErrorPolicy {
+                timeout: SurfaceErrorAction::Fail,
+                ..SurfaceErrorPolicy::default()
+            },
+            ..EngineConfig::default()
+        }
+    }
+
+    #[test]
+    fn partial_files_keep_the_defaults() {
+        assert_eq!(
+            EngineConfig::from_toml(PARTIAL_TOML).unwrap(),
+            partial_config()
+        );
+        assert_eq!(
+            EngineConfig::from_ron(PARTIAL_RON).unwrap(),
+            partial_config()
+        );
+        assert_eq!(
+            EngineConfig::from_toml("").unwrap(),
+            EngineConfig::default()
+        );
+        assert_eq!(
+            EngineConfig::from_ron("()").unwrap(),
+            EngineConfig::default()
+        );
+    }
+
+    #[test]
+    fn configs_round_trip() {
+        let config = partial_config();
+        let toml = config.to_toml().unwrap();
+        assert_eq!(EngineConfig::from_toml(&toml).unwrap(), config);
+        let ron = config.to_ron().unwrap();
+        assert_eq!(EngineConfig::from_ron(&ron).unwrap(), config);
+    }
+
+    #[test]
+    fn load_picks_the_format_from_the_extension() {
+        let directory = std::env::temp_dir().join(format!("config-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let toml_path = directory.join("engine.toml");
+        let ron_path = directory.join("engine.ron");
+        std::fs::write(&toml_path, PARTIAL_TOML).unwrap();
+        std::fs::write(&ron_path, PARTIAL_RON).unwrap();
+        assert_eq!(EngineConfig::load(&toml_path).unwrap(), partial_config());
+        assert_eq!(EngineConfig::load(&ron_path).unwrap(), partial_config());
+        assert!(EngineConfig::load(directory.join("missing.toml")).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn misspelt_settings_are_rejected() {
+        assert!(EngineConfig::from_toml("widht = 800").is_err());
+        assert!(EngineConfig::from_toml("[surface_errors]\ntimout = \"Fail\"").is_err());
+        assert!(EngineConfig::from_ron("(widht: 800)").is_err());
+        assert!(EngineConfig::from_toml("cursor_grab = \"Lockd\"").is_err());
+    }
+
+    #[test]
+    fn validate_rejects_unusable_settings() {
+        assert!(EngineConfig::default().validate().is_ok());
+        let error = EngineConfig::from_toml("width = 0").unwrap_err();
+        assert_eq!(error.to_string(), "Window size 0x720 must be at least 1x1");
+        assert!(EngineConfig::from_toml("height = 0").is_err());
+        for fov in ["0.0", "180.0", "-45.0", "nan"] {
+            let camera = format!("camera_fov = {}", fov);
+            let player = format!("player_fov = {}", fov);
+            assert!(EngineConfig::from_toml(&camera).is_err(), "{}", camera);
+            assert!(EngineConfig::from_toml(&player).is_err(), "{}", player);
+        }
+        assert!(EngineConfig::from_toml("player_fov = 179.0").is_ok());
+        assert!(EngineConfig::from_toml("updates_per_second = 0").is_err());
+        assert!(EngineConfig::from_toml("[depth]\nclear_value = 1.5").is_err());
+    }
+}
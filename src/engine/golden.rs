@@ -0,0 +1,246 @@
+use crate::engine::actors::entity::Entity3D;
+use crate::engine::primitives::quaternion::Quaternion;
+use crate::engine::primitives::vector::{Vector2, Vector3};
+use crate::engine::render_data::RenderData;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+// Golden image testing renders a scene headlessly and compares the frame against a reference PNG
+// which has been checked by eye once. This catches regressions in the camera, projection and
+// quaternion code which would otherwise only be noticed when running the game.
+// The references live in tests/golden, see tests/golden.rs. They are (re)generated by running
+// the tests with UPDATE_GOLDEN=1 set: UPDATE_GOLDEN=1 cargo test --test golden
+
+pub const UPDATE_GOLDEN_VAR: &str = "UPDATE_GOLDEN";
+
+pub struct SceneEntity {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: f32,
+}
+
+/// A fully described scene, everything needed to render the same frame every time.
+pub struct GoldenScene {
+    pub width: u32,
+    pub height: u32,
+    // See CameraController3D::set_translation
+    pub camera_translation: Vector3<f32>,
+    // yaw (x) and pitch (y) in degrees
+    pub camera_rotation: Vector2<f32>,
+    pub entities: Vec<SceneEntity>,
+    pub texture_path: String,
+}
+
+impl GoldenScene {
+    /// Render a single frame of the scene using the headless renderer.
+    pub fn render(self) -> Result<image::RgbaImage> {
+        let mut render_data = pollster::block_on(RenderData::new_headless(
+            self.width,
+            self.height,
+            &self.texture_path,
+        ))?;
+        let camera_controller = render_data.camera_controller_mut();
+        camera_controller.set_translation(self.camera_translation);
+        camera_controller.set_rotation(self.camera_rotation);
+        let entities = self
+            .entities
+            .into_iter()
+            .map(|entity| {
+                Entity3D::new(
+                    None,
+                    entity.position,
+                    entity.scale,
+                    entity.rotation,
+                    Vec::new(),
+                    Vec::new(),
+                )
+            })
+            .collect();
+        render_data.set_entities(entities);
+        render_data.render()?;
+        render_data.read_frame()
+    }
+}
+
+pub struct ImageDiff {
+    pub mismatched_pixels: u32,
+    // largest difference of any channel of any pixel
+    pub max_difference: u8,
+    // mismatched pixels are red, the rest are a faded copy of the expected image
+    pub image: image::RgbaImage,
+}
+
+/// Compare two images pixel by pixel. A pixel mismatches when any of its channels differ by more
+/// than `tolerance`.
+pub fn diff_images(
+    actual: &image::RgbaImage,
+    expected: &image::RgbaImage,
+    tolerance: u8,
+) -> Result<ImageDiff> {
+    if actual.dimensions() != expected.dimensions() {
+        bail!(
+            "Image size {:?} does not match the reference size {:?}",
+            actual.dimensions(),
+            expected.dimensions()
+        );
+    }
+    let mut mismatched_pixels = 0;
+    let mut max_difference = 0;
+    let mut image = image::RgbaImage::new(expected.width(), expected.height());
+    for ((actual, expected), diff) in actual
+        .pixels()
+        .zip(expected.pixels())
+        .zip(image.pixels_mut())
+    {
+        let difference = actual
+            .0
+            .iter()
+            .zip(expected.0.iter())
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+        if difference > tolerance {
+            mismatched_pixels += 1;
+            *diff = image::Rgba([255, 0, 0, 255]);
+        } else {
+            let luma = (expected.0[0] as u32 + expected.0[1] as u32 + expected.0[2] as u32) / 3;
+            let faded = (luma / 4) as u8;
+            *diff = image::Rgba([faded, faded, faded, 255]);
+        }
+    }
+    Ok(ImageDiff {
+        mismatched_pixels,
+        max_difference,
+        image,
+    })
+}
+
+/// Compares rendered frames against the PNG references stored in `reference_dir`.
+/// On failure the actual frame and a diff image are written to `output_dir`.
+pub struct GoldenTest {
+    reference_dir: PathBuf,
+    output_dir: PathBuf,
+    tolerance: u8,
+    max_mismatched_pixels: u32,
+}
+
+impl GoldenTest {
+    pub fn new(reference_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            reference_dir: reference_dir.into(),
+            output_dir: PathBuf::from("target/golden"),
+            // software adapters do not rasterise identically to hardware, allow small differences
+            tolerance: 2,
+            max_mismatched_pixels: 0,
+        }
+    }
+
+    pub fn with_tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_max_mismatched_pixels(mut self, max_mismatched_pixels: u32) -> Self {
+        self.max_mismatched_pixels = max_mismatched_pixels;
+        self
+    }
+
+    pub fn with_output_dir(mut self, output_dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = output_dir.into();
+        self
+    }
+
+    /// Check `actual` against the reference `<reference_dir>/<name>.png`.
+    pub fn check(&self, name: &str, actual: &image::RgbaImage) -> Result<()> {
+        let reference_path = self.reference_dir.join(format!("{}.png", name));
+        if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+            save(actual, &reference_path)?;
+            log::info!("Updated golden image {}", reference_path.display());
+            return Ok(());
+        }
+        if !reference_path.exists() {
+            let actual_path = self.output_dir.join(format!("{}.actual.png", name));
+            save(actual, &actual_path)?;
+            bail!(
+                "Missing golden image {}, the rendered frame was written to {}. \
+                 Run with {}=1 to accept it",
+                reference_path.display(),
+                actual_path.display(),
+                UPDATE_GOLDEN_VAR
+            );
+        }
+
+        let expected = image::open(&reference_path)
+            .with_context(|| format!("Could not read golden image {}", reference_path.display()))?
+            .to_rgba8();
+        let diff = diff_images(actual, &expected, self.tolerance)
+            .with_context(|| format!("Golden image {} does not match", name))?;
+        if diff.mismatched_pixels > self.max_mismatched_pixels {
+            let actual_path = self.output_dir.join(format!("{}.actual.png", name));
+            let diff_path = self.output_dir.join(format!("{}.diff.png", name));
+            save(actual, &actual_path)?;
+            save(&diff.image, &diff_path)?;
+            bail!(
+                "Golden image {} does not match: {} pixels differ by more than {} \
+                 (max difference {}), see {}",
+                name,
+                diff.mismatched_pixels,
+                self.tolerance,
+                diff.max_difference,
+                diff_path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+fn save(image: &image::RgbaImage, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    image
+        .save(path)
+        .with_context(|| format!("Could not write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(colour: [u8; 4]) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(4, 3, image::Rgba(colour))
+    }
+
+    #[test]
+    fn differences_within_tolerance_match() {
+        let expected = filled([100, 150, 200, 255]);
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 1, image::Rgba([102, 149, 200, 255]));
+        let diff = diff_images(&actual, &expected, 2).unwrap();
+        assert_eq!(diff.mismatched_pixels, 0);
+        assert_eq!(diff.max_difference, 2);
+    }
+
+    #[test]
+    fn differences_beyond_tolerance_mismatch() {
+        let expected = filled([100, 150, 200, 255]);
+        let mut actual = expected.clone();
+        actual.put_pixel(0, 0, image::Rgba([100, 150, 203, 255]));
+        actual.put_pixel(3, 2, image::Rgba([0, 150, 200, 255]));
+        let diff = diff_images(&actual, &expected, 2).unwrap();
+        assert_eq!(diff.mismatched_pixels, 2);
+        assert_eq!(diff.max_difference, 100);
+        // mismatched pixels are marked in red
+        assert_eq!(diff.image.get_pixel(0, 0), &image::Rgba([255, 0, 0, 255]));
+        assert_eq!(diff.image.get_pixel(3, 2), &image::Rgba([255, 0, 0, 255]));
+        assert_ne!(diff.image.get_pixel(1, 1), &image::Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn images_of_different_sizes_do_not_match() {
+        let expected = filled([0, 0, 0, 255]);
+        let actual = image::RgbaImage::new(3, 4);
+        assert!(diff_images(&actual, &expected, 255).is_err());
+    }
+}
@@ -1,6 +1,16 @@
 pub mod actors;
 pub mod advanced_types;
+pub mod app;
+pub mod config;
+pub mod golden;
+pub mod pipeline;
+pub mod preprocessor;
 pub mod primitives;
+pub mod raycasting;
 pub mod render_data;
+pub mod render_graph;
+pub mod shader;
 pub mod texture;
+pub mod time;
 pub mod traits;
+pub mod world;
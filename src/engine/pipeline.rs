@@ -0,0 +1,544 @@
+use crate::engine::config::DepthConfig;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Render pipelines are described with a PipelineBuilder and fetched from the PipelineCache, which
+// only builds a pipeline the first time it is asked for one with that description. Renderers
+// drawing the same way (eg. walls and sprites) share a pipeline rather than each building their
+// own. Shaders are added to the cache by name and pipelines refer to them by that name, replacing
+// a shader rebuilds every pipeline built from it.
+
+/// Everything that makes one pipeline different from another. Bind group layouts are compared by
+/// id, wgpu gives layouts with the same entries the same id.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    shader: String,
+    vertex_entry: String,
+    fragment_entry: String,
+    bind_group_layouts: Vec<wgpu::Id<wgpu::BindGroupLayout>>,
+    vertex_layouts: Vec<VertexLayoutKey>,
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+    depth: Option<wgpu::DepthStencilState>,
+    topology: wgpu::PrimitiveTopology,
+    // only set for strip topologies, so list pipelines don't differ by it
+    strip_index_format: Option<wgpu::IndexFormat>,
+    cull_mode: Option<wgpu::Face>,
+}
+
+impl PipelineKey {
+    pub fn shader(&self) -> &str {
+        &self.shader
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct VertexLayoutKey {
+    array_stride: wgpu::BufferAddress,
+    step_mode: wgpu::VertexStepMode,
+    attributes: Vec<wgpu::VertexAttribute>,
+}
+
+/// Describes a render pipeline drawing into a single colour target. By default triangle lists
+/// are drawn with both faces, alpha blending, no depth testing, and the entry points vs_main and
+/// fs_main. Strips are drawn with u32 indices unless told otherwise.
+pub struct PipelineBuilder<'a> {
+    label: &'a str,
+    shader: &'a str,
+    vertex_entry: &'a str,
+    fragment_entry: &'a str,
+    bind_group_layouts: Vec<&'a wgpu::BindGroupLayout>,
+    vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+    depth: Option<wgpu::DepthStencilState>,
+    topology: wgpu::PrimitiveTopology,
+    strip_index_format: wgpu::IndexFormat,
+    cull_mode: Option<wgpu::Face>,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    /// `shader` is the name the shader was added to the cache with, `format` is the format of
+    /// the colour target drawn into.
+    pub fn new(label: &'a str, shader: &'a str, format: wgpu::TextureFormat) -> Self {
+        Self {
+            label,
+            shader,
+            vertex_entry: "vs_main",
+            fragment_entry: "fs_main",
+            bind_group_layouts: Vec::new(),
+            vertex_layouts: Vec::new(),
+            format,
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            depth: None,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: wgpu::IndexFormat::Uint32,
+            cull_mode: None,
+        }
+    }
+
+    pub fn entry_points(mut self, vertex_entry: &'a str, fragment_entry: &'a str) -> Self {
+        self.vertex_entry = vertex_entry;
+        self.fragment_entry = fragment_entry;
+        self
+    }
+
+    /// Add the layout of the next bind group, in the order of the shader's @group indices.
+    pub fn bind_group_layout(mut self, layout: &'a wgpu::BindGroupLayout) -> Self {
+        self.bind_group_layouts.push(layout);
+        self
+    }
+
+    /// Add the layout of the next vertex buffer, in the order of the vertex buffer slots.
+    pub fn vertex_layout(mut self, layout: wgpu::VertexBufferLayout<'static>) -> Self {
+        self.vertex_layouts.push(layout);
+        self
+    }
+
+    /// None replaces whatever is already in the target.
+    pub fn blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Has to match the depth buffer of the pass the pipeline is drawn in, None if there isn't one.
+    pub fn depth(mut self, depth: Option<&DepthConfig>) -> Self {
+        self.depth = depth.map(DepthConfig::depth_stencil_state);
+        self
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// The format of the index buffers strips are drawn with, ignored for list topologies.
+    pub fn strip_index_format(mut self, format: wgpu::IndexFormat) -> Self {
+        self.strip_index_format = format;
+        self
+    }
+
+    /// Front faces are counter clockwise.
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn key(&self) -> PipelineKey {
+        PipelineKey {
+            shader: self.shader.to_string(),
+            vertex_entry: self.vertex_entry.to_string(),
+            fragment_entry: self.fragment_entry.to_string(),
+            bind_group_layouts: self
+                .bind_group_layouts
+                .iter()
+                .map(|layout| layout.global_id())
+                .collect(),
+            vertex_layouts: self
+                .vertex_layouts
+                .iter()
+                .map(|layout| VertexLayoutKey {
+                    array_stride: layout.array_stride,
+                    step_mode: layout.step_mode,
+                    attributes: layout.attributes.to_vec(),
+                })
+                .collect(),
+            format: self.format,
+            blend: self.blend,
+            depth: self.depth.clone(),
+            topology: self.topology,
+            strip_index_format: match self.topology {
+                wgpu::PrimitiveTopology::LineStrip | wgpu::PrimitiveTopology::TriangleStrip => {
+                    Some(self.strip_index_format)
+                }
+                _ => None,
+            },
+            cull_mode: self.cull_mode,
+        }
+    }
+
+    /// Build the pipeline with `shader`, without going through a cache.
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        let layout = self.create_layout(device);
+        create_pipeline(device, self.label, &layout, &self.key(), shader)
+    }
+
+    fn create_layout(&self, device: &wgpu::Device) -> wgpu::PipelineLayout {
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(self.label),
+            bind_group_layouts: &self.bind_group_layouts,
+            push_constant_ranges: &[],
+        })
+    }
+}
+
+// Everything but the pipeline layout comes from the key, so pipelines can be rebuilt from their
+// key and layout alone when their shader changes
+fn create_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    key: &PipelineKey,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    let vertex_layouts: Vec<wgpu::VertexBufferLayout> = key
+        .vertex_layouts
+        .iter()
+        .map(|layout| wgpu::VertexBufferLayout {
+            array_stride: layout.array_stride,
+            step_mode: layout.step_mode,
+            attributes: &layout.attributes,
+        })
+        .collect();
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: &key.vertex_entry,
+            buffers: &vertex_layouts,
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: key.topology,
+            strip_index_format: key.strip_index_format,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: key.cull_mode,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: key.depth.clone(),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: &key.fragment_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format: key.format,
+                blend: key.blend,
+                write_mask: wgpu::ColorWrites::all(),
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+/// A pipeline from a PipelineCache. Pipelines are rebuilt when their shader is replaced, refresh
+/// picks up the rebuilt pipeline.
+#[derive(Clone)]
+pub struct PipelineHandle {
+    key: PipelineKey,
+    pipeline: Rc<wgpu::RenderPipeline>,
+    generation: u64,
+}
+
+impl PipelineHandle {
+    pub fn key(&self) -> &PipelineKey {
+        &self.key
+    }
+
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    /// Switch to the latest build of the pipeline, cheap if nothing has been rebuilt since.
+    pub fn refresh(&mut self, pipelines: &PipelineCache) {
+        if self.generation == pipelines.generation {
+            return;
+        }
+        if let Some(cached) = pipelines.pipelines.get(&self.key) {
+            self.pipeline = cached.pipeline.clone();
+        }
+        self.generation = pipelines.generation;
+    }
+}
+
+struct CachedPipeline {
+    label: String,
+    layout: wgpu::PipelineLayout,
+    pipeline: Rc<wgpu::RenderPipeline>,
+}
+
+/// Shader modules by name, and every pipeline built from them.
+#[derive(Default)]
+pub struct PipelineCache {
+    shaders: HashMap<String, wgpu::ShaderModule>,
+    pipelines: HashMap<PipelineKey, CachedPipeline>,
+    // counts rebuilds, so handles know when to refresh
+    generation: u64,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compile a WGSL shader for pipelines to use as `name`, see set_shader. The source is not
+    /// validated, see ShaderRegistry for loading shaders that might not compile.
+    pub fn add_shader(&mut self, device: &wgpu::Device, name: &str, source: &str) -> Result<()> {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(name),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        self.set_shader(device, name, module)
+    }
+
+    /// Use an already compiled shader as `name`. Replacing a shader rebuilds every pipeline built
+    /// from the old one, handles to them pick up the new pipelines when refreshed. If any of them
+    /// can't be built with the new shader (eg. an entry point is missing) nothing is replaced.
+    pub fn set_shader(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        module: wgpu::ShaderModule,
+    ) -> Result<()> {
+        // wgpu panics on invalid pipelines unless the error is caught by a scope
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let rebuilt: Vec<(PipelineKey, wgpu::RenderPipeline)> = self
+            .pipelines
+            .iter()
+            .filter(|(key, _)| key.shader == name)
+            .map(|(key, cached)| {
+                let pipeline = create_pipeline(device, &cached.label, &cached.layout, key, &module);
+                (key.clone(), pipeline)
+            })
+            .collect();
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            bail!(
+                "The pipelines using shader {} could not be rebuilt: {}",
+                name,
+                error
+            );
+        }
+        if !rebuilt.is_empty() {
+            self.generation += 1;
+        }
+        for (key, pipeline) in rebuilt {
+            if let Some(cached) = self.pipelines.get_mut(&key) {
+                cached.pipeline = Rc::new(pipeline);
+            }
+        }
+        self.shaders.insert(name.to_string(), module);
+        Ok(())
+    }
+
+    pub fn shader(&self, name: &str) -> Option<&wgpu::ShaderModule> {
+        self.shaders.get(name)
+    }
+
+    /// The pipeline described by `builder`, built the first time it is asked for.
+    pub fn get(
+        &mut self,
+        device: &wgpu::Device,
+        builder: &PipelineBuilder,
+    ) -> Result<PipelineHandle> {
+        let key = builder.key();
+        if let Some(cached) = self.pipelines.get(&key) {
+            return Ok(PipelineHandle {
+                key,
+                pipeline: cached.pipeline.clone(),
+                generation: self.generation,
+            });
+        }
+        let shader = self
+            .shaders
+            .get(builder.shader)
+            .with_context(|| format!("No shader called {} has been added", builder.shader))?;
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let layout = builder.create_layout(device);
+        let pipeline = Rc::new(create_pipeline(
+            device,
+            builder.label,
+            &layout,
+            &key,
+            shader,
+        ));
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            bail!("Pipeline {} could not be built: {}", builder.label, error);
+        }
+        self.pipelines.insert(
+            key.clone(),
+            CachedPipeline {
+                label: builder.label.to_string(),
+                layout,
+                pipeline: pipeline.clone(),
+            },
+        );
+        Ok(PipelineHandle {
+            key,
+            pipeline,
+            generation: self.generation,
+        })
+    }
+
+    /// The keys of every pipeline built so far.
+    pub fn keys(&self) -> impl Iterator<Item = &PipelineKey> {
+        self.pipelines.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::engine::primitives::vertex::{Vertex2D, Vertex3D};
+    use std::collections::HashSet;
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    // draws a single triangle from the vertex index, so no buffers or bind groups are needed
+    const TRIANGLE: &str = "
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(index) - 1.0, f32(index % 2u), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
+";
+
+    fn builder() -> PipelineBuilder<'static> {
+        PipelineBuilder::new("Test Pipeline", "shader", FORMAT)
+            .vertex_layout(Vertex2D::descriptor())
+    }
+
+    // the same software adapter as the headless renderer, None where there isn't one
+    pub(crate) fn headless_device() -> Option<wgpu::Device> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::LowPower,
+            force_fallback_adapter: true,
+            compatible_surface: None,
+        }))?;
+        let (device, _) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::downlevel_defaults(),
+                label: None,
+            },
+            None,
+        ))
+        .ok()?;
+        Some(device)
+    }
+
+    fn texture_layout(
+        device: &wgpu::Device,
+        visibility: wgpu::ShaderStages,
+    ) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            }],
+        })
+    }
+
+    #[test]
+    fn equal_descriptions_make_equal_keys() {
+        let a = builder().depth(Some(&DepthConfig::default())).key();
+        let b = builder().depth(Some(&DepthConfig::default())).key();
+        assert_eq!(a, b);
+        // equal keys find the same cached pipeline
+        let keys: HashSet<PipelineKey> = [a, b].into_iter().collect();
+        assert_eq!(keys.len(), 1);
+        // the label is only for debugging, so it doesn't make a different pipeline
+        let relabelled = PipelineBuilder::new("Another Label", "shader", FORMAT)
+            .vertex_layout(Vertex2D::descriptor())
+            .key();
+        assert_eq!(relabelled, builder().key());
+    }
+
+    #[test]
+    fn every_setting_is_part_of_the_key() {
+        let depth = DepthConfig::default();
+        let variants = [
+            builder().key(),
+            builder().blend(None).key(),
+            builder().blend(Some(wgpu::BlendState::REPLACE)).key(),
+            builder().cull_mode(Some(wgpu::Face::Back)).key(),
+            builder().cull_mode(Some(wgpu::Face::Front)).key(),
+            builder().depth(Some(&depth)).key(),
+            builder().depth(Some(&depth.overlay())).key(),
+            builder().topology(wgpu::PrimitiveTopology::LineList).key(),
+            builder()
+                .topology(wgpu::PrimitiveTopology::TriangleStrip)
+                .key(),
+            builder()
+                .topology(wgpu::PrimitiveTopology::TriangleStrip)
+                .strip_index_format(wgpu::IndexFormat::Uint16)
+                .key(),
+            builder().entry_points("vs_other", "fs_main").key(),
+            builder().entry_points("vs_main", "fs_other").key(),
+            PipelineBuilder::new("Test Pipeline", "other", FORMAT)
+                .vertex_layout(Vertex2D::descriptor())
+                .key(),
+            PipelineBuilder::new("Test Pipeline", "shader", wgpu::TextureFormat::Bgra8Unorm)
+                .vertex_layout(Vertex2D::descriptor())
+                .key(),
+            PipelineBuilder::new("Test Pipeline", "shader", FORMAT)
+                .vertex_layout(Vertex3D::descriptor())
+                .key(),
+            PipelineBuilder::new("Test Pipeline", "shader", FORMAT).key(),
+            builder().vertex_layout(Vertex2D::descriptor()).key(),
+        ];
+        let keys: HashSet<PipelineKey> = variants.iter().cloned().collect();
+        assert_eq!(keys.len(), variants.len());
+    }
+
+    #[test]
+    fn strip_index_format_only_matters_for_strips() {
+        let uint16 = builder().strip_index_format(wgpu::IndexFormat::Uint16);
+        assert_eq!(uint16.key(), builder().key());
+        let strip = builder().topology(wgpu::PrimitiveTopology::LineStrip);
+        assert_eq!(
+            strip.key().strip_index_format,
+            Some(wgpu::IndexFormat::Uint32)
+        );
+        assert_eq!(builder().key().strip_index_format, None);
+    }
+
+    #[test]
+    fn the_cache_builds_each_pipeline_once() {
+        let Some(device) = headless_device() else {
+            eprintln!("skipped, no fallback adapter is available");
+            return;
+        };
+        let fragment = texture_layout(&device, wgpu::ShaderStages::FRAGMENT);
+        let vertex = texture_layout(&device, wgpu::ShaderStages::VERTEX);
+        let describe = |layout| {
+            PipelineBuilder::new("Test Pipeline", "triangle", FORMAT).bind_group_layout(layout)
+        };
+        assert_ne!(describe(&fragment).key(), describe(&vertex).key());
+
+        let mut pipelines = PipelineCache::new();
+        assert!(pipelines.get(&device, &describe(&fragment)).is_err());
+        pipelines.add_shader(&device, "triangle", TRIANGLE).unwrap();
+        let first = pipelines.get(&device, &describe(&fragment)).unwrap();
+        let again = pipelines.get(&device, &describe(&fragment)).unwrap();
+        assert!(Rc::ptr_eq(&first.pipeline, &again.pipeline));
+        assert_eq!(pipelines.len(), 1);
+        pipelines.get(&device, &describe(&vertex)).unwrap();
+        assert_eq!(pipelines.len(), 2);
+    }
+}
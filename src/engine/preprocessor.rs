@@ -0,0 +1,435 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// A small preprocessor run over WGSL before it is compiled, so shaders can share code:
+//
+// #include "common.wgsl"    the file's contents, relative to the file including it
+// #define NAME             a feature toggle for #ifdef
+// #define NAME value       as well, every NAME in the code after it is replaced by value
+// #undef NAME
+// #ifdef NAME / #ifndef NAME / #else / #endif
+//
+// A file is only included once per shader, later includes of it are skipped, so files can
+// include what they use without clashing definitions. Every line of the output remembers the
+// file and line it came from, so errors in the output can point at the original.
+
+/// The output of the preprocessor, ready to be compiled.
+pub struct PreprocessedShader {
+    source: String,
+    // every file the source was built from, the first is the shader itself
+    files: Vec<PathBuf>,
+    // for every line of the source, the file (index into files) and line it came from
+    lines: Vec<(usize, u32)>,
+}
+
+impl PreprocessedShader {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.files[0]
+    }
+
+    /// Every file the shader was built from, changes to any of them change the shader.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// The file and line a line of the source came from, both lines counting from 1.
+    pub fn original_line(&self, line: u32) -> Option<(&Path, u32)> {
+        let (file, original) = *self.lines.get(line.checked_sub(1)? as usize)?;
+        Some((&self.files[file], original))
+    }
+}
+
+#[derive(Default)]
+pub struct Preprocessor {
+    defines: BTreeMap<String, String>,
+    // sources for includes that aren't found on disk, eg. copies built into the executable
+    built_in: HashMap<String, String>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define `name` for every shader preprocessed from now on, as if each started with
+    /// `#define name value`. An empty value only defines it for #ifdef.
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.defines.remove(name);
+    }
+
+    pub fn defines(&self) -> &BTreeMap<String, String> {
+        &self.defines
+    }
+
+    /// Use `source` when `#include "name"` can't be read from disk.
+    pub fn add_built_in(&mut self, name: &str, source: &str) {
+        self.built_in.insert(name.to_string(), source.to_string());
+    }
+
+    /// Read and preprocess the shader at `path`.
+    pub fn process_file(&self, path: &Path) -> Result<PreprocessedShader> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read shader {}", path.display()))?;
+        self.process(path, &source)
+    }
+
+    /// Preprocess `source`, read from `path`. Includes are looked for next to `path`.
+    pub fn process(&self, path: &Path, source: &str) -> Result<PreprocessedShader> {
+        let mut state = State {
+            shader: PreprocessedShader {
+                source: String::new(),
+                files: Vec::new(),
+                lines: Vec::new(),
+            },
+            defines: self.defines.clone(),
+            included: HashSet::new(),
+        };
+        self.process_source(&mut state, path, source)?;
+        Ok(state.shader)
+    }
+
+    fn process_source(&self, state: &mut State, path: &Path, source: &str) -> Result<()> {
+        let file = state.shader.files.len();
+        state.shader.files.push(path.to_path_buf());
+        state.included.insert(path.to_path_buf());
+
+        let mut conditions: Vec<Condition> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let number = index as u32 + 1;
+            let active = conditions.iter().all(|condition| condition.active);
+            let error_at = || format!("{}:{}", path.display(), number);
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if active {
+                    let line = substitute(line, &state.defines);
+                    state.shader.source.push_str(&line);
+                    state.shader.source.push('\n');
+                    state.shader.lines.push((file, number));
+                }
+                continue;
+            };
+
+            let (command, argument) = match directive.trim().split_once(char::is_whitespace) {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (directive.trim(), ""),
+            };
+            match command {
+                "ifdef" | "ifndef" => {
+                    let name = identifier(argument).with_context(error_at)?;
+                    let defined = state.defines.contains_key(name);
+                    conditions.push(Condition {
+                        active: defined == (command == "ifdef"),
+                        has_else: false,
+                        line: number,
+                    });
+                }
+                "else" => {
+                    let condition = conditions
+                        .last_mut()
+                        .filter(|condition| !condition.has_else)
+                        .ok_or_else(|| anyhow!("#else without #ifdef"))
+                        .with_context(error_at)?;
+                    condition.active = !condition.active;
+                    condition.has_else = true;
+                }
+                "endif" => {
+                    conditions
+                        .pop()
+                        .ok_or_else(|| anyhow!("#endif without #ifdef"))
+                        .with_context(error_at)?;
+                }
+                // everything else only counts when it isn't excluded by a condition
+                _ if !active => {}
+                "define" => {
+                    let (name, value) = match argument.split_once(char::is_whitespace) {
+                        Some((name, value)) => (name, value.trim()),
+                        None => (argument, ""),
+                    };
+                    let name = identifier(name).with_context(error_at)?;
+                    state.defines.insert(name.to_string(), value.to_string());
+                }
+                "undef" => {
+                    let name = identifier(argument).with_context(error_at)?;
+                    state.defines.remove(name);
+                }
+                "include" => {
+                    let name = argument
+                        .strip_prefix('"')
+                        .and_then(|argument| argument.strip_suffix('"'))
+                        .ok_or_else(|| anyhow!("#include needs a file name in quotes"))
+                        .with_context(error_at)?;
+                    self.include(state, path, name).with_context(error_at)?;
+                }
+                _ => return Err(anyhow!("Unknown directive #{}", command)).with_context(error_at),
+            }
+        }
+        if let Some(condition) = conditions.last() {
+            bail!(
+                "{}:{}: #ifdef without #endif",
+                path.display(),
+                condition.line
+            );
+        }
+        Ok(())
+    }
+
+    fn include(&self, state: &mut State, from: &Path, name: &str) -> Result<()> {
+        let path = from.parent().unwrap_or(Path::new("")).join(name);
+        if state.included.contains(&path) {
+            return Ok(());
+        }
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(error) => match self.built_in.get(name) {
+                Some(source) => source.clone(),
+                None => {
+                    return Err(error)
+                        .with_context(|| format!("Could not include {}", path.display()))
+                }
+            },
+        };
+        self.process_source(state, &path, &source)
+    }
+}
+
+struct State {
+    shader: PreprocessedShader,
+    defines: BTreeMap<String, String>,
+    included: HashSet<PathBuf>,
+}
+
+struct Condition {
+    // whether the lines up to the next #else or #endif are kept
+    active: bool,
+    has_else: bool,
+    // where the #ifdef is, for when it isn't closed
+    line: u32,
+}
+
+fn identifier(name: &str) -> Result<&str> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_');
+    if !valid {
+        bail!("Expected a name, found \"{}\"", name);
+    }
+    Ok(name)
+}
+
+// Replace every identifier defined with a value by that value
+fn substitute(line: &str, defines: &BTreeMap<String, String>) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(|char: char| char.is_ascii_alphabetic() || char == '_') {
+        // the letters in numbers like 1e5 or 0xffu aren't names, nor are members after a .
+        let skip = rest[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|char| char.is_ascii_alphanumeric() || char == '.');
+        let end = rest[start..]
+            .find(|char: char| !(char.is_ascii_alphanumeric() || char == '_'))
+            .map_or(rest.len(), |end| start + end);
+        output.push_str(&rest[..start]);
+        let word = &rest[start..end];
+        match defines.get(word) {
+            Some(value) if !skip && !value.is_empty() => output.push_str(value),
+            _ => output.push_str(word),
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // nothing exists at this path, so every include comes from the built in sources
+    const SHADER: &str = "virtual/main.wgsl";
+
+    fn process(preprocessor: &Preprocessor, source: &str) -> Result<PreprocessedShader> {
+        preprocessor.process(Path::new(SHADER), source)
+    }
+
+    fn lines(shader: &PreprocessedShader) -> Vec<&str> {
+        shader.source().lines().collect()
+    }
+
+    fn error(preprocessor: &Preprocessor, source: &str) -> String {
+        match process(preprocessor, source) {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => format!("{:#}", error),
+        }
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.add_built_in("common.wgsl", "common");
+        // a and b include each other
+        preprocessor.add_built_in("a.wgsl", "#include \"b.wgsl\"\na\n#include \"common.wgsl\"");
+        preprocessor.add_built_in("b.wgsl", "#include \"a.wgsl\"\n#include \"common.wgsl\"\nb");
+        let shader = process(
+            &preprocessor,
+            "#include \"a.wgsl\"\n#include \"common.wgsl\"\n#include \"b.wgsl\"\nmain",
+        )
+        .unwrap();
+        assert_eq!(lines(&shader), ["common", "b", "a", "main"]);
+        let files: Vec<_> = shader
+            .files()
+            .iter()
+            .map(|file| file.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                SHADER,
+                "virtual/a.wgsl",
+                "virtual/b.wgsl",
+                "virtual/common.wgsl"
+            ]
+        );
+        // every line points back at where it was written
+        let original = |line| {
+            let (path, line) = shader.original_line(line).unwrap();
+            (path.to_str().unwrap(), line)
+        };
+        assert_eq!(original(1), ("virtual/common.wgsl", 1));
+        assert_eq!(original(2), ("virtual/b.wgsl", 3));
+        assert_eq!(original(3), ("virtual/a.wgsl", 2));
+        assert_eq!(original(4), (SHADER, 4));
+        assert_eq!(shader.original_line(0), None);
+        assert_eq!(shader.original_line(5), None);
+    }
+
+    #[test]
+    fn a_shader_including_itself_is_skipped() {
+        let shader = process(&Preprocessor::new(), "#include \"main.wgsl\"\nmain").unwrap();
+        assert_eq!(lines(&shader), ["main"]);
+    }
+
+    #[test]
+    fn missing_include_is_an_error() {
+        let message = error(&Preprocessor::new(), "\n#include \"missing.wgsl\"");
+        assert!(message.starts_with("virtual/main.wgsl:2: Could not include virtual/missing.wgsl"));
+    }
+
+    #[test]
+    fn nested_conditionals() {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.define("SHADOWS", "");
+        let source = "\
+#ifdef SHADOWS
+shadows
+#ifndef SOFT
+hard
+#else
+soft
+#endif
+#else
+no shadows
+#ifdef SHADOWS
+never
+#endif
+#endif
+end";
+        let shader = process(&preprocessor, source).unwrap();
+        assert_eq!(lines(&shader), ["shadows", "hard", "end"]);
+        preprocessor.define("SOFT", "");
+        let shader = process(&preprocessor, source).unwrap();
+        assert_eq!(lines(&shader), ["shadows", "soft", "end"]);
+        preprocessor.undefine("SHADOWS");
+        let shader = process(&preprocessor, source).unwrap();
+        assert_eq!(lines(&shader), ["no shadows", "end"]);
+    }
+
+    #[test]
+    fn directives_in_excluded_code_are_ignored() {
+        let source = "\
+#ifdef MISSING
+#define FEATURE
+#include \"missing.wgsl\"
+#unknown
+#endif
+#ifdef FEATURE
+feature
+#endif";
+        let shader = process(&Preprocessor::new(), source).unwrap();
+        assert!(shader.source().is_empty());
+    }
+
+    #[test]
+    fn defines_replace_whole_names() {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.define("SIZE", "64u");
+        let source = "\
+var a: array<f32, SIZE>;
+let b = SIZE_X + v.SIZE + 1e5;
+#undef SIZE
+let c = SIZE;";
+        let shader = process(&preprocessor, source).unwrap();
+        assert_eq!(
+            lines(&shader),
+            [
+                "var a: array<f32, 64u>;",
+                "let b = SIZE_X + v.SIZE + 1e5;",
+                "let c = SIZE;"
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_directive() {
+        let preprocessor = Preprocessor::new();
+        assert_eq!(
+            error(&preprocessor, "line\n#pragma once"),
+            "virtual/main.wgsl:2: Unknown directive #pragma"
+        );
+        assert_eq!(
+            error(&preprocessor, "#else"),
+            "virtual/main.wgsl:1: #else without #ifdef"
+        );
+        assert_eq!(
+            error(&preprocessor, "#ifdef A\n#else\n#else\n#endif"),
+            "virtual/main.wgsl:3: #else without #ifdef"
+        );
+        assert_eq!(
+            error(&preprocessor, "#endif"),
+            "virtual/main.wgsl:1: #endif without #ifdef"
+        );
+        assert_eq!(
+            error(&preprocessor, "#ifdef A\n#ifdef B\n#endif"),
+            "virtual/main.wgsl:1: #ifdef without #endif"
+        );
+        assert_eq!(
+            error(&preprocessor, "#define 2D"),
+            "virtual/main.wgsl:1: Expected a name, found \"2D\""
+        );
+        assert_eq!(
+            error(&preprocessor, "#include common.wgsl"),
+            "virtual/main.wgsl:1: #include needs a file name in quotes"
+        );
+    }
+
+    #[test]
+    fn errors_in_includes_point_at_the_included_file() {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.add_built_in("common.wgsl", "ok\n#bad");
+        let message = error(&preprocessor, "#include \"common.wgsl\"");
+        assert_eq!(
+            message,
+            "virtual/main.wgsl:1: virtual/common.wgsl:2: Unknown directive #bad"
+        );
+    }
+}
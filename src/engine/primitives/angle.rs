@@ -0,0 +1,30 @@
+use num_traits::Float;
+
+// Functions taking an angle take `impl Into<Radians<T>>`, so the unit is written at every call,
+// eg. Quaternion::from_axis_angle(axis, Degrees(90.0))
+
+/// An angle in radians.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Radians<T>(pub T);
+
+/// An angle in degrees.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Degrees<T>(pub T);
+
+impl<T> From<Degrees<T>> for Radians<T>
+where
+    T: Float,
+{
+    fn from(degrees: Degrees<T>) -> Self {
+        Radians(degrees.0.to_radians())
+    }
+}
+
+impl<T> From<Radians<T>> for Degrees<T>
+where
+    T: Float,
+{
+    fn from(radians: Radians<T>) -> Self {
+        Degrees(radians.0.to_degrees())
+    }
+}
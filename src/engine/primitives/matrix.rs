@@ -1,6 +1,8 @@
+use super::vector::{Vector2, Vector3, Vector4};
 use num_traits::{Float, NumCast};
 
 /// Struct for operations on a column-major 4x4 matrix
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Matrix4<T> {
     matrix: [[T; 4]; 4],
 }
@@ -13,6 +15,216 @@ where
         Self { matrix }
     }
 
+    pub fn identity() -> Self {
+        let (zero, one) = (T::zero(), T::one());
+        Self::new([
+            [one, zero, zero, zero],
+            [zero, one, zero, zero],
+            [zero, zero, one, zero],
+            [zero, zero, zero, one],
+        ])
+    }
+
+    pub fn from_translation(translation: Vector3<T>) -> Self {
+        let mut matrix = Self::identity();
+        matrix.set_column(3, translation.to_point().to_raw());
+        matrix
+    }
+
+    pub fn from_scale(scale: Vector3<T>) -> Self {
+        let mut matrix = Self::identity();
+        matrix[0][0] = scale.x;
+        matrix[1][1] = scale.y;
+        matrix[2][2] = scale.z;
+        matrix
+    }
+
+    /// A rotation of `angle` radians around `axis`, which must be unit length. Looking down the
+    /// axis, positive angles turn anticlockwise.
+    pub fn from_rotation(axis: Vector3<T>, angle: T) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let one_minus_cos = T::one() - cos;
+        let Vector3 { x, y, z } = axis;
+        let zero = T::zero();
+        // https://en.wikipedia.org/wiki/Rotation_matrix#Rotation_matrix_from_axis_and_angle
+        Self::new([
+            [
+                cos + x * x * one_minus_cos,
+                y * x * one_minus_cos + z * sin,
+                z * x * one_minus_cos - y * sin,
+                zero,
+            ],
+            [
+                x * y * one_minus_cos - z * sin,
+                cos + y * y * one_minus_cos,
+                z * y * one_minus_cos + x * sin,
+                zero,
+            ],
+            [
+                x * z * one_minus_cos + y * sin,
+                y * z * one_minus_cos - x * sin,
+                cos + z * z * one_minus_cos,
+                zero,
+            ],
+            [zero, zero, zero, T::one()],
+        ])
+    }
+
+    /// A view matrix for a camera at `eye` looking at `target`, in a right handed space where
+    /// the camera looks down -z.
+    pub fn look_at_rh(eye: Vector3<T>, target: Vector3<T>, up: Vector3<T>) -> Self {
+        Self::look_to_rh(eye, target - eye, up)
+    }
+
+    /// A view matrix for a camera at `eye` looking at `target`, in a left handed space where
+    /// the camera looks down +z, as the engine's 3D camera does.
+    pub fn look_at_lh(eye: Vector3<T>, target: Vector3<T>, up: Vector3<T>) -> Self {
+        Self::look_to_rh(eye, eye - target, up)
+    }
+
+    fn look_to_rh(eye: Vector3<T>, direction: Vector3<T>, up: Vector3<T>) -> Self {
+        let forward = direction.normalised();
+        let side = forward.cross(up).normalised();
+        let up = side.cross(forward);
+        let zero = T::zero();
+        Self::new([
+            [side.x, up.x, -forward.x, zero],
+            [side.y, up.y, -forward.y, zero],
+            [side.z, up.z, -forward.z, zero],
+            [-eye.dot(side), -eye.dot(up), eye.dot(forward), T::one()],
+        ])
+    }
+
+    /// A left handed perspective projection with depths from 0 at `near` to 1 at `far`, as wgpu
+    /// expects. `fov` is the vertical field of view in radians and `aspect_ratio` is width /
+    /// height.
+    pub fn perspective(fov: T, aspect_ratio: T, near: T, far: T) -> Self {
+        let two = T::one() + T::one();
+        let focal_length = T::one() / (fov / two).tan();
+        let zero = T::zero();
+        Self::new([
+            [focal_length / aspect_ratio, zero, zero, zero],
+            [zero, focal_length, zero, zero],
+            [zero, zero, far / (far - near), T::one()],
+            [zero, zero, -far * near / (far - near), zero],
+        ])
+    }
+
+    /// A left handed orthographic projection of the box between the planes, with depths from 0
+    /// at `near` to 1 at `far`.
+    pub fn orthographic(left: T, right: T, bottom: T, top: T, near: T, far: T) -> Self {
+        let two = T::one() + T::one();
+        let zero = T::zero();
+        Self::new([
+            [two / (right - left), zero, zero, zero],
+            [zero, two / (top - bottom), zero, zero],
+            [zero, zero, T::one() / (far - near), zero],
+            [
+                -(right + left) / (right - left),
+                -(top + bottom) / (top - bottom),
+                -near / (far - near),
+                T::one(),
+            ],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut transposed = *self;
+        for column in 0..4 {
+            for row in 0..4 {
+                transposed[column][row] = self[row][column];
+            }
+        }
+        transposed
+    }
+
+    pub fn determinant(&self) -> T {
+        let (s, c) = self.minors();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+
+    /// The inverse of the matrix, None if it has none (its determinant is 0).
+    pub fn inverse(&self) -> Option<Self> {
+        let (s, c) = self.minors();
+        let determinant =
+            s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        if determinant == T::zero() || !determinant.is_finite() {
+            return None;
+        }
+        let m = &self.matrix;
+        // the adjugate, https://www.geometrictools.com/Documentation/LaplaceExpansionTheorem.pdf
+        let adjugate = [
+            [
+                m[1][1] * c[5] - m[1][2] * c[4] + m[1][3] * c[3],
+                -m[0][1] * c[5] + m[0][2] * c[4] - m[0][3] * c[3],
+                m[3][1] * s[5] - m[3][2] * s[4] + m[3][3] * s[3],
+                -m[2][1] * s[5] + m[2][2] * s[4] - m[2][3] * s[3],
+            ],
+            [
+                -m[1][0] * c[5] + m[1][2] * c[2] - m[1][3] * c[1],
+                m[0][0] * c[5] - m[0][2] * c[2] + m[0][3] * c[1],
+                -m[3][0] * s[5] + m[3][2] * s[2] - m[3][3] * s[1],
+                m[2][0] * s[5] - m[2][2] * s[2] + m[2][3] * s[1],
+            ],
+            [
+                m[1][0] * c[4] - m[1][1] * c[2] + m[1][3] * c[0],
+                -m[0][0] * c[4] + m[0][1] * c[2] - m[0][3] * c[0],
+                m[3][0] * s[4] - m[3][1] * s[2] + m[3][3] * s[0],
+                -m[2][0] * s[4] + m[2][1] * s[2] - m[2][3] * s[0],
+            ],
+            [
+                -m[1][0] * c[3] + m[1][1] * c[1] - m[1][2] * c[0],
+                m[0][0] * c[3] - m[0][1] * c[1] + m[0][2] * c[0],
+                -m[3][0] * s[3] + m[3][1] * s[1] - m[3][2] * s[0],
+                m[2][0] * s[3] - m[2][1] * s[1] + m[2][2] * s[0],
+            ],
+        ];
+        Some(Self::new(
+            adjugate.map(|column| column.map(|value| value / determinant)),
+        ))
+    }
+
+    /// Whether every element is within `epsilon` of the other matrix's.
+    pub fn approx_eq(&self, other: &Self, epsilon: T) -> bool {
+        self.matrix
+            .iter()
+            .flatten()
+            .zip(other.matrix.iter().flatten())
+            .all(|(a, b)| (*a - *b).abs() <= epsilon)
+    }
+
+    /// The matrix for transforming normals by this transform, the inverse transpose of its top
+    /// left 3x3 so normals stay at right angles to their surfaces under non-uniform scaling.
+    /// None if the transform flattens everything (its determinant is 0).
+    pub fn normal_matrix(&self) -> Option<Matrix3<T>> {
+        Matrix3::from(self)
+            .inverse()
+            .map(|inverse| inverse.transpose())
+    }
+
+    // The 2x2 determinants of the first two and last two columns, which the determinant and
+    // inverse are built from
+    fn minors(&self) -> ([T; 6], [T; 6]) {
+        let m = &self.matrix;
+        let s = [
+            m[0][0] * m[1][1] - m[1][0] * m[0][1],
+            m[0][0] * m[1][2] - m[1][0] * m[0][2],
+            m[0][0] * m[1][3] - m[1][0] * m[0][3],
+            m[0][1] * m[1][2] - m[1][1] * m[0][2],
+            m[0][1] * m[1][3] - m[1][1] * m[0][3],
+            m[0][2] * m[1][3] - m[1][2] * m[0][3],
+        ];
+        let c = [
+            m[2][0] * m[3][1] - m[3][0] * m[2][1],
+            m[2][0] * m[3][2] - m[3][0] * m[2][2],
+            m[2][0] * m[3][3] - m[3][0] * m[2][3],
+            m[2][1] * m[3][2] - m[3][1] * m[2][2],
+            m[2][1] * m[3][3] - m[3][1] * m[2][3],
+            m[2][2] * m[3][3] - m[3][2] * m[2][3],
+        ];
+        (s, c)
+    }
+
     pub fn get(&self, column: usize, row: usize) -> T {
         self.matrix[column][row]
     }
@@ -24,6 +236,17 @@ where
     pub fn set_column(&mut self, column: usize, value: [T; 4]) {
         self.matrix[column] = value;
     }
+
+    /// Transform a position, which is translated, dividing by w afterwards so projections
+    /// work too.
+    pub fn transform_point(&self, point: Vector3<T>) -> Vector3<T> {
+        (self * point.to_point()).to_cartesian()
+    }
+
+    /// Transform a direction, which isn't translated.
+    pub fn transform_vector(&self, vector: Vector3<T>) -> Vector3<T> {
+        (self * vector.to_direction()).xyz()
+    }
 }
 
 impl<T> Matrix4<T>
@@ -124,6 +347,28 @@ where
     }
 }
 
+impl<T> std::ops::Mul<Vector4<T>> for &Matrix4<T>
+where
+    T: NumCast + Copy + Float,
+{
+    type Output = Vector4<T>;
+
+    fn mul(self, rhs: Vector4<T>) -> Self::Output {
+        let row = |row: usize| {
+            self[0][row] * rhs.x
+                + self[1][row] * rhs.y
+                + self[2][row] * rhs.z
+                + self[3][row] * rhs.w
+        };
+        Vector4 {
+            x: row(0),
+            y: row(1),
+            z: row(2),
+            w: row(3),
+        }
+    }
+}
+
 impl<T> std::ops::Index<usize> for Matrix4<T> {
     type Output = [T; 4];
 
@@ -131,3 +376,678 @@ impl<T> std::ops::Index<usize> for Matrix4<T> {
         &self.matrix[index]
     }
 }
+
+impl<T> std::ops::IndexMut<usize> for Matrix4<T> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.matrix[index]
+    }
+}
+
+/// Struct for operations on a column-major 2x2 matrix, eg. 2D rotation and scale
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix2<T> {
+    matrix: [[T; 2]; 2],
+}
+
+impl<T> Matrix2<T>
+where
+    T: NumCast + Copy + Float,
+{
+    pub fn new(matrix: [[T; 2]; 2]) -> Self {
+        Self { matrix }
+    }
+
+    pub fn identity() -> Self {
+        Self::new([[T::one(), T::zero()], [T::zero(), T::one()]])
+    }
+
+    /// A rotation of `angle` radians, anticlockwise when y points up.
+    pub fn from_rotation(angle: T) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::new([[cos, sin], [-sin, cos]])
+    }
+
+    pub fn from_scale(scale: Vector2<T>) -> Self {
+        Self::new([[scale.x, T::zero()], [T::zero(), scale.y]])
+    }
+
+    pub fn get(&self, column: usize, row: usize) -> T {
+        self.matrix[column][row]
+    }
+
+    pub fn set(&mut self, column: usize, row: usize, value: T) {
+        self.matrix[column][row] = value;
+    }
+
+    pub fn to_raw(&self) -> [[T; 2]; 2] {
+        self.matrix
+    }
+
+    pub fn transpose(&self) -> Self {
+        let m = &self.matrix;
+        Self::new([[m[0][0], m[1][0]], [m[0][1], m[1][1]]])
+    }
+
+    pub fn determinant(&self) -> T {
+        let m = &self.matrix;
+        m[0][0] * m[1][1] - m[1][0] * m[0][1]
+    }
+
+    /// The inverse of the matrix, None if it has none (its determinant is 0).
+    pub fn inverse(&self) -> Option<Self> {
+        let determinant = self.determinant();
+        if determinant == T::zero() || !determinant.is_finite() {
+            return None;
+        }
+        let m = &self.matrix;
+        Some(Self::new([
+            [m[1][1] / determinant, -m[0][1] / determinant],
+            [-m[1][0] / determinant, m[0][0] / determinant],
+        ]))
+    }
+
+    /// Whether every element is within `epsilon` of the other matrix's.
+    pub fn approx_eq(&self, other: &Self, epsilon: T) -> bool {
+        self.matrix
+            .iter()
+            .flatten()
+            .zip(other.matrix.iter().flatten())
+            .all(|(a, b)| (*a - *b).abs() <= epsilon)
+    }
+}
+
+impl<T> std::ops::Mul<&Matrix2<T>> for &Matrix2<T>
+where
+    T: NumCast + Copy + Float,
+{
+    type Output = Matrix2<T>;
+
+    fn mul(self, rhs: &Matrix2<T>) -> Self::Output {
+        let column = |column: usize| {
+            let Vector2 { x, y } = self
+                * Vector2 {
+                    x: rhs[column][0],
+                    y: rhs[column][1],
+                };
+            [x, y]
+        };
+        Matrix2::new([column(0), column(1)])
+    }
+}
+
+impl<T> std::ops::Mul<Vector2<T>> for &Matrix2<T>
+where
+    T: NumCast + Copy + Float,
+{
+    type Output = Vector2<T>;
+
+    fn mul(self, rhs: Vector2<T>) -> Self::Output {
+        Vector2 {
+            x: self[0][0] * rhs.x + self[1][0] * rhs.y,
+            y: self[0][1] * rhs.x + self[1][1] * rhs.y,
+        }
+    }
+}
+
+impl<T> std::ops::Index<usize> for Matrix2<T> {
+    type Output = [T; 2];
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.matrix[index]
+    }
+}
+
+impl<T> std::ops::IndexMut<usize> for Matrix2<T> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.matrix[index]
+    }
+}
+
+/// The top left of a Matrix3, its rotation and scale without the 2D translation.
+impl<T> From<&Matrix3<T>> for Matrix2<T>
+where
+    T: NumCast + Copy + Float,
+{
+    fn from(matrix: &Matrix3<T>) -> Self {
+        Self::new([[matrix[0][0], matrix[0][1]], [matrix[1][0], matrix[1][1]]])
+    }
+}
+
+/// Struct for operations on a column-major 3x3 matrix, eg. 2D affine transforms or the normal
+/// matrix of a 3D transform
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix3<T> {
+    matrix: [[T; 3]; 3],
+}
+
+impl<T> Matrix3<T>
+where
+    T: NumCast + Copy + Float,
+{
+    pub fn new(matrix: [[T; 3]; 3]) -> Self {
+        Self { matrix }
+    }
+
+    pub fn identity() -> Self {
+        let (zero, one) = (T::zero(), T::one());
+        Self::new([[one, zero, zero], [zero, one, zero], [zero, zero, one]])
+    }
+
+    /// A 2D transform moving points by `translation`.
+    pub fn from_translation_2d(translation: Vector2<T>) -> Self {
+        let mut matrix = Self::identity();
+        matrix[2][0] = translation.x;
+        matrix[2][1] = translation.y;
+        matrix
+    }
+
+    /// A 2D transform rotating points `angle` radians around (0, 0), see Matrix2::from_rotation.
+    pub fn from_rotation_2d(angle: T) -> Self {
+        Self::from_linear_2d(&Matrix2::from_rotation(angle))
+    }
+
+    /// A 2D transform scaling points away from (0, 0).
+    pub fn from_scale_2d(scale: Vector2<T>) -> Self {
+        Self::from_linear_2d(&Matrix2::from_scale(scale))
+    }
+
+    /// A 2D transform applying `linear` (eg. a rotation, a scale or both) without translating.
+    pub fn from_linear_2d(linear: &Matrix2<T>) -> Self {
+        let zero = T::zero();
+        Self::new([
+            [linear[0][0], linear[0][1], zero],
+            [linear[1][0], linear[1][1], zero],
+            [zero, zero, T::one()],
+        ])
+    }
+
+    /// The 2D transform applied around `origin` rather than (0, 0), eg. to rotate a sprite around
+    /// its centre.
+    pub fn about(&self, origin: Vector2<T>) -> Self {
+        let to_origin = Self::from_translation_2d(-origin);
+        let back = Self::from_translation_2d(origin);
+        &back * &(self * &to_origin)
+    }
+
+    pub fn get(&self, column: usize, row: usize) -> T {
+        self.matrix[column][row]
+    }
+
+    pub fn set(&mut self, column: usize, row: usize, value: T) {
+        self.matrix[column][row] = value;
+    }
+
+    pub fn to_raw(&self) -> [[T; 3]; 3] {
+        self.matrix
+    }
+
+    /// Transform a 2D position, which is translated.
+    pub fn transform_point_2d(&self, point: Vector2<T>) -> Vector2<T> {
+        let Vector3 { x, y, .. } = self
+            * Vector3 {
+                x: point.x,
+                y: point.y,
+                z: T::one(),
+            };
+        Vector2 { x, y }
+    }
+
+    /// Transform a 2D direction, which isn't translated.
+    pub fn transform_vector_2d(&self, vector: Vector2<T>) -> Vector2<T> {
+        &Matrix2::from(self) * vector
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut transposed = *self;
+        for column in 0..3 {
+            for row in 0..3 {
+                transposed[column][row] = self[row][column];
+            }
+        }
+        transposed
+    }
+
+    pub fn determinant(&self) -> T {
+        let m = &self.matrix;
+        m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2])
+            - m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2])
+            + m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2])
+    }
+
+    /// The inverse of the matrix, None if it has none (its determinant is 0).
+    pub fn inverse(&self) -> Option<Self> {
+        let determinant = self.determinant();
+        if determinant == T::zero() || !determinant.is_finite() {
+            return None;
+        }
+        let m = &self.matrix;
+        // each column of the inverse is the cross product of two columns of the matrix, divided
+        // by the determinant, then the whole thing transposed
+        let cross = |a: usize, b: usize| {
+            Vector3 {
+                x: m[a][0],
+                y: m[a][1],
+                z: m[a][2],
+            }
+            .cross(Vector3 {
+                x: m[b][0],
+                y: m[b][1],
+                z: m[b][2],
+            }) / determinant
+        };
+        let rows = [cross(1, 2), cross(2, 0), cross(0, 1)];
+        Some(Self::new([
+            [rows[0].x, rows[1].x, rows[2].x],
+            [rows[0].y, rows[1].y, rows[2].y],
+            [rows[0].z, rows[1].z, rows[2].z],
+        ]))
+    }
+
+    /// Whether every element is within `epsilon` of the other matrix's.
+    pub fn approx_eq(&self, other: &Self, epsilon: T) -> bool {
+        self.matrix
+            .iter()
+            .flatten()
+            .zip(other.matrix.iter().flatten())
+            .all(|(a, b)| (*a - *b).abs() <= epsilon)
+    }
+}
+
+impl<T> std::ops::Mul<&Matrix3<T>> for &Matrix3<T>
+where
+    T: NumCast + Copy + Float,
+{
+    type Output = Matrix3<T>;
+
+    fn mul(self, rhs: &Matrix3<T>) -> Self::Output {
+        let column = |column: usize| {
+            let Vector3 { x, y, z } = self
+                * Vector3 {
+                    x: rhs[column][0],
+                    y: rhs[column][1],
+                    z: rhs[column][2],
+                };
+            [x, y, z]
+        };
+        Matrix3::new([column(0), column(1), column(2)])
+    }
+}
+
+impl<T> std::ops::Mul<Vector3<T>> for &Matrix3<T>
+where
+    T: NumCast + Copy + Float,
+{
+    type Output = Vector3<T>;
+
+    fn mul(self, rhs: Vector3<T>) -> Self::Output {
+        let row = |row: usize| self[0][row] * rhs.x + self[1][row] * rhs.y + self[2][row] * rhs.z;
+        Vector3 {
+            x: row(0),
+            y: row(1),
+            z: row(2),
+        }
+    }
+}
+
+impl<T> std::ops::Index<usize> for Matrix3<T> {
+    type Output = [T; 3];
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.matrix[index]
+    }
+}
+
+impl<T> std::ops::IndexMut<usize> for Matrix3<T> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.matrix[index]
+    }
+}
+
+/// The top left of a Matrix4, its rotation and scale without the translation.
+impl<T> From<&Matrix4<T>> for Matrix3<T>
+where
+    T: NumCast + Copy + Float,
+{
+    fn from(matrix: &Matrix4<T>) -> Self {
+        Self::new([
+            [matrix[0][0], matrix[0][1], matrix[0][2]],
+            [matrix[1][0], matrix[1][1], matrix[1][2]],
+            [matrix[2][0], matrix[2][1], matrix[2][2]],
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, SquareMatrix};
+
+    const EPSILON: f32 = 1e-4;
+
+    // cgmath's projections are right handed with depths from -1 to 1, this turns them into
+    // left handed ones with depths from 0 to 1 to compare with ours
+    fn to_wgpu(projection: cgmath::Matrix4<f32>) -> Matrix4<f32> {
+        let depth = cgmath::Matrix4::from([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.5, 0.0],
+            [0.0, 0.0, 0.5, 1.0],
+        ]);
+        let flip_z = cgmath::Matrix4::from_nonuniform_scale(1.0, 1.0, -1.0);
+        Matrix4::new((depth * projection * flip_z).into())
+    }
+
+    fn vector(x: f32, y: f32, z: f32) -> Vector3<f32> {
+        Vector3 { x, y, z }
+    }
+
+    fn point(vector: Vector3<f32>) -> cgmath::Point3<f32> {
+        cgmath::Point3::new(vector.x, vector.y, vector.z)
+    }
+
+    fn matrices() -> Vec<cgmath::Matrix4<f32>> {
+        let axis = cgmath::Vector3::new(1.0, -2.0, 0.5).normalize();
+        vec![
+            cgmath::Matrix4::identity(),
+            cgmath::Matrix4::from_translation(cgmath::Vector3::new(3.0, -1.0, 7.0)),
+            cgmath::Matrix4::from_nonuniform_scale(2.0, 0.5, -3.0),
+            cgmath::Matrix4::from_translation(cgmath::Vector3::new(-2.0, 4.0, 1.0))
+                * cgmath::Matrix4::from_axis_angle(axis, cgmath::Rad(1.3))
+                * cgmath::Matrix4::from_nonuniform_scale(1.5, 2.0, 0.25),
+            cgmath::perspective(cgmath::Deg(60.0), 1.5, 0.1, 100.0),
+            cgmath::Matrix4::from([
+                [2.0, 1.0, 0.0, 3.0],
+                [-1.0, 4.0, 2.0, 0.0],
+                [0.5, 0.0, 3.0, -2.0],
+                [1.0, 2.0, -1.0, 5.0],
+            ]),
+        ]
+    }
+
+    #[test]
+    fn determinant_matches_cgmath() {
+        for expected in matrices() {
+            let matrix = Matrix4::new(expected.into());
+            let determinant = expected.determinant();
+            assert!((matrix.determinant() - determinant).abs() <= EPSILON * determinant.abs());
+        }
+    }
+
+    #[test]
+    fn inverse_matches_cgmath() {
+        for expected in matrices() {
+            let matrix = Matrix4::new(expected.into());
+            let inverse = matrix.inverse().unwrap();
+            let expected = Matrix4::new(expected.invert().unwrap().into());
+            assert!(inverse.approx_eq(&expected, EPSILON), "{:?}", matrix);
+            assert!((&matrix * &inverse).approx_eq(&Matrix4::identity(), EPSILON));
+            assert!((&inverse * &matrix).approx_eq(&Matrix4::identity(), EPSILON));
+        }
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        // the last column is the sum of the first two
+        let matrix = Matrix4::new([
+            [1.0, 2.0, 3.0, 4.0],
+            [0.0, 1.0, -1.0, 2.0],
+            [5.0, 0.0, 2.0, 1.0],
+            [1.0, 3.0, 2.0, 6.0],
+        ]);
+        assert_eq!(matrix.determinant(), 0.0);
+        assert_eq!(matrix.inverse(), None);
+        let flattened = Matrix4::from_scale(vector(1.0, 0.0, 1.0));
+        assert_eq!(flattened.inverse(), None);
+        assert_eq!(Matrix4::new([[0.0; 4]; 4]).inverse(), None);
+    }
+
+    #[test]
+    fn transpose_matches_cgmath() {
+        use cgmath::Matrix;
+        for expected in matrices() {
+            let matrix = Matrix4::new(expected.into());
+            assert_eq!(
+                matrix.transpose(),
+                Matrix4::new(expected.transpose().into())
+            );
+        }
+    }
+
+    #[test]
+    fn matrix_times_vector4_matches_cgmath() {
+        let vectors = [
+            Vector4 {
+                x: 1.0,
+                y: -2.0,
+                z: 3.0,
+                w: 1.0,
+            },
+            Vector4 {
+                x: 0.5,
+                y: 4.0,
+                z: -1.0,
+                w: 0.0,
+            },
+        ];
+        for expected in matrices() {
+            let matrix = Matrix4::new(expected.into());
+            for v in vectors {
+                let product = expected * cgmath::Vector4::new(v.x, v.y, v.z, v.w);
+                let product = Vector4 {
+                    x: product.x,
+                    y: product.y,
+                    z: product.z,
+                    w: product.w,
+                };
+                assert!((&matrix * v).approx_eq(product, EPSILON));
+            }
+        }
+    }
+
+    #[test]
+    fn translation_moves_points_but_not_vectors() {
+        let translation = Matrix4::from_translation(vector(3.0, -1.0, 7.0));
+        let v = vector(1.0, 2.0, -3.0);
+        assert_eq!(translation.transform_point(v), vector(4.0, 1.0, 4.0));
+        assert_eq!(translation.transform_vector(v), v);
+        assert_eq!(
+            &translation * v.to_point(),
+            vector(4.0, 1.0, 4.0).to_point()
+        );
+        assert_eq!(&translation * v.to_direction(), v.to_direction());
+        // the rest of the transform still applies to vectors
+        let scale = Matrix4::from_scale(vector(2.0, 2.0, 2.0));
+        let transform = &translation * &scale;
+        assert_eq!(transform.transform_point(v), vector(5.0, 3.0, 1.0));
+        assert_eq!(transform.transform_vector(v), vector(2.0, 4.0, -6.0));
+    }
+
+    #[test]
+    fn transform_point_divides_by_w() {
+        let projection = Matrix4::perspective(90.0f32.to_radians(), 1.0, 1.0, 10.0);
+        // the top right corner of the far plane is at the top right of the screen
+        let corner = projection.transform_point(vector(10.0, 10.0, 10.0));
+        assert!(corner.approx_eq(vector(1.0, 1.0, 1.0), EPSILON));
+        let w = (&projection * vector(10.0, 10.0, 10.0).to_point()).w;
+        assert!((w - 10.0).abs() <= EPSILON);
+    }
+
+    #[test]
+    fn rotation_matches_cgmath() {
+        let axes = [
+            vector(1.0, 0.0, 0.0),
+            vector(0.0, 1.0, 0.0),
+            vector(0.3, -1.0, 0.5).normalised(),
+        ];
+        for axis in axes {
+            for angle in [0.0f32, 0.5, -2.0, std::f32::consts::PI] {
+                let expected = cgmath::Matrix4::from_axis_angle(
+                    cgmath::Vector3::new(axis.x, axis.y, axis.z),
+                    cgmath::Rad(angle),
+                );
+                let rotation = Matrix4::from_rotation(axis, angle);
+                assert!(rotation.approx_eq(&Matrix4::new(expected.into()), EPSILON));
+            }
+        }
+    }
+
+    #[test]
+    fn look_at_matches_cgmath() {
+        let cameras = [
+            (vector(0.0, 0.0, -5.0), vector(0.0, 0.0, 0.0)),
+            (vector(3.0, 2.0, 1.0), vector(-1.0, 0.5, 4.0)),
+            (vector(-2.0, 6.0, 2.0), vector(0.0, 0.0, 0.0)),
+        ];
+        let up = vector(0.0, 1.0, 0.0);
+        let cgmath_up = cgmath::Vector3::unit_y();
+        for (eye, target) in cameras {
+            let rh = cgmath::Matrix4::look_at_rh(point(eye), point(target), cgmath_up);
+            let lh = cgmath::Matrix4::look_at_lh(point(eye), point(target), cgmath_up);
+            assert!(
+                Matrix4::look_at_rh(eye, target, up).approx_eq(&Matrix4::new(rh.into()), EPSILON)
+            );
+            assert!(
+                Matrix4::look_at_lh(eye, target, up).approx_eq(&Matrix4::new(lh.into()), EPSILON)
+            );
+            // the camera sits at the origin of view space, looking down +z when left handed
+            let view = Matrix4::look_at_lh(eye, target, up);
+            assert!(view
+                .transform_point(eye)
+                .approx_eq(vector(0.0, 0.0, 0.0), EPSILON));
+            let distance = eye.distance(target);
+            assert!(view
+                .transform_point(target)
+                .approx_eq(vector(0.0, 0.0, distance), EPSILON));
+        }
+    }
+
+    #[test]
+    fn perspective_matches_cgmath() {
+        for (fov, aspect_ratio, near, far) in
+            [(45.0f32, 16.0 / 9.0, 0.1, 100.0), (90.0, 1.0, 1.0, 10.0)]
+        {
+            let expected = to_wgpu(cgmath::perspective(
+                cgmath::Deg(fov),
+                aspect_ratio,
+                near,
+                far,
+            ));
+            let projection = Matrix4::perspective(fov.to_radians(), aspect_ratio, near, far);
+            assert!(projection.approx_eq(&expected, EPSILON));
+            // depth runs from 0 at the near plane to 1 at the far plane
+            let near_depth = projection.transform_point(vector(0.0, 0.0, near)).z;
+            let far_depth = projection.transform_point(vector(0.0, 0.0, far)).z;
+            assert!(near_depth.abs() <= EPSILON);
+            assert!((far_depth - 1.0).abs() <= EPSILON);
+        }
+    }
+
+    #[test]
+    fn orthographic_matches_cgmath() {
+        let (left, right, bottom, top, near, far) = (-4.0, 2.0, -1.0, 3.0, 0.5, 20.0);
+        let expected = to_wgpu(cgmath::ortho(left, right, bottom, top, near, far));
+        let projection = Matrix4::orthographic(left, right, bottom, top, near, far);
+        assert!(projection.approx_eq(&expected, EPSILON));
+        let corner = projection.transform_point(vector(right, top, far));
+        assert!(corner.approx_eq(vector(1.0, 1.0, 1.0), EPSILON));
+    }
+
+    fn vector_2d(x: f32, y: f32) -> Vector2<f32> {
+        Vector2 { x, y }
+    }
+
+    #[test]
+    fn matrix2_matches_cgmath() {
+        let expected = cgmath::Matrix2::new(2.0, -1.0, 0.5, 3.0);
+        let matrix = Matrix2::new(expected.into());
+        assert!((matrix.determinant() - expected.determinant()).abs() <= EPSILON);
+        let inverse = Matrix2::new(expected.invert().unwrap().into());
+        assert!(matrix.inverse().unwrap().approx_eq(&inverse, EPSILON));
+        // a rotation's inverse is its transpose
+        let rotation = Matrix2::from_rotation(0.7);
+        assert!(rotation
+            .inverse()
+            .unwrap()
+            .approx_eq(&rotation.transpose(), EPSILON));
+        let flattened = Matrix2::from_scale(vector_2d(3.0, 0.0));
+        assert_eq!(flattened.inverse(), None);
+    }
+
+    #[test]
+    fn matrix3_matches_cgmath() {
+        let expected = cgmath::Matrix3::new(2.0, 1.0, 0.0, -1.0, 4.0, 2.0, 0.5, 0.0, 3.0);
+        let matrix = Matrix3::new(expected.into());
+        let determinant = expected.determinant();
+        assert!((matrix.determinant() - determinant).abs() <= EPSILON * determinant.abs());
+        let inverse = Matrix3::new(expected.invert().unwrap().into());
+        assert!(matrix.inverse().unwrap().approx_eq(&inverse, EPSILON));
+        assert!((&matrix * &matrix.inverse().unwrap()).approx_eq(&Matrix3::identity(), EPSILON));
+    }
+
+    #[test]
+    fn affine_2d_composes_and_inverts() {
+        let translation = Matrix3::from_translation_2d(vector_2d(4.0, -2.0));
+        let rotation = Matrix3::from_rotation_2d(std::f32::consts::FRAC_PI_2);
+        let scale = Matrix3::from_scale_2d(vector_2d(2.0, 3.0));
+        let transform = &translation * &(&rotation * &scale);
+        // scaled to (2, 3), turned a quarter anticlockwise to (-3, 2), then moved
+        let point = vector_2d(1.0, 1.0);
+        let moved = transform.transform_point_2d(point);
+        assert!(moved.approx_eq(vector_2d(1.0, 0.0), EPSILON));
+        // directions are scaled and turned but not moved
+        let direction = transform.transform_vector_2d(point);
+        assert!(direction.approx_eq(vector_2d(-3.0, 2.0), EPSILON));
+        let inverse = transform.inverse().unwrap();
+        assert!(inverse.transform_point_2d(moved).approx_eq(point, EPSILON));
+        assert!((&inverse * &transform).approx_eq(&Matrix3::identity(), EPSILON));
+        assert!((&transform * &inverse).approx_eq(&Matrix3::identity(), EPSILON));
+    }
+
+    #[test]
+    fn affine_2d_about_keeps_the_origin_in_place() {
+        let centre = vector_2d(1.0, 1.0);
+        let rotation = Matrix3::from_rotation_2d(std::f32::consts::FRAC_PI_2).about(centre);
+        assert!(rotation
+            .transform_point_2d(centre)
+            .approx_eq(centre, EPSILON));
+        let turned = rotation.transform_point_2d(vector_2d(2.0, 1.0));
+        assert!(turned.approx_eq(vector_2d(1.0, 2.0), EPSILON));
+    }
+
+    #[test]
+    fn normal_matrix_matches_cgmath() {
+        use cgmath::Matrix;
+        let axis = cgmath::Vector3::new(1.0, -2.0, 0.5).normalize();
+        let expected = cgmath::Matrix4::from_translation(cgmath::Vector3::new(-2.0, 4.0, 1.0))
+            * cgmath::Matrix4::from_axis_angle(axis, cgmath::Rad(1.3))
+            * cgmath::Matrix4::from_nonuniform_scale(1.5, 4.0, 0.25);
+        let transform = Matrix4::new(expected.into());
+        let top_left = cgmath::Matrix3::from_cols(
+            expected.x.truncate(),
+            expected.y.truncate(),
+            expected.z.truncate(),
+        );
+        let normal_matrix = transform.normal_matrix().unwrap();
+        let cgmath_normal = Matrix3::new(top_left.invert().unwrap().transpose().into());
+        assert!(normal_matrix.approx_eq(&cgmath_normal, EPSILON));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = Matrix4::from_scale(vector(4.0, 1.0, 1.0));
+        // a surface sloping at 45 degrees in x and y
+        let tangent = vector(1.0, -1.0, 0.0);
+        let normal = vector(1.0, 1.0, 0.0).normalised();
+        let tangent = transform.transform_vector(tangent);
+        let normal_matrix = transform.normal_matrix().unwrap();
+        let transformed = (&normal_matrix * normal).normalised();
+        assert!(transformed.dot(tangent).abs() <= EPSILON);
+        // transforming the normal like any other direction tilts it off the surface
+        let skewed = transform.transform_vector(normal);
+        assert!(skewed.dot(tangent).abs() > 1.0);
+        assert_eq!(
+            Matrix4::from_scale(vector(1.0, 0.0, 1.0)).normal_matrix(),
+            None
+        );
+    }
+}
@@ -1,3 +1,4 @@
+pub mod angle;
 pub mod matrix;
 pub mod quaternion;
 pub mod transformation;
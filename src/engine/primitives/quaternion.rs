@@ -1,86 +1,506 @@
-use core::num;
-use std::f32::consts::PI;
-
 use num_traits::{Float, NumCast};
 
+use crate::engine::primitives::angle::Radians;
 use crate::engine::primitives::vector::Vector3;
 
-use super::matrix::Matrix4;
+use super::matrix::{Matrix3, Matrix4};
 
+/// A rotation as a unit quaternion, w + xi + yj + zk. Multiplying two rotations gives the
+/// rotation applying the right one first, then the left.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Quaternion<T>
 where
     T: num_traits::Num + NumCast + Copy,
 {
-    angle: T,
-    axis: Vector3<T>,
+    pub w: T,
+    pub x: T,
+    pub y: T,
+    pub z: T,
 }
 
 impl<T> Quaternion<T>
 where
     T: NumCast + Copy + num_traits::Num + Float,
 {
-    /*
-        axis: the axis to rotate around
-        angle: the angle to rotate
-        unit: whether the quaternion is a unit quaternion
-            (I am worried about floating point inaccuracies causing scaling)
-    */
-    pub fn new(axis: Vector3<T>, angle: T) -> Self {
-        Self { angle, axis }
+    pub fn new(w: T, x: T, y: T, z: T) -> Self {
+        Self { w, x, y, z }
+    }
+
+    /// No rotation.
+    pub fn identity() -> Self {
+        Self::new(T::one(), T::zero(), T::zero(), T::zero())
+    }
+
+    /// A rotation of `angle` around `axis`, anticlockwise looking down the axis. The axis
+    /// doesn't have to be unit length, but mustn't be zero.
+    pub fn from_axis_angle(axis: Vector3<T>, angle: impl Into<Radians<T>>) -> Self {
+        let two = T::one() + T::one();
+        let (sin, cos) = (angle.into().0 / two).sin_cos();
+        let axis = axis.normalised() * sin;
+        Self::new(cos, axis.x, axis.y, axis.z)
+    }
+
+    /// Turn `yaw` around y, after turning `pitch` around x, after turning `roll` around z, the
+    /// usual order for cameras and characters.
+    pub fn from_euler(
+        pitch: impl Into<Radians<T>>,
+        yaw: impl Into<Radians<T>>,
+        roll: impl Into<Radians<T>>,
+    ) -> Self {
+        let (zero, one) = (T::zero(), T::one());
+        let around = |x, y, z, angle: Radians<T>| Self::from_axis_angle(Vector3 { x, y, z }, angle);
+        around(zero, one, zero, yaw.into())
+            * around(one, zero, zero, pitch.into())
+            * around(zero, zero, one, roll.into())
+    }
+
+    /// The rotation of a matrix without scaling, eg. the top left of a Matrix4 from
+    /// Matrix4::from_rotation.
+    pub fn from_rotation_matrix(matrix: &Matrix3<T>) -> Self {
+        // element (row, column), the matrix is stored column first
+        let m = |row: usize, column: usize| matrix[column][row];
+        let (one, two) = (T::one(), T::one() + T::one());
+        let four = two + two;
+        // divide by the largest of w, x, y and z, to keep precision
+        // https://www.euclideanspace.com/maths/geometry/rotations/conversions/matrixToQuaternion/
+        let trace = m(0, 0) + m(1, 1) + m(2, 2);
+        let quaternion = if trace > T::zero() {
+            let s = (trace + one).sqrt() * two;
+            Self::new(
+                s / four,
+                (m(2, 1) - m(1, 2)) / s,
+                (m(0, 2) - m(2, 0)) / s,
+                (m(1, 0) - m(0, 1)) / s,
+            )
+        } else if m(0, 0) > m(1, 1) && m(0, 0) > m(2, 2) {
+            let s = (one + m(0, 0) - m(1, 1) - m(2, 2)).sqrt() * two;
+            Self::new(
+                (m(2, 1) - m(1, 2)) / s,
+                s / four,
+                (m(0, 1) + m(1, 0)) / s,
+                (m(0, 2) + m(2, 0)) / s,
+            )
+        } else if m(1, 1) > m(2, 2) {
+            let s = (one + m(1, 1) - m(0, 0) - m(2, 2)).sqrt() * two;
+            Self::new(
+                (m(0, 2) - m(2, 0)) / s,
+                (m(0, 1) + m(1, 0)) / s,
+                s / four,
+                (m(1, 2) + m(2, 1)) / s,
+            )
+        } else {
+            let s = (one + m(2, 2) - m(0, 0) - m(1, 1)).sqrt() * two;
+            Self::new(
+                (m(1, 0) - m(0, 1)) / s,
+                (m(0, 2) + m(2, 0)) / s,
+                (m(1, 2) + m(2, 1)) / s,
+                s / four,
+            )
+        };
+        quaternion.normalised()
+    }
+
+    /// The axis (unit length) and angle of the rotation, the angle being from 0 to 2 pi. With
+    /// no rotation the axis is x.
+    pub fn to_axis_angle(&self) -> (Vector3<T>, Radians<T>) {
+        let two = T::one() + T::one();
+        let quaternion = self.normalised();
+        let angle = two * quaternion.w.max(-T::one()).min(T::one()).acos();
+        let sin = (T::one() - quaternion.w * quaternion.w).sqrt();
+        if sin <= T::epsilon() {
+            let x_axis = Vector3 {
+                x: T::one(),
+                y: T::zero(),
+                z: T::zero(),
+            };
+            return (x_axis, Radians(angle));
+        }
+        (quaternion.vector() / sin, Radians(angle))
+    }
+
+    pub fn dot(&self, other: Self) -> T {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn magnitude(&self) -> T {
+        self.dot(*self).sqrt()
     }
 
-    pub fn set_rotation(&mut self, axis: Vector3<T>, angle: T) {
-        self.axis = axis;
-        self.angle = angle;
+    pub fn normalise(&mut self) {
+        *self = self.normalised();
     }
 
-    pub fn axis(&self) -> &Vector3<T> {
-        &self.axis
+    /// A copy of the quaternion with a magnitude of 1, rotations drift away from it as they are
+    /// multiplied together.
+    pub fn normalised(&self) -> Self {
+        self.scaled(T::one() / self.magnitude())
     }
 
-    pub fn angle(&self) -> T {
-        self.angle
+    /// The opposite rotation, for a unit quaternion.
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
     }
 
-    pub fn set_angle(&mut self, angle: T) {
-        self.angle = angle;
+    /// The opposite rotation, None for the zero quaternion.
+    pub fn inverse(&self) -> Option<Self> {
+        let square_magnitude = self.dot(*self);
+        if square_magnitude == T::zero() {
+            return None;
+        }
+        Some(self.conjugate().scaled(T::one() / square_magnitude))
     }
 
-    pub fn set_axis(&mut self, axis: Vector3<T>) {
-        self.axis = axis;
+    pub fn rotate_vector(&self, vector: Vector3<T>) -> Vector3<T> {
+        // q v q*, expanded to avoid the multiplications by zero
+        let two = T::one() + T::one();
+        let axis = self.vector();
+        let t = axis.cross(vector) * two;
+        vector + t * self.w + axis.cross(t)
     }
 
-    pub fn to_matrix(&self) -> Matrix4<f32> {
-        Matrix4::new(self.to_raw())
+    /// Turn from this rotation `t` of the way to `other` at a constant speed, taking the
+    /// shortest way round.
+    pub fn slerp(&self, other: Self, t: T) -> Self {
+        let (other, cos) = self.nearest(other);
+        // sin of the angle is close to 0, so fall back on a straight line
+        if cos > T::from(0.9995).unwrap() {
+            return self.nlerp(other, t);
+        }
+        let angle = cos.acos();
+        let start = ((T::one() - t) * angle).sin();
+        let end = (t * angle).sin();
+        self.scaled(start)
+            .plus(other.scaled(end))
+            .scaled(T::one() / angle.sin())
     }
 
-    pub fn to_raw(&self) -> [[f32; 4]; 4] {
-        let w = (self.angle.to_f32().unwrap().to_radians() / 2.0).cos();
-        let angle = self.angle.to_f32().unwrap().to_radians();
-        let x = self.axis.x.to_f32().unwrap() * (angle / 2.0).sin();
-        let y = self.axis.y.to_f32().unwrap() * (angle / 2.0).sin();
-        let z = self.axis.z.to_f32().unwrap() * (angle / 2.0).sin();
+    /// Like slerp but cheaper, turning faster in the middle than at the ends.
+    pub fn nlerp(&self, other: Self, t: T) -> Self {
+        let (other, _) = self.nearest(other);
+        self.scaled(T::one() - t).plus(other.scaled(t)).normalised()
+    }
+
+    /// Whether every component is within `epsilon` of the other quaternion's. q and -q are the
+    /// same rotation, but aren't approximately equal.
+    pub fn approx_eq(&self, other: Self, epsilon: T) -> bool {
+        (self.w - other.w).abs() <= epsilon
+            && (self.x - other.x).abs() <= epsilon
+            && (self.y - other.y).abs() <= epsilon
+            && (self.z - other.z).abs() <= epsilon
+    }
+
+    pub fn to_matrix(&self) -> Matrix4<T> {
+        let Self { w, x, y, z } = *self;
+        let (zero, one, two) = (T::zero(), T::one(), T::one() + T::one());
         // https://en.wikipedia.org/wiki/Quaternions_and_spatial_rotation
-        [
+        Matrix4::new([
             [
-                w.powi(2) + x.powi(2) - y.powi(2) - z.powi(2),
-                2.0 * x * y + 2.0 * w * z,
-                2.0 * x * z - 2.0 * w * y,
-                0.0,
+                w * w + x * x - y * y - z * z,
+                two * x * y + two * w * z,
+                two * x * z - two * w * y,
+                zero,
             ],
             [
-                2.0 * x * y - 2.0 * w * z,
-                w.powi(2) - x.powi(2) + y.powi(2) - z.powi(2),
-                2.0 * y * z + 2.0 * w * x,
-                0.0,
+                two * x * y - two * w * z,
+                w * w - x * x + y * y - z * z,
+                two * y * z + two * w * x,
+                zero,
             ],
             [
-                2.0 * x * z + 2.0 * w * y,
-                2.0 * y * z - 2.0 * w * x,
-                w.powi(2) - x.powi(2) - y.powi(2) + z.powi(2),
-                0.0,
+                two * x * z + two * w * y,
+                two * y * z - two * w * x,
+                w * w - x * x - y * y + z * z,
+                zero,
             ],
-            [0.0, 0.0, 0.0, 1.0],
-        ]
+            [zero, zero, zero, one],
+        ])
+    }
+
+    fn vector(&self) -> Vector3<T> {
+        Vector3 {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+        }
+    }
+
+    fn scaled(&self, scale: T) -> Self {
+        Self::new(
+            self.w * scale,
+            self.x * scale,
+            self.y * scale,
+            self.z * scale,
+        )
+    }
+
+    fn plus(&self, other: Self) -> Self {
+        Self::new(
+            self.w + other.w,
+            self.x + other.x,
+            self.y + other.y,
+            self.z + other.z,
+        )
+    }
+
+    // q and -q are the same rotation, interpolating towards whichever is closer takes the short
+    // way round
+    fn nearest(&self, other: Self) -> (Self, T) {
+        let cos = self.dot(other);
+        if cos < T::zero() {
+            (other.scaled(-T::one()), -cos)
+        } else {
+            (other, cos)
+        }
+    }
+}
+
+impl<T> std::ops::Mul for Quaternion<T>
+where
+    T: NumCast + Copy + num_traits::Num + Float,
+{
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        )
+    }
+}
+
+impl<T> std::ops::MulAssign for Quaternion<T>
+where
+    T: NumCast + Copy + num_traits::Num + Float,
+{
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl<T> Default for Quaternion<T>
+where
+    T: NumCast + Copy + num_traits::Num + Float,
+{
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::primitives::angle::Degrees;
+    use cgmath::{InnerSpace, Rotation, Rotation3};
+
+    const EPSILON: f32 = 1e-5;
+
+    fn vector(x: f32, y: f32, z: f32) -> Vector3<f32> {
+        Vector3 { x, y, z }
+    }
+
+    fn around(axis: Vector3<f32>, degrees: f32) -> Quaternion<f32> {
+        Quaternion::from_axis_angle(axis, Degrees(degrees))
+    }
+
+    fn to_cgmath(quaternion: Quaternion<f32>) -> cgmath::Quaternion<f32> {
+        cgmath::Quaternion::new(quaternion.w, quaternion.x, quaternion.y, quaternion.z)
+    }
+
+    fn from_cgmath(quaternion: cgmath::Quaternion<f32>) -> Quaternion<f32> {
+        let v = quaternion.v;
+        Quaternion::new(quaternion.s, v.x, v.y, v.z)
+    }
+
+    // q and -q are the same rotation
+    fn same_rotation(a: Quaternion<f32>, b: Quaternion<f32>) -> bool {
+        a.approx_eq(b, EPSILON) || a.approx_eq(b.scaled(-1.0), EPSILON)
+    }
+
+    #[test]
+    fn to_matrix_matches_from_rotation() {
+        let axis = vector(1.0, -2.0, 0.5).normalised();
+        let rotation = around(axis, 70.0);
+        let expected = Matrix4::from_rotation(axis, 70.0f32.to_radians());
+        assert!(rotation.to_matrix().approx_eq(&expected, EPSILON));
+    }
+
+    #[test]
+    fn composition_matches_matrix_product() {
+        let first = around(vector(0.0, 1.0, 0.0), 90.0);
+        let second = around(vector(1.0, 1.0, 0.0), -40.0);
+        let product = &second.to_matrix() * &first.to_matrix();
+        assert!((second * first).to_matrix().approx_eq(&product, EPSILON));
+        // the right hand rotation is applied first
+        let v = vector(1.0, 2.0, 3.0);
+        let expected = second.rotate_vector(first.rotate_vector(v));
+        assert!((second * first)
+            .rotate_vector(v)
+            .approx_eq(expected, EPSILON));
+        assert!(product.transform_vector(v).approx_eq(expected, EPSILON));
+    }
+
+    #[test]
+    fn slerp_endpoints_and_midpoint() {
+        let axis = vector(0.0, 1.0, 0.0);
+        let start = around(axis, 10.0);
+        let end = around(axis, 100.0);
+        assert!(start.slerp(end, 0.0).approx_eq(start, EPSILON));
+        assert!(start.slerp(end, 1.0).approx_eq(end, EPSILON));
+        assert!(start.slerp(end, 0.5).approx_eq(around(axis, 55.0), EPSILON));
+        // constant speed, a quarter of the way is a quarter of the angle
+        assert!(start
+            .slerp(end, 0.25)
+            .approx_eq(around(axis, 32.5), EPSILON));
+    }
+
+    #[test]
+    fn slerp_takes_the_shortest_path() {
+        let axis = vector(0.0, 0.0, 1.0);
+        let start = Quaternion::identity();
+        // 270 degrees one way is 90 degrees the other, with every component negated
+        let end = around(axis, 270.0);
+        assert!(start.dot(end) < 0.0);
+        let middle = start.slerp(end, 0.5);
+        assert!(same_rotation(middle, around(axis, -45.0)));
+        assert!(!same_rotation(middle, around(axis, 135.0)));
+        // -end is the same rotation, so it makes no difference which is given
+        assert!(start
+            .slerp(end.scaled(-1.0), 0.5)
+            .approx_eq(middle, EPSILON));
+        assert!(same_rotation(start.slerp(end, 1.0), end));
+    }
+
+    #[test]
+    fn from_rotation_matrix_round_trips() {
+        let rotations = [
+            // a positive trace
+            around(vector(1.0, 2.0, 3.0), 30.0),
+            Quaternion::identity(),
+            // a negative trace, with x, y and z the largest of the diagonal in turn
+            around(vector(1.0, 0.2, 0.1), 170.0),
+            around(vector(1.0, 0.0, 0.0), 180.0),
+            around(vector(0.1, 1.0, 0.2), 170.0),
+            around(vector(0.0, 1.0, 0.0), 180.0),
+            around(vector(0.2, 0.1, 1.0), 170.0),
+            around(vector(0.0, 0.0, 1.0), 180.0),
+        ];
+        for rotation in rotations {
+            let matrix = Matrix3::from(&rotation.to_matrix());
+            let converted = Quaternion::from_rotation_matrix(&matrix);
+            assert!(same_rotation(converted, rotation), "{:?}", rotation);
+        }
+    }
+    #[test]
+    fn from_axis_angle_matches_cgmath() {
+        let axis = vector(1.0, -2.0, 0.5);
+        for degrees in [0.0, 45.0, 170.0, 270.0, -60.0] {
+            let unit = axis.normalised();
+            let expected = cgmath::Quaternion::from_axis_angle(
+                cgmath::Vector3::new(unit.x, unit.y, unit.z),
+                cgmath::Deg(degrees),
+            );
+            // the axis doesn't have to be unit length
+            assert!(around(axis, degrees).approx_eq(from_cgmath(expected), EPSILON));
+        }
+    }
+
+    #[test]
+    fn to_axis_angle_round_trips() {
+        let axes = [
+            vector(1.0, 0.0, 0.0),
+            vector(0.0, -1.0, 0.0),
+            vector(1.0, 2.0, 3.0).normalised(),
+        ];
+        for axis in axes {
+            for degrees in [10.0, 90.0, 180.0, 270.0, 350.0] {
+                let (found_axis, angle) = around(axis, degrees).to_axis_angle();
+                assert!(found_axis.approx_eq(axis, 1e-4), "{:?} {}", axis, degrees);
+                assert!((angle.0.to_degrees() - degrees).abs() < 1e-3);
+            }
+        }
+        // a negative angle comes back as the same turn the other way round the axis
+        let (axis, angle) = around(vector(0.0, 0.0, 1.0), -90.0).to_axis_angle();
+        assert!(axis.approx_eq(vector(0.0, 0.0, -1.0), EPSILON));
+        assert!((angle.0.to_degrees() - 90.0).abs() < 1e-3);
+        // no rotation has no axis, so x is given
+        let (axis, angle) = Quaternion::<f32>::identity().to_axis_angle();
+        assert_eq!((axis, angle.0), (vector(1.0, 0.0, 0.0), 0.0));
+    }
+
+    #[test]
+    fn from_euler_turns_roll_then_pitch_then_yaw() {
+        let (pitch, yaw, roll) = (Degrees(20.0f32), Degrees(-70.0), Degrees(35.0));
+        let rotation = Quaternion::from_euler(pitch, yaw, roll);
+        let expected = cgmath::Quaternion::from_angle_y(cgmath::Deg(yaw.0))
+            * cgmath::Quaternion::from_angle_x(cgmath::Deg(pitch.0))
+            * cgmath::Quaternion::from_angle_z(cgmath::Deg(roll.0));
+        assert!(rotation.approx_eq(from_cgmath(expected), EPSILON));
+        let v = vector(1.0, 2.0, 3.0);
+        let turned = around(vector(0.0, 1.0, 0.0), yaw.0).rotate_vector(
+            around(vector(1.0, 0.0, 0.0), pitch.0)
+                .rotate_vector(around(vector(0.0, 0.0, 1.0), roll.0).rotate_vector(v)),
+        );
+        assert!(rotation.rotate_vector(v).approx_eq(turned, 1e-4));
+        // a quarter turn of yaw on its own turns forward (+z) to +x
+        let yaw_only = Quaternion::from_euler(Degrees(0.0), Degrees(90.0), Degrees(0.0));
+        let forward = yaw_only.rotate_vector(vector(0.0, 0.0, 1.0));
+        assert!(forward.approx_eq(vector(1.0, 0.0, 0.0), EPSILON));
+    }
+
+    #[test]
+    fn inverse_undoes_the_rotation() {
+        let rotation = around(vector(1.0, 2.0, 3.0), 130.0);
+        // not unit length, which the conjugate alone wouldn't undo
+        let scaled = rotation.scaled(3.0);
+        for quaternion in [rotation, scaled] {
+            let inverse = quaternion.inverse().unwrap();
+            let expected = to_cgmath(quaternion).invert();
+            assert!(inverse.approx_eq(from_cgmath(expected), EPSILON));
+            assert!((quaternion * inverse).approx_eq(Quaternion::identity(), EPSILON));
+            assert!((inverse * quaternion).approx_eq(Quaternion::identity(), EPSILON));
+        }
+        let v = vector(-1.0, 0.5, 2.0);
+        let inverse = rotation.inverse().unwrap();
+        assert!(inverse
+            .rotate_vector(rotation.rotate_vector(v))
+            .approx_eq(v, 1e-4));
+        assert_eq!(Quaternion::new(0.0f32, 0.0, 0.0, 0.0).inverse(), None);
+    }
+
+    #[test]
+    fn rotate_vector_matches_cgmath() {
+        let rotation = around(vector(-1.0, 0.5, 2.0), 75.0);
+        let v = vector(1.0, 2.0, 3.0);
+        let expected = to_cgmath(rotation) * cgmath::Vector3::new(v.x, v.y, v.z);
+        let rotated = rotation.rotate_vector(v);
+        assert!(rotated.approx_eq(vector(expected.x, expected.y, expected.z), 1e-4));
+    }
+
+    #[test]
+    fn nlerp_matches_cgmath() {
+        let start = around(vector(0.0, 1.0, 0.0), 10.0);
+        let ends = [
+            around(vector(1.0, 1.0, 0.0), 100.0),
+            // the long way round, which both take the short way instead
+            around(vector(0.0, 0.0, 1.0), 300.0),
+        ];
+        for end in ends {
+            assert!(start.nlerp(end, 0.0).approx_eq(start, EPSILON));
+            assert!(same_rotation(start.nlerp(end, 1.0), end));
+            for t in [0.25, 0.5, 0.8] {
+                let nlerp = start.nlerp(end, t);
+                let expected = to_cgmath(start).nlerp(to_cgmath(end), t);
+                assert!(nlerp.approx_eq(from_cgmath(expected), EPSILON));
+                assert!((to_cgmath(nlerp).magnitude() - 1.0).abs() < EPSILON);
+            }
+        }
+        // turning around a single axis, halfway is the same as slerp's halfway
+        let axis = vector(0.0, 1.0, 0.0);
+        let (start, end) = (around(axis, 10.0), around(axis, 100.0));
+        assert!(start
+            .nlerp(end, 0.5)
+            .approx_eq(start.slerp(end, 0.5), EPSILON));
     }
 }
@@ -1,4 +1,8 @@
-use super::{matrix::Matrix4, quaternion::Quaternion, vector::Vector3};
+use super::{
+    matrix::{Matrix2, Matrix3, Matrix4},
+    quaternion::Quaternion,
+    vector::{Vector2, Vector3},
+};
 
 pub struct Transformation3D {
     rotation: Quaternion<f32>,
@@ -8,18 +12,12 @@ pub struct Transformation3D {
 
 impl Transformation3D {
     pub fn new(position: Vector3<f32>, rotation: Quaternion<f32>, scale: f32) -> Self {
-        let position = Matrix4::new([
-            [1.0, 0.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [position.x, position.y, position.z, 1.0],
-        ]);
-        let scale = Matrix4::new([
-            [scale, 0.0, 0.0, 0.0],
-            [0.0, scale, 0.0, 0.0],
-            [0.0, 0.0, scale, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ]);
+        let position = Matrix4::from_translation(position);
+        let scale = Matrix4::from_scale(Vector3 {
+            x: scale,
+            y: scale,
+            z: scale,
+        });
         Self {
             position,
             scale,
@@ -47,12 +45,10 @@ impl Transformation3D {
         self.rotation = rotation;
     }
 
-    pub fn set_angle(&mut self, angle: f32) {
-        self.rotation.set_angle(angle);
-    }
-
-    pub fn set_axis(&mut self, axis: Vector3<f32>) {
-        self.rotation.set_axis(axis);
+    /// Turn by `rotation` on top of the current rotation.
+    pub fn rotate(&mut self, rotation: Quaternion<f32>) {
+        // renormalise so rounding errors don't build up into scaling
+        self.rotation = (rotation * self.rotation).normalised();
     }
 
     pub fn set_scale(&mut self, scale: f32) {
@@ -81,23 +77,27 @@ pub struct Transformation2D {
 
 impl Transformation2D {
     pub fn new(rotation: f32, scale: f32) -> Self {
-        let rotation = [
-            [rotation.cos(), -(rotation.sin())],
-            [rotation.sin(), rotation.cos()],
-        ];
-
-        let scale = [[scale, 0.0], [0.0, scale]];
+        Self {
+            rotation: rotation_2d(rotation).to_raw(),
+            scale: Matrix2::from_scale(Vector2 { x: scale, y: scale }).to_raw(),
+        }
+    }
 
-        Self { rotation, scale }
+    pub fn update(&mut self, rotation: f32, scale_x: f32, scale_y: f32) {
+        self.rotation = rotation_2d(rotation).to_raw();
+        self.scale = Matrix2::from_scale(Vector2 {
+            x: scale_x,
+            y: scale_y,
+        })
+        .to_raw();
     }
 
-    pub fn update(&mut self, rotation: f32, scale: f32) {
-        self.rotation = [
-            [rotation.cos(), -(rotation.sin())],
-            [rotation.sin(), rotation.cos()],
-        ];
-        self.scale[0][0] = scale;
-        self.scale[1][1] = scale;
+    /// The whole transform shader_2d.wgsl applies to an entity's quad, taking it to pixels on
+    /// the screen, eg. to find what is under the cursor.
+    pub fn to_matrix(&self, position: Vector2<f32>, origin: Vector2<f32>) -> Matrix3<f32> {
+        let rotate = Matrix3::from_linear_2d(&Matrix2::new(self.rotation)).about(origin);
+        let scale = Matrix3::from_linear_2d(&Matrix2::new(self.scale));
+        &Matrix3::from_translation_2d(position) * &(&rotate * &scale)
     }
 
     pub fn rotation(&self) -> [[f32; 2]; 2] {
@@ -108,3 +108,8 @@ impl Transformation2D {
         self.scale
     }
 }
+
+// y points down the screen, so the angle is negated to keep positive rotations anticlockwise
+fn rotation_2d(angle: f32) -> Matrix2<f32> {
+    Matrix2::from_rotation(-angle)
+}
@@ -1,6 +1,7 @@
-use num_traits::{real::Real, Float, NumCast};
+use num_traits::{Float, NumCast};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Vector2<T>
 where
     T: num_traits::Num + Copy,
@@ -16,6 +17,16 @@ where
     pub fn to_raw(&self) -> [T; 2] {
         [self.x, self.y]
     }
+
+    pub fn dot(&self, other: Vector2<T>) -> T {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// The z component of the cross product of the two vectors extended into 3D, positive when
+    /// `other` is anticlockwise of this vector.
+    pub fn cross(&self, other: Vector2<T>) -> T {
+        self.x * other.y - self.y * other.x
+    }
 }
 
 impl<T> Vector2<T>
@@ -35,9 +46,46 @@ where
     pub fn square_magnitude(&self) -> T {
         self.x.powi(2) + self.y.powi(2)
     }
+
+    /// A copy of the vector with a magnitude of 1, see normalise.
+    pub fn normalised(&self) -> Self {
+        *self / self.magnitude()
+    }
+
+    pub fn distance(&self, other: Self) -> T {
+        (other - *self).magnitude()
+    }
+
+    /// The point `t` of the way from this vector to `other`, `t` isn't clamped.
+    pub fn lerp(&self, other: Self, t: T) -> Self {
+        *self + (other - *self) * t
+    }
+
+    /// The vector bounced off a surface with the unit length `normal`.
+    pub fn reflect(&self, normal: Self) -> Self {
+        let two = T::one() + T::one();
+        *self - normal * (two * self.dot(normal))
+    }
+
+    /// The part of the vector pointing along `onto`, which must not be zero.
+    pub fn project(&self, onto: Self) -> Self {
+        onto * (self.dot(onto) / onto.square_magnitude())
+    }
+
+    /// The angle between the two vectors in radians, from 0 to pi.
+    pub fn angle_between(&self, other: Self) -> T {
+        let cos = self.dot(other) / (self.magnitude() * other.magnitude());
+        // rounding can put the cosine of near parallel vectors just outside -1 to 1
+        cos.max(-T::one()).min(T::one()).acos()
+    }
+
+    /// Whether every component is within `epsilon` of the other vector's.
+    pub fn approx_eq(&self, other: Self, epsilon: T) -> bool {
+        (self.x - other.x).abs() <= epsilon && (self.y - other.y).abs() <= epsilon
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vector3<T>
 where
     T: num_traits::Num + Copy,
@@ -54,6 +102,38 @@ where
     pub fn to_raw(&self) -> [T; 3] {
         [self.x, self.y, self.z]
     }
+
+    pub fn dot(&self, other: Vector3<T>) -> T {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: Vector3<T>) -> Vector3<T> {
+        Vector3 {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    /// The position as homogeneous coordinates, with a w of 1 so it is translated.
+    pub fn to_point(&self) -> Vector4<T> {
+        Vector4 {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            w: T::one(),
+        }
+    }
+
+    /// The direction as homogeneous coordinates, with a w of 0 so it isn't translated.
+    pub fn to_direction(&self) -> Vector4<T> {
+        Vector4 {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            w: T::zero(),
+        }
+    }
 }
 
 impl<T> Vector3<T>
@@ -74,4 +154,472 @@ where
     pub fn square_magnitude(&self) -> T {
         self.x.powi(2) + self.y.powi(2) + self.z.powi(2)
     }
+
+    /// A copy of the vector with a magnitude of 1, see normalise.
+    pub fn normalised(&self) -> Self {
+        *self / self.magnitude()
+    }
+
+    pub fn distance(&self, other: Self) -> T {
+        (other - *self).magnitude()
+    }
+
+    /// The point `t` of the way from this vector to `other`, `t` isn't clamped.
+    pub fn lerp(&self, other: Self, t: T) -> Self {
+        *self + (other - *self) * t
+    }
+
+    /// The vector bounced off a surface with the unit length `normal`.
+    pub fn reflect(&self, normal: Self) -> Self {
+        let two = T::one() + T::one();
+        *self - normal * (two * self.dot(normal))
+    }
+
+    /// The part of the vector pointing along `onto`, which must not be zero.
+    pub fn project(&self, onto: Self) -> Self {
+        onto * (self.dot(onto) / onto.square_magnitude())
+    }
+
+    /// The angle between the two vectors in radians, from 0 to pi.
+    pub fn angle_between(&self, other: Self) -> T {
+        let cos = self.dot(other) / (self.magnitude() * other.magnitude());
+        // rounding can put the cosine of near parallel vectors just outside -1 to 1
+        cos.max(-T::one()).min(T::one()).acos()
+    }
+
+    /// Whether every component is within `epsilon` of the other vector's.
+    pub fn approx_eq(&self, other: Self, epsilon: T) -> bool {
+        (self.x - other.x).abs() <= epsilon
+            && (self.y - other.y).abs() <= epsilon
+            && (self.z - other.z).abs() <= epsilon
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Vector4<T>
+where
+    T: num_traits::Num + Copy,
+{
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+impl<T> Vector4<T>
+where
+    T: num_traits::Num + Copy,
+{
+    pub fn to_raw(&self) -> [T; 4] {
+        [self.x, self.y, self.z, self.w]
+    }
+
+    pub fn dot(&self, other: Vector4<T>) -> T {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    /// The first three components, dropping w.
+    pub fn xyz(&self) -> Vector3<T> {
+        Vector3 {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+        }
+    }
+
+    /// The point the homogeneous coordinates stand for, dividing by w. Directions, with a w of
+    /// 0, are returned as they are.
+    pub fn to_cartesian(&self) -> Vector3<T> {
+        if self.w == T::zero() {
+            return self.xyz();
+        }
+        Vector3 {
+            x: self.x / self.w,
+            y: self.y / self.w,
+            z: self.z / self.w,
+        }
+    }
+}
+
+impl<T> Vector4<T>
+where
+    T: num_traits::Num + Copy + NumCast + Float,
+{
+    pub fn normalise(&mut self) {
+        *self = self.normalised();
+    }
+
+    pub fn magnitude(&self) -> T {
+        self.square_magnitude().sqrt()
+    }
+
+    pub fn square_magnitude(&self) -> T {
+        self.x.powi(2) + self.y.powi(2) + self.z.powi(2) + self.w.powi(2)
+    }
+
+    /// A copy of the vector with a magnitude of 1, see normalise.
+    pub fn normalised(&self) -> Self {
+        *self / self.magnitude()
+    }
+
+    pub fn distance(&self, other: Self) -> T {
+        (other - *self).magnitude()
+    }
+
+    /// The point `t` of the way from this vector to `other`, `t` isn't clamped.
+    pub fn lerp(&self, other: Self, t: T) -> Self {
+        *self + (other - *self) * t
+    }
+
+    /// The vector bounced off a surface with the unit length `normal`.
+    pub fn reflect(&self, normal: Self) -> Self {
+        let two = T::one() + T::one();
+        *self - normal * (two * self.dot(normal))
+    }
+
+    /// The part of the vector pointing along `onto`, which must not be zero.
+    pub fn project(&self, onto: Self) -> Self {
+        onto * (self.dot(onto) / onto.square_magnitude())
+    }
+
+    /// The angle between the two vectors in radians, from 0 to pi.
+    pub fn angle_between(&self, other: Self) -> T {
+        let cos = self.dot(other) / (self.magnitude() * other.magnitude());
+        // rounding can put the cosine of near parallel vectors just outside -1 to 1
+        cos.max(-T::one()).min(T::one()).acos()
+    }
+
+    /// Whether every component is within `epsilon` of the other vector's.
+    pub fn approx_eq(&self, other: Self, epsilon: T) -> bool {
+        (self.x - other.x).abs() <= epsilon
+            && (self.y - other.y).abs() <= epsilon
+            && (self.z - other.z).abs() <= epsilon
+            && (self.w - other.w).abs() <= epsilon
+    }
+}
+
+// The arithmetic operators work component by component, with a scalar on the right of * and /
+// (or the left of * for f32 and f64)
+macro_rules! impl_vector_ops {
+    ($vector:ident { $($component:ident),+ }) => {
+        impl<T> Add for $vector<T>
+        where
+            T: num_traits::Num + Copy,
+        {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self::Output {
+                $vector { $($component: self.$component + rhs.$component),+ }
+            }
+        }
+
+        impl<T> Sub for $vector<T>
+        where
+            T: num_traits::Num + Copy,
+        {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self::Output {
+                $vector { $($component: self.$component - rhs.$component),+ }
+            }
+        }
+
+        impl<T> Mul<T> for $vector<T>
+        where
+            T: num_traits::Num + Copy,
+        {
+            type Output = Self;
+
+            fn mul(self, rhs: T) -> Self::Output {
+                $vector { $($component: self.$component * rhs),+ }
+            }
+        }
+
+        impl<T> Div<T> for $vector<T>
+        where
+            T: num_traits::Num + Copy,
+        {
+            type Output = Self;
+
+            fn div(self, rhs: T) -> Self::Output {
+                $vector { $($component: self.$component / rhs),+ }
+            }
+        }
+
+        impl<T> Neg for $vector<T>
+        where
+            T: num_traits::Num + Copy + Neg<Output = T>,
+        {
+            type Output = Self;
+
+            fn neg(self) -> Self::Output {
+                $vector { $($component: -self.$component),+ }
+            }
+        }
+
+        impl<T> AddAssign for $vector<T>
+        where
+            T: num_traits::Num + Copy,
+        {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl<T> SubAssign for $vector<T>
+        where
+            T: num_traits::Num + Copy,
+        {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl<T> MulAssign<T> for $vector<T>
+        where
+            T: num_traits::Num + Copy,
+        {
+            fn mul_assign(&mut self, rhs: T) {
+                *self = *self * rhs;
+            }
+        }
+
+        impl<T> DivAssign<T> for $vector<T>
+        where
+            T: num_traits::Num + Copy,
+        {
+            fn div_assign(&mut self, rhs: T) {
+                *self = *self / rhs;
+            }
+        }
+
+        impl Mul<$vector<f32>> for f32 {
+            type Output = $vector<f32>;
+
+            fn mul(self, rhs: $vector<f32>) -> Self::Output {
+                rhs * self
+            }
+        }
+
+        impl Mul<$vector<f64>> for f64 {
+            type Output = $vector<f64>;
+
+            fn mul(self, rhs: $vector<f64>) -> Self::Output {
+                rhs * self
+            }
+        }
+    };
+}
+
+impl_vector_ops!(Vector2 { x, y });
+impl_vector_ops!(Vector3 { x, y, z });
+impl_vector_ops!(Vector4 { x, y, z, w });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, MetricSpace};
+    use proptest::prelude::*;
+
+    fn component() -> impl Strategy<Value = f32> {
+        -100.0f32..100.0
+    }
+
+    fn vector2() -> impl Strategy<Value = Vector2<f32>> {
+        (component(), component()).prop_map(|(x, y)| Vector2 { x, y })
+    }
+
+    fn vector3() -> impl Strategy<Value = Vector3<f32>> {
+        (component(), component(), component()).prop_map(|(x, y, z)| Vector3 { x, y, z })
+    }
+
+    fn vector4() -> impl Strategy<Value = Vector4<f32>> {
+        (component(), component(), component(), component()).prop_map(|(x, y, z, w)| Vector4 {
+            x,
+            y,
+            z,
+            w,
+        })
+    }
+
+    // rounding errors grow with the size of the numbers involved
+    fn tolerance(magnitude: f32) -> f32 {
+        1e-5 * (1.0 + magnitude)
+    }
+
+    fn cgmath2<T: num_traits::Num + Copy>(vector: Vector2<T>) -> cgmath::Vector2<T> {
+        cgmath::Vector2::new(vector.x, vector.y)
+    }
+
+    fn cgmath3<T: num_traits::Num + Copy>(vector: Vector3<T>) -> cgmath::Vector3<T> {
+        cgmath::Vector3::new(vector.x, vector.y, vector.z)
+    }
+
+    fn cgmath4(vector: Vector4<f32>) -> cgmath::Vector4<f32> {
+        cgmath::Vector4::new(vector.x, vector.y, vector.z, vector.w)
+    }
+
+    // the arccosine loses precision near 0 and pi, so angles are compared in f64
+    fn wide2(vector: Vector2<f32>) -> Vector2<f64> {
+        Vector2 {
+            x: vector.x as f64,
+            y: vector.y as f64,
+        }
+    }
+
+    fn wide3(vector: Vector3<f32>) -> Vector3<f64> {
+        Vector3 {
+            x: vector.x as f64,
+            y: vector.y as f64,
+            z: vector.z as f64,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn addition_is_commutative(a in vector3(), b in vector3()) {
+            prop_assert_eq!(a + b, b + a);
+        }
+
+        #[test]
+        fn subtracting_itself_gives_zero(a in vector4()) {
+            prop_assert_eq!(a - a, Vector4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 });
+        }
+
+        #[test]
+        fn cross_product_is_perpendicular(a in vector3(), b in vector3()) {
+            let cross = a.cross(b);
+            // the dot product multiplies three components together
+            let scale = a.magnitude().max(b.magnitude()).powi(3);
+            prop_assert!(cross.dot(a).abs() <= tolerance(scale));
+            prop_assert!(cross.dot(b).abs() <= tolerance(scale));
+        }
+
+        #[test]
+        fn dot_with_itself_is_square_magnitude(a in vector2(), b in vector3(), c in vector4()) {
+            prop_assert_eq!(a.dot(a), a.square_magnitude());
+            prop_assert_eq!(b.dot(b), b.square_magnitude());
+            prop_assert_eq!(c.dot(c), c.square_magnitude());
+        }
+
+        #[test]
+        fn lerp_ends_at_both_vectors(a in vector3(), b in vector3()) {
+            prop_assert_eq!(a.lerp(b, 0.0), a);
+            let scale = a.magnitude().max(b.magnitude());
+            prop_assert!(a.lerp(b, 1.0).approx_eq(b, tolerance(scale)));
+        }
+
+        #[test]
+        fn reflecting_twice_gives_the_original(a in vector3(), normal in vector3()) {
+            prop_assume!(normal.magnitude() > 0.1);
+            let normal = normal.normalised();
+            let twice = a.reflect(normal).reflect(normal);
+            prop_assert!(twice.approx_eq(a, tolerance(a.magnitude())));
+        }
+
+        #[test]
+        fn reflecting_in_2d_twice_gives_the_original(a in vector2(), normal in vector2()) {
+            prop_assume!(normal.magnitude() > 0.1);
+            let normal = normal.normalised();
+            let twice = a.reflect(normal).reflect(normal);
+            prop_assert!(twice.approx_eq(a, tolerance(a.magnitude())));
+        }
+
+        #[test]
+        fn projecting_onto_itself_gives_itself(a in vector3()) {
+            prop_assume!(a.magnitude() > 0.1);
+            prop_assert!(a.project(a).approx_eq(a, tolerance(a.magnitude())));
+        }
+
+        #[test]
+        fn homogeneous_coordinates_round_trip(a in vector3(), w in 0.5f32..4.0) {
+            prop_assert_eq!(a.to_point().w, 1.0);
+            prop_assert_eq!(a.to_direction().w, 0.0);
+            prop_assert_eq!(a.to_point().to_cartesian(), a);
+            prop_assert_eq!(a.to_direction().to_cartesian(), a);
+            // any multiple of a point's homogeneous coordinates is the same point
+            let scaled = Vector4 { x: a.x * w, y: a.y * w, z: a.z * w, w };
+            prop_assert!(scaled.to_cartesian().approx_eq(a, tolerance(a.magnitude())));
+        }
+
+        #[test]
+        fn operators_match_cgmath(a in vector3(), b in vector3(), scalar in component()) {
+            prop_assert_eq!(cgmath3(a + b), cgmath3(a) + cgmath3(b));
+            prop_assert_eq!(cgmath3(a - b), cgmath3(a) - cgmath3(b));
+            prop_assert_eq!(cgmath3(a * scalar), cgmath3(a) * scalar);
+            prop_assert_eq!(cgmath3(scalar * a), scalar * cgmath3(a));
+            prop_assert_eq!(cgmath3(-a), -cgmath3(a));
+        }
+
+        #[test]
+        fn operators_match_cgmath_in_2d_and_4d(
+            a in vector2(),
+            b in vector2(),
+            c in vector4(),
+            d in vector4(),
+            scalar in component(),
+        ) {
+            prop_assert_eq!(cgmath2(a + b), cgmath2(a) + cgmath2(b));
+            prop_assert_eq!(cgmath2(a - b), cgmath2(a) - cgmath2(b));
+            prop_assert_eq!(cgmath2(a * scalar), cgmath2(a) * scalar);
+            prop_assert_eq!(cgmath4(c + d), cgmath4(c) + cgmath4(d));
+            prop_assert_eq!(cgmath4(c - d), cgmath4(c) - cgmath4(d));
+            prop_assert_eq!(cgmath4(c * scalar), cgmath4(c) * scalar);
+        }
+
+        #[test]
+        fn assignment_operators_match_cgmath(a in vector3(), b in vector3(), scalar in component()) {
+            prop_assume!(scalar.abs() > 0.1);
+            let (mut ours, mut theirs) = (a, cgmath3(a));
+            ours += b;
+            theirs += cgmath3(b);
+            prop_assert_eq!(cgmath3(ours), theirs);
+            ours -= a;
+            theirs -= cgmath3(a);
+            prop_assert_eq!(cgmath3(ours), theirs);
+            ours *= scalar;
+            theirs *= scalar;
+            prop_assert_eq!(cgmath3(ours), theirs);
+            ours /= scalar;
+            theirs /= scalar;
+            prop_assert_eq!(cgmath3(ours), theirs);
+        }
+
+        #[test]
+        fn distance_matches_cgmath(a in vector2(), b in vector2(), c in vector3(), d in vector3()) {
+            let scale = a.magnitude().max(b.magnitude());
+            prop_assert!((a.distance(b) - cgmath2(a).distance(cgmath2(b))).abs() <= tolerance(scale));
+            let scale = c.magnitude().max(d.magnitude());
+            prop_assert!((c.distance(d) - cgmath3(c).distance(cgmath3(d))).abs() <= tolerance(scale));
+            prop_assert_eq!(a.distance(b), b.distance(a));
+        }
+
+        #[test]
+        fn angle_between_matches_cgmath(a in vector2(), b in vector2(), c in vector3(), d in vector3()) {
+            prop_assume!(a.magnitude() > 0.1 && b.magnitude() > 0.1);
+            prop_assume!(c.magnitude() > 0.1 && d.magnitude() > 0.1);
+            // cgmath's 2D angle is signed, from a round to b
+            let (a, b) = (wide2(a), wide2(b));
+            let expected = cgmath2(a).angle(cgmath2(b)).0.abs();
+            prop_assert!((a.angle_between(b) - expected).abs() <= 1e-6);
+            let (c, d) = (wide3(c), wide3(d));
+            let expected = cgmath3(c).angle(cgmath3(d)).0;
+            prop_assert!((c.angle_between(d) - expected).abs() <= 1e-6);
+        }
+    }
+
+    #[test]
+    fn angle_between_parallel_vectors() {
+        let a = Vector3 {
+            x: 1.0f32,
+            y: 2.0,
+            z: 3.0,
+        };
+        // rounding must not take the cosine outside -1 to 1, which would give NaN
+        assert_eq!(a.angle_between(a * 3.0), 0.0);
+        // the arccosine is steep near -1, so a rounding error in the cosine moves it noticeably
+        assert!((a.angle_between(-a) - std::f32::consts::PI).abs() < 1e-3);
+        let right = Vector2 { x: 1.0f32, y: 0.0 }.angle_between(Vector2 { x: 0.0, y: -2.0 });
+        assert!((right - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+    }
 }
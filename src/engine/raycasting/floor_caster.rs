@@ -0,0 +1,218 @@
+use crate::engine::primitives::vector::Vector2;
+use crate::engine::raycasting::ray_caster::PlayerPose;
+use crate::engine::world::map::Map;
+use std::collections::BTreeMap;
+
+// Floor and ceiling casting is the second pass of a textured ray caster. Rather than casting a
+// ray per column, every pixel row below the horizon is a horizontal line on the floor at a fixed
+// distance from the player, so the world position of each pixel can be interpolated along it.
+// Rows above the horizon mirror the rows below, hitting the ceiling at the same positions.
+//
+// This is the CPU reference implementation, floor.wgsl does the same per fragment on the GPU.
+// Both sample pixel centres, (column + 0.5, row + 0.5), so their results line up.
+
+/// Which tile and texel a floor or ceiling pixel shows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FloorSample {
+    pub map_x: i32,
+    pub map_y: i32,
+    pub ceiling: bool,
+    // texture id of the floor or ceiling of the tile
+    pub texture: u32,
+    // position within the tile, in [0, 1)
+    pub u: f32,
+    pub v: f32,
+    pub distance: f32,
+}
+
+/// The world position and distance of the floor (or ceiling) seen through a pixel.
+/// Returns None for pixels on the horizon, which never reach the floor.
+pub fn floor_point(
+    pose: &PlayerPose,
+    column: u32,
+    row: u32,
+    screen_width: u32,
+    screen_height: u32,
+) -> Option<(Vector2<f32>, f32)> {
+    let horizon = screen_height as f32 / 2.0;
+    // distance of the pixel centre from the horizon, the camera sits half way up the walls
+    let offset = (row as f32 + 0.5 - horizon).abs();
+    if offset == 0.0 {
+        return None;
+    }
+    let distance = horizon / offset;
+    // rays through the left and right edges of the screen
+    let left = pose.direction - pose.plane;
+    let right = pose.direction + pose.plane;
+    let along = (column as f32 + 0.5) / screen_width as f32;
+    let point = pose.position + left.lerp(right, along) * distance;
+    Some((point, distance))
+}
+
+/// Work out what a single pixel of the floor or ceiling shows.
+/// Returns None on the horizon and where the floor lies outside the map.
+pub fn floor_sample(
+    map: &Map,
+    pose: &PlayerPose,
+    column: u32,
+    row: u32,
+    screen_width: u32,
+    screen_height: u32,
+) -> Option<FloorSample> {
+    let (point, distance) = floor_point(pose, column, row, screen_width, screen_height)?;
+    let map_x = point.x.floor() as i32;
+    let map_y = point.y.floor() as i32;
+    let tile = map.tile(map_x, map_y)?;
+    let ceiling = (row as f32 + 0.5) < screen_height as f32 / 2.0;
+    Some(FloorSample {
+        map_x,
+        map_y,
+        ceiling,
+        texture: if ceiling { tile.ceiling } else { tile.floor },
+        u: point.x - map_x as f32,
+        v: point.y - map_y as f32,
+        distance,
+    })
+}
+
+/// Render the floor and ceiling of a whole frame on the CPU, using nearest texel sampling and
+/// the same distance shading as the walls. Pixels with no texture are left transparent.
+/// `textures` maps texture ids to their images.
+pub fn render_floor_and_ceiling(
+    map: &Map,
+    pose: &PlayerPose,
+    textures: &BTreeMap<u32, image::RgbaImage>,
+    screen_width: u32,
+    screen_height: u32,
+    falloff: f32,
+) -> image::RgbaImage {
+    let mut frame = image::RgbaImage::new(screen_width, screen_height);
+    for row in 0..screen_height {
+        for column in 0..screen_width {
+            let Some(sample) = floor_sample(map, pose, column, row, screen_width, screen_height)
+            else {
+                continue;
+            };
+            let Some(texture) = textures.get(&sample.texture) else {
+                continue;
+            };
+            let texel_x = ((sample.u * texture.width() as f32) as u32).min(texture.width() - 1);
+            let texel_y = ((sample.v * texture.height() as f32) as u32).min(texture.height() - 1);
+            let texel = texture.get_pixel(texel_x, texel_y);
+            let shade = 1.0 / (1.0 + sample.distance * falloff);
+            frame.put_pixel(
+                column,
+                row,
+                image::Rgba([
+                    shade_srgb(texel[0], shade),
+                    shade_srgb(texel[1], shade),
+                    shade_srgb(texel[2], shade),
+                    texel[3],
+                ]),
+            );
+        }
+    }
+    frame
+}
+
+// Textures are stored as sRGB, but the GPU shades in linear space, so do the same here
+fn shade_srgb(value: u8, shade: f32) -> u8 {
+    let value = value as f32 / 255.0;
+    let linear = if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    };
+    let linear = linear * shade;
+    let encoded = if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    // a 3x3 room with the player in the middle, facing +x with a 90 degree field of view
+    const ROOM: &str = "\
+[textures]
+1 = floor.png
+2 = ceiling.png
+
+[legend]
+. floor=1 ceiling=2
+P floor=1 ceiling=2 spawn=0
+
+[map]
+...
+.P.
+...
+";
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const YELLOW: Rgba<u8> = Rgba([255, 255, 0, 255]);
+    const TRANSPARENT: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    fn pose() -> PlayerPose {
+        PlayerPose::new(Vector2 { x: 1.5, y: 1.5 }, 0.0, 90.0)
+    }
+
+    // the floor is a 2x2 texture, one colour per quarter of the tile, the ceiling is plain
+    fn textures() -> BTreeMap<u32, RgbaImage> {
+        let mut floor = RgbaImage::new(2, 2);
+        floor.put_pixel(0, 0, RED);
+        floor.put_pixel(1, 0, GREEN);
+        floor.put_pixel(0, 1, BLUE);
+        floor.put_pixel(1, 1, WHITE);
+        BTreeMap::from([(1, floor), (2, RgbaImage::from_pixel(1, 1, YELLOW))])
+    }
+
+    fn render(falloff: f32) -> RgbaImage {
+        let map = Map::from_ascii(ROOM).unwrap();
+        render_floor_and_ceiling(&map, &pose(), &textures(), 4, 4, falloff)
+    }
+
+    #[test]
+    fn floor_point_distance_follows_row() {
+        // on a 4 pixel high screen the horizon is at 2, the bottom row is 1.5 pixels below it
+        let (point, distance) = floor_point(&pose(), 1, 3, 4, 4).unwrap();
+        assert!((distance - 4.0 / 3.0).abs() < 1e-5);
+        assert!((point.x - (1.5 + 4.0 / 3.0)).abs() < 1e-5);
+        assert!((point.y - (1.5 - 1.0 / 3.0)).abs() < 1e-5);
+        // the row above mirrors it onto the ceiling
+        let (mirrored, _) = floor_point(&pose(), 1, 0, 4, 4).unwrap();
+        assert!((mirrored - point).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn floor_and_ceiling_texels() {
+        let frame = render(0.0);
+        // the bottom row lands at u 0.83 in the tile ahead, v 0.17 left of centre and 0.83 right
+        assert_eq!(*frame.get_pixel(1, 3), GREEN);
+        assert_eq!(*frame.get_pixel(2, 3), WHITE);
+        assert_eq!(*frame.get_pixel(1, 0), YELLOW);
+        assert_eq!(*frame.get_pixel(2, 0), YELLOW);
+    }
+
+    #[test]
+    fn floor_outside_the_map_is_transparent() {
+        // the rows next to the horizon are 4 units away, well past the walls of the room
+        let frame = render(0.0);
+        assert_eq!(*frame.get_pixel(0, 2), TRANSPARENT);
+        assert_eq!(*frame.get_pixel(0, 1), TRANSPARENT);
+    }
+
+    #[test]
+    fn floor_is_shaded_in_linear_space() {
+        // 1 / (1 + 4/3) of full brightness in linear space is 175 once encoded back to sRGB
+        let frame = render(1.0);
+        assert_eq!(*frame.get_pixel(1, 3), Rgba([0, 175, 0, 255]));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::engine::config::DepthConfig;
+use crate::engine::pipeline::{PipelineBuilder, PipelineCache, PipelineHandle};
+use crate::engine::raycasting::ray_caster::PlayerPose;
+use crate::engine::raycasting::wall_renderer::WallShading;
+use crate::engine::texture::{Texture2DArray, TextureStore};
+use crate::engine::world::map::Map;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use wgpu::util::DeviceExt;
+
+// Draws the floor and ceiling on the GPU with a single full screen triangle, see floor.wgsl.
+// The map's floor and ceiling textures are copied into the layers of a texture array, so the
+// shader can look up the tile under each fragment. Only textures used on a floor or ceiling are
+// copied, the images come from the TextureStore the walls and sprites are drawn from. Walls are drawn over the top afterwards.
+
+// Marks tiles without a floor or ceiling texture, matches NO_TEXTURE in floor.wgsl
+const NO_TEXTURE: u32 = u32::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct FloorUniform {
+    position: [f32; 2],
+    direction: [f32; 2],
+    plane: [f32; 2],
+    screen_size: [f32; 2],
+    map_size: [u32; 2],
+    falloff: f32,
+    // rounds the size up to a multiple of 16 bytes, as uniforms must be
+    padding: f32,
+}
+
+// Pod is implemented by hand as the derive's padding check is flagged as dead code. Every field
+// is made of 4 byte numbers, so the struct has no padding as long as it is the sum of its fields.
+const _: () = assert!(std::mem::size_of::<FloorUniform>() == 48);
+unsafe impl bytemuck::Zeroable for FloorUniform {}
+unsafe impl bytemuck::Pod for FloorUniform {}
+
+pub struct FloorRenderer {
+    pipeline: PipelineHandle,
+    uniform_buffer: wgpu::Buffer,
+    tile_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    textures: Texture2DArray,
+    // texture id to texture array layer
+    layers: BTreeMap<u32, u32>,
+    map_size: [u32; 2],
+    screen_width: u32,
+    screen_height: u32,
+    falloff: f32,
+}
+
+impl FloorRenderer {
+    pub fn new(
+        map: &Map,
+        textures: &TextureStore,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        depth: Option<&DepthConfig>,
+        pipelines: &mut PipelineCache,
+    ) -> Result<Self> {
+        let used: BTreeSet<u32> = map
+            .tiles()
+            .iter()
+            .flat_map(|tile| [tile.floor, tile.ceiling])
+            .collect();
+        let mut images = Vec::new();
+        let mut layers = BTreeMap::new();
+        for (id, texture) in textures.iter().filter(|(id, _)| used.contains(id)) {
+            layers.insert(id, images.len() as u32);
+            images.push(texture.rgba_buffer().clone());
+        }
+        let textures = Texture2DArray::new(&images, queue, device);
+
+        let tile_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Floor tile buffer"),
+            contents: bytemuck::cast_slice(&tile_layers(map, &layers)),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Floor uniform buffer"),
+            size: std::mem::size_of::<FloorUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Floor bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Floor bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: tile_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(textures.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(textures.sampler()),
+                },
+            ],
+        });
+
+        // the floor and ceiling are drawn by a single full screen triangle
+        let pipeline = pipelines.get(
+            device,
+            &PipelineBuilder::new("Floor Pipeline", "floor", config.format)
+                .bind_group_layout(&bind_group_layout)
+                .depth(depth),
+        )?;
+
+        Ok(Self {
+            pipeline,
+            uniform_buffer,
+            tile_buffer,
+            bind_group,
+            textures,
+            layers,
+            map_size: [map.width(), map.height()],
+            screen_width: config.width,
+            screen_height: config.height,
+            falloff: WallShading::default().falloff,
+        })
+    }
+
+    /// Upload the player's pose for the next frame.
+    pub fn update(&self, pose: &PlayerPose, queue: &wgpu::Queue) {
+        let uniform = FloorUniform {
+            position: pose.position.to_raw(),
+            direction: pose.direction.to_raw(),
+            plane: pose.plane.to_raw(),
+            screen_size: [self.screen_width as f32, self.screen_height as f32],
+            map_size: self.map_size,
+            falloff: self.falloff,
+            padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Re-upload the floor and ceiling textures of every tile, the map must be the same size.
+    pub fn update_tiles(&self, map: &Map, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.tile_buffer,
+            0,
+            bytemuck::cast_slice(&tile_layers(map, &self.layers)),
+        );
+    }
+
+    /// Pick up the pipeline again if its shader has been reloaded, see PipelineHandle::refresh.
+    pub fn refresh_pipeline(&mut self, pipelines: &PipelineCache) {
+        self.pipeline.refresh(pipelines);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(self.pipeline.pipeline());
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Change the size of the screen the floor and ceiling are cast for, takes effect from the
+    /// next update.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.screen_width = width;
+        self.screen_height = height;
+    }
+
+    pub fn falloff(&self) -> f32 {
+        self.falloff
+    }
+
+    /// Distance shading, see WallShading
+    pub fn set_falloff(&mut self, falloff: f32) {
+        self.falloff = falloff;
+    }
+
+    pub fn textures(&self) -> &Texture2DArray {
+        &self.textures
+    }
+}
+
+fn tile_layers(map: &Map, layers: &BTreeMap<u32, u32>) -> Vec<[u32; 2]> {
+    let layer = |id: u32| layers.get(&id).copied().unwrap_or(NO_TEXTURE);
+    map.tiles()
+        .iter()
+        .map(|tile| [layer(tile.floor), layer(tile.ceiling)])
+        .collect()
+}
@@ -0,0 +1,6 @@
+pub mod floor_caster;
+pub mod floor_renderer;
+pub mod ray_caster;
+pub mod sprite;
+pub mod sprite_renderer;
+pub mod wall_renderer;
@@ -0,0 +1,562 @@
+use crate::engine::primitives::vector::Vector2;
+use crate::engine::traits::tile_map::TileMap;
+
+// The ray caster runs entirely on the CPU. For every column of the screen a ray is cast from the
+// player through the camera plane, and stepped through the tile grid one cell boundary at a time
+// (DDA, see https://lodev.org/cgtutor/raycasting.html) until it hits a solid tile.
+// Tiles can also hold walls that only fill part of the tile, such as doors, which are checked
+// as the ray passes through the tile.
+// The resulting hits describe one vertical wall strip per column, which are drawn using Batch2D.
+
+/// Where the player is and which way they are looking.
+/// `plane` is perpendicular to `direction`, its length relative to `direction` sets the field of view.
+#[derive(Clone, Copy)]
+pub struct PlayerPose {
+    pub position: Vector2<f32>,
+    pub direction: Vector2<f32>,
+    pub plane: Vector2<f32>,
+}
+
+impl PlayerPose {
+    /// angle and fov are in degrees, an angle of 0 faces along +x.
+    /// +y points down the map (increasing rows), so angles increase clockwise when viewed top down.
+    pub fn new(position: Vector2<f32>, angle: f32, fov: f32) -> Self {
+        let angle = angle.to_radians();
+        let direction = Vector2 {
+            x: angle.cos(),
+            y: angle.sin(),
+        };
+        let plane_length = (fov.to_radians() / 2.0).tan();
+        let plane = Vector2 {
+            x: -direction.y * plane_length,
+            y: direction.x * plane_length,
+        };
+        Self {
+            position,
+            direction,
+            plane,
+        }
+    }
+
+    /// The direction of the ray through `column` of a screen `screen_width` columns wide.
+    pub fn ray_direction(&self, column: u32, screen_width: u32) -> Vector2<f32> {
+        // -1 on the left edge of the screen, 1 on the right
+        let camera_x = 2.0 * (column as f32 + 0.5) / screen_width as f32 - 1.0;
+        self.direction + self.plane * camera_x
+    }
+}
+
+/// The face of the tile which a ray hit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WallSide {
+    // faces -y
+    North,
+    // faces +y
+    South,
+    // faces +x
+    East,
+    // faces -x
+    West,
+}
+
+impl WallSide {
+    /// Whether the face lies along a vertical grid line (constant x).
+    pub fn is_x_side(&self) -> bool {
+        matches!(self, WallSide::East | WallSide::West)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    // Distance to the wall measured perpendicular to the camera plane, this avoids fisheye distortion
+    pub distance: f32,
+    pub side: WallSide,
+    pub tile: u32,
+    pub map_x: i32,
+    pub map_y: i32,
+    // Horizontal texture coordinate of the hit along the wall face, in [0, 1]
+    pub texture_u: f32,
+}
+
+impl RayHit {
+    /// Height in pixels of the wall strip for this hit, before clipping to the screen.
+    pub fn line_height(&self, screen_height: u32) -> f32 {
+        screen_height as f32 / self.distance
+    }
+}
+
+pub struct RayCaster {
+    max_distance: f32,
+}
+
+impl RayCaster {
+    /// Rays travelling further than `max_distance` tiles without hitting a wall are discarded.
+    pub fn new(max_distance: f32) -> Self {
+        Self { max_distance }
+    }
+
+    pub fn max_distance(&self) -> f32 {
+        self.max_distance
+    }
+
+    pub fn set_max_distance(&mut self, max_distance: f32) {
+        self.max_distance = max_distance;
+    }
+
+    /// Cast one ray per screen column, from left to right.
+    pub fn cast(
+        &self,
+        map: &impl TileMap,
+        pose: &PlayerPose,
+        screen_width: u32,
+    ) -> Vec<Option<RayHit>> {
+        (0..screen_width)
+            .map(|column| self.cast_column(map, pose, column, screen_width))
+            .collect()
+    }
+
+    pub fn cast_column(
+        &self,
+        map: &impl TileMap,
+        pose: &PlayerPose,
+        column: u32,
+        screen_width: u32,
+    ) -> Option<RayHit> {
+        self.cast_ray(map, pose.position, pose.ray_direction(column, screen_width))
+    }
+
+    /// Step a ray from `origin` along `direction` until it hits a solid tile, or the wall
+    /// within a tile such as a door.
+    /// Returns None if the ray leaves the map or travels further than the max distance.
+    /// The returned distance is in multiples of the length of `direction`.
+    pub fn cast_ray(
+        &self,
+        map: &impl TileMap,
+        origin: Vector2<f32>,
+        direction: Vector2<f32>,
+    ) -> Option<RayHit> {
+        let walk = GridWalk::new(origin, direction);
+        // the ray may start inside a tile with a door in it, solid tiles it starts in are ignored
+        let (start_x, start_y) = walk.tile();
+        if let Some(hit) = partial_hit(map, origin, direction, start_x, start_y, 0.0, walk.exit()) {
+            return Some(hit);
+        }
+        for step in walk {
+            if step.distance > self.max_distance || !map.contains(step.map_x, step.map_y) {
+                return None;
+            }
+            if map.is_solid(step.map_x, step.map_y) {
+                return Some(RayHit {
+                    distance: step.distance,
+                    side: step.side,
+                    tile: map.wall_texture(step.map_x, step.map_y),
+                    map_x: step.map_x,
+                    map_y: step.map_y,
+                    texture_u: texture_u(origin, direction, step.distance, step.side),
+                });
+            }
+            if let Some(hit) = partial_hit(
+                map,
+                origin,
+                direction,
+                step.map_x,
+                step.map_y,
+                step.distance,
+                step.exit,
+            ) {
+                return Some(hit);
+            }
+        }
+        None
+    }
+}
+
+/// One tile crossed by a GridWalk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridStep {
+    pub map_x: i32,
+    pub map_y: i32,
+    // distance along the ray where it enters and leaves the tile
+    pub distance: f32,
+    pub exit: f32,
+    // the face of the tile the ray entered through
+    pub side: WallSide,
+}
+
+/// Steps a ray through the tile grid one cell boundary at a time (DDA), yielding every tile it
+/// enters after the one it starts in. The walk never ends on its own, so callers must stop it.
+pub struct GridWalk {
+    map_x: i32,
+    map_y: i32,
+    step_x: i32,
+    step_y: i32,
+    // distance along the ray between two vertical / horizontal grid lines
+    delta_x: f32,
+    delta_y: f32,
+    // distance along the ray to the next vertical / horizontal grid line
+    side_x: f32,
+    side_y: f32,
+}
+
+impl GridWalk {
+    pub fn new(origin: Vector2<f32>, direction: Vector2<f32>) -> Self {
+        let map_x = origin.x.floor() as i32;
+        let map_y = origin.y.floor() as i32;
+        let delta_x = if direction.x == 0.0 {
+            f32::INFINITY
+        } else {
+            (1.0 / direction.x).abs()
+        };
+        let delta_y = if direction.y == 0.0 {
+            f32::INFINITY
+        } else {
+            (1.0 / direction.y).abs()
+        };
+        let (step_x, side_x) = if direction.x < 0.0 {
+            (-1, (origin.x - map_x as f32) * delta_x)
+        } else {
+            (1, (map_x as f32 + 1.0 - origin.x) * delta_x)
+        };
+        let (step_y, side_y) = if direction.y < 0.0 {
+            (-1, (origin.y - map_y as f32) * delta_y)
+        } else {
+            (1, (map_y as f32 + 1.0 - origin.y) * delta_y)
+        };
+        Self {
+            map_x,
+            map_y,
+            step_x,
+            step_y,
+            delta_x,
+            delta_y,
+            side_x,
+            side_y,
+        }
+    }
+
+    /// The tile the walk is currently in.
+    pub fn tile(&self) -> (i32, i32) {
+        (self.map_x, self.map_y)
+    }
+
+    // distance along the ray where it leaves the current tile
+    fn exit(&self) -> f32 {
+        self.side_x.min(self.side_y)
+    }
+}
+
+impl Iterator for GridWalk {
+    type Item = GridStep;
+
+    fn next(&mut self) -> Option<GridStep> {
+        let (distance, side) = if self.side_x < self.side_y {
+            self.map_x += self.step_x;
+            self.side_x += self.delta_x;
+            let side = if self.step_x > 0 {
+                WallSide::West
+            } else {
+                WallSide::East
+            };
+            (self.side_x - self.delta_x, side)
+        } else {
+            self.map_y += self.step_y;
+            self.side_y += self.delta_y;
+            let side = if self.step_y > 0 {
+                WallSide::North
+            } else {
+                WallSide::South
+            };
+            (self.side_y - self.delta_y, side)
+        };
+        Some(GridStep {
+            map_x: self.map_x,
+            map_y: self.map_y,
+            distance,
+            exit: self.exit(),
+            side,
+        })
+    }
+}
+
+// Check the ray against the partial wall of a tile, between where it enters and leaves the tile.
+// The bounds are tested as a box, the face the ray enters through is the one it hits.
+fn partial_hit(
+    map: &impl TileMap,
+    origin: Vector2<f32>,
+    direction: Vector2<f32>,
+    map_x: i32,
+    map_y: i32,
+    enter: f32,
+    exit: f32,
+) -> Option<RayHit> {
+    let bounds = map.partial_wall(map_x, map_y)?;
+    let (enter_x, exit_x) = slab(origin.x, direction.x, bounds.min.x, bounds.max.x)?;
+    let (enter_y, exit_y) = slab(origin.y, direction.y, bounds.min.y, bounds.max.y)?;
+    let distance = enter_x.max(enter_y);
+    if distance > exit_x.min(exit_y) || distance < enter || distance > exit {
+        return None;
+    }
+    let (side, along, start) = if enter_x >= enter_y {
+        let side = if direction.x > 0.0 {
+            WallSide::West
+        } else {
+            WallSide::East
+        };
+        (side, origin.y + distance * direction.y, bounds.min.y)
+    } else {
+        let side = if direction.y > 0.0 {
+            WallSide::North
+        } else {
+            WallSide::South
+        };
+        (side, origin.x + distance * direction.x, bounds.min.x)
+    };
+    // textures are fixed to the bounds so they move with doors and push walls
+    Some(RayHit {
+        distance,
+        side,
+        tile: map.wall_texture(map_x, map_y),
+        map_x,
+        map_y,
+        texture_u: flip_u((along - start).clamp(0.0, 1.0), side),
+    })
+}
+
+// Distances along the ray where it enters and leaves the space between min and max on one axis
+fn slab(origin: f32, direction: f32, min: f32, max: f32) -> Option<(f32, f32)> {
+    if direction == 0.0 {
+        return if origin >= min && origin <= max {
+            Some((f32::NEG_INFINITY, f32::INFINITY))
+        } else {
+            None
+        };
+    }
+    let a = (min - origin) / direction;
+    let b = (max - origin) / direction;
+    Some((a.min(b), a.max(b)))
+}
+
+// Where along the wall face the ray hit. Faces are flipped where needed so that textures
+// read left to right when looking at any face.
+pub(crate) fn texture_u(
+    origin: Vector2<f32>,
+    direction: Vector2<f32>,
+    distance: f32,
+    side: WallSide,
+) -> f32 {
+    let wall_position = if side.is_x_side() {
+        origin.y + distance * direction.y
+    } else {
+        origin.x + distance * direction.x
+    };
+    flip_u(wall_position - wall_position.floor(), side)
+}
+
+fn flip_u(u: f32, side: WallSide) -> f32 {
+    match side {
+        WallSide::East | WallSide::North => 1.0 - u,
+        WallSide::West | WallSide::South => u,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::traits::tile_map::WallBounds;
+
+    // '#' and '2' are walls with textures 1 and 2, 'D' is a door across the middle of its tile
+    // (along y) slid `door_open` of the way open towards +y
+    struct TestMap {
+        rows: Vec<&'static str>,
+        door_open: f32,
+    }
+
+    impl TestMap {
+        fn new(rows: Vec<&'static str>) -> Self {
+            Self {
+                rows,
+                door_open: 0.0,
+            }
+        }
+
+        fn tile(&self, x: i32, y: i32) -> u8 {
+            self.rows[y as usize].as_bytes()[x as usize]
+        }
+    }
+
+    impl TileMap for TestMap {
+        fn contains(&self, x: i32, y: i32) -> bool {
+            y >= 0 && (y as usize) < self.rows.len() && x >= 0 && (x as usize) < self.rows[0].len()
+        }
+
+        fn is_solid(&self, x: i32, y: i32) -> bool {
+            matches!(self.tile(x, y), b'#' | b'2')
+        }
+
+        fn wall_texture(&self, x: i32, y: i32) -> u32 {
+            match self.tile(x, y) {
+                b'#' => 1,
+                b'2' => 2,
+                b'D' => 3,
+                _ => 0,
+            }
+        }
+
+        fn partial_wall(&self, x: i32, y: i32) -> Option<WallBounds> {
+            if self.tile(x, y) != b'D' {
+                return None;
+            }
+            let (x, y) = (x as f32, y as f32);
+            Some(WallBounds {
+                min: Vector2 {
+                    x: x + 0.5,
+                    y: y + self.door_open,
+                },
+                max: Vector2 {
+                    x: x + 0.5,
+                    y: y + 1.0,
+                },
+            })
+        }
+    }
+
+    fn room() -> TestMap {
+        TestMap::new(vec!["#####", "#...#", "#...2", "#...#", "#####"])
+    }
+
+    fn cast(map: &TestMap, origin: (f32, f32), direction: (f32, f32)) -> Option<RayHit> {
+        let origin = Vector2 {
+            x: origin.0,
+            y: origin.1,
+        };
+        let direction = Vector2 {
+            x: direction.0,
+            y: direction.1,
+        };
+        RayCaster::new(64.0).cast_ray(map, origin, direction)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {}, found {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_the_facing_side() {
+        let map = room();
+        let east = cast(&map, (1.5, 2.25), (1.0, 0.0)).unwrap();
+        assert_close(east.distance, 2.5);
+        assert_eq!(east.side, WallSide::West);
+        assert_eq!((east.map_x, east.map_y, east.tile), (4, 2, 2));
+
+        let west = cast(&map, (2.5, 2.25), (-1.0, 0.0)).unwrap();
+        assert_close(west.distance, 1.5);
+        assert_eq!(west.side, WallSide::East);
+        assert_eq!((west.map_x, west.map_y, west.tile), (0, 2, 1));
+
+        let south = cast(&map, (2.25, 1.5), (0.0, 1.0)).unwrap();
+        assert_close(south.distance, 2.5);
+        assert_eq!(south.side, WallSide::North);
+        assert_eq!((south.map_x, south.map_y), (2, 4));
+
+        let north = cast(&map, (2.25, 1.5), (0.0, -1.0)).unwrap();
+        assert_close(north.distance, 0.5);
+        assert_eq!(north.side, WallSide::South);
+        assert_eq!((north.map_x, north.map_y), (2, 0));
+    }
+
+    #[test]
+    fn diagonal_rays_hit_the_first_wall_crossed() {
+        let map = room();
+        // crosses x = 2, y = 2 and x = 3 before entering the wall at x = 4, y = 2.9
+        let hit = cast(&map, (1.2, 1.5), (1.0, 0.5)).unwrap();
+        assert_close(hit.distance, 2.8);
+        assert_eq!(hit.side, WallSide::West);
+        assert_eq!((hit.map_x, hit.map_y), (4, 2));
+        assert_close(hit.texture_u, 0.9);
+
+        // the same ray mirrored along the diagonal hits the bottom wall instead
+        let hit = cast(&map, (1.5, 1.2), (0.5, 1.0)).unwrap();
+        assert_close(hit.distance, 2.8);
+        assert_eq!(hit.side, WallSide::North);
+        assert_eq!((hit.map_x, hit.map_y), (2, 4));
+        assert_close(hit.texture_u, 0.1);
+    }
+
+    #[test]
+    fn texture_u_reads_left_to_right_on_every_side() {
+        let map = room();
+        // x sides measure along y, flipped on east faces
+        assert_close(cast(&map, (1.5, 2.25), (1.0, 0.0)).unwrap().texture_u, 0.25);
+        assert_close(
+            cast(&map, (2.5, 2.25), (-1.0, 0.0)).unwrap().texture_u,
+            0.75,
+        );
+        // y sides measure along x, flipped on north faces
+        assert_close(cast(&map, (2.25, 1.5), (0.0, 1.0)).unwrap().texture_u, 0.75);
+        assert_close(
+            cast(&map, (2.25, 1.5), (0.0, -1.0)).unwrap().texture_u,
+            0.25,
+        );
+    }
+
+    #[test]
+    fn rays_leaving_the_map_or_going_too_far_miss() {
+        let map = TestMap::new(vec!["#####", "#....", "#####"]);
+        assert_eq!(cast(&map, (1.5, 1.5), (1.0, 0.0)), None);
+        assert!(cast(&map, (3.5, 1.5), (-1.0, 0.0)).is_some());
+
+        let short = RayCaster::new(2.0);
+        let origin = Vector2 { x: 1.5, y: 2.5 };
+        let direction = Vector2 { x: 1.0, y: 0.0 };
+        assert_eq!(short.cast_ray(&room(), origin, direction), None);
+    }
+
+    #[test]
+    fn closed_doors_stop_rays_in_the_middle_of_their_tile() {
+        let map = TestMap::new(vec!["#####", "#..D#", "#####"]);
+        let hit = cast(&map, (1.5, 1.25), (1.0, 0.0)).unwrap();
+        assert_close(hit.distance, 2.0);
+        assert_eq!(hit.side, WallSide::West);
+        assert_eq!((hit.map_x, hit.map_y, hit.tile), (3, 1, 3));
+        // measured from the edge of the door rather than the tile
+        assert_close(hit.texture_u, 0.25);
+
+        // starting inside the door's tile, in front of the door
+        let hit = cast(&map, (3.25, 1.5), (1.0, 0.0)).unwrap();
+        assert_close(hit.distance, 0.25);
+        assert_eq!(hit.map_x, 3);
+    }
+
+    #[test]
+    fn rays_pass_through_the_open_part_of_doors() {
+        let mut map = TestMap::new(vec!["#####", "#..D#", "#####"]);
+        map.door_open = 0.5;
+        let past = cast(&map, (1.5, 1.25), (1.0, 0.0)).unwrap();
+        assert_close(past.distance, 2.5);
+        assert_eq!((past.map_x, past.tile), (4, 1));
+
+        let door = cast(&map, (1.5, 1.75), (1.0, 0.0)).unwrap();
+        assert_close(door.distance, 2.0);
+        assert_eq!(door.map_x, 3);
+        // the texture moves with the door
+        assert_close(door.texture_u, 0.25);
+    }
+
+    #[test]
+    fn distances_are_perpendicular_to_the_camera_plane() {
+        // every column sees the flat wall in front at the same distance, without fisheye
+        let pose = PlayerPose::new(Vector2 { x: 1.5, y: 2.5 }, 0.0, 60.0);
+        let hits = RayCaster::new(64.0).cast(&room(), &pose, 16);
+        assert_eq!(hits.len(), 16);
+        for hit in hits {
+            let hit = hit.unwrap();
+            assert_eq!(hit.map_x, 4);
+            assert_close(hit.distance, 2.5);
+        }
+    }
+}
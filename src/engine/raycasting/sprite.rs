@@ -0,0 +1,296 @@
+use crate::engine::primitives::vector::Vector2;
+use crate::engine::raycasting::ray_caster::{PlayerPose, RayHit};
+
+// Sprites are flat images placed in the world which always face the camera, eg. pickups,
+// enemies and decorations. Each one is projected onto the screen like a wall column would be,
+// then cut into runs of columns that are in front of the walls, using the perpendicular wall
+// distances of the ray cast as a z-buffer. Sprites are drawn back to front so the nearest one
+// ends up on top, and transparent texels are blended over whatever is behind them.
+
+// Sprites closer than this to the camera plane are not drawn
+const NEAR_PLANE: f32 = 0.05;
+
+/// A billboard standing on the floor. Position is in tile units, scale is relative to the
+/// height of a wall. Texture ids refer to the map's texture table.
+/// Facing and frame pick the frame to show when the texture is a sprite sheet, see SpriteSheet.
+#[derive(Clone, Copy)]
+pub struct Sprite {
+    pub position: Vector2<f32>,
+    pub texture_id: u32,
+    pub scale: f32,
+    // degrees, the same as PlayerPose angles
+    pub facing: f32,
+    // animation frame
+    pub frame: u32,
+}
+
+impl Sprite {
+    pub fn new(position: Vector2<f32>, texture_id: u32) -> Self {
+        Self {
+            position,
+            texture_id,
+            scale: 1.0,
+            facing: 0.0,
+            frame: 0,
+        }
+    }
+}
+
+/// A run of neighbouring screen columns of one sprite that are in front of the walls,
+/// clipped to the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteSlice {
+    // index of the sprite this slice belongs to
+    pub sprite: usize,
+    pub texture_id: u32,
+    // first column and number of columns
+    pub column: u32,
+    pub width: u32,
+    // top pixel row and height in pixels
+    pub top: u32,
+    pub height: u32,
+    // UV of the top left of the visible part of the slice, and how much of the texture is visible
+    pub tex_u: f32,
+    pub tex_v: f32,
+    pub tex_width: f32,
+    pub tex_height: f32,
+    // perpendicular distance from the camera plane, comparable with RayHit::distance
+    pub depth: f32,
+    pub shade: f32,
+}
+
+/// The perpendicular wall distance of every column, columns where the ray hit nothing are
+/// infinitely far away.
+pub fn z_buffer(hits: &[Option<RayHit>]) -> Vec<f32> {
+    hits.iter()
+        .map(|hit| hit.map_or(f32::INFINITY, |hit| hit.distance))
+        .collect()
+}
+
+/// Project the sprites onto the screen and clip them against the walls.
+/// Slices are returned back to front, so drawing them in order gives the right overlap.
+/// `z_buffer` has one entry per screen column, see z_buffer(). Shading matches the walls,
+/// shade = 1 / (1 + depth * falloff).
+pub fn project_sprites(
+    sprites: &[Sprite],
+    pose: &PlayerPose,
+    z_buffer: &[f32],
+    screen_width: u32,
+    screen_height: u32,
+    falloff: f32,
+) -> Vec<SpriteSlice> {
+    // furthest first
+    let mut order: Vec<(usize, f32)> = sprites
+        .iter()
+        .enumerate()
+        .map(|(index, sprite)| {
+            let x = sprite.position.x - pose.position.x;
+            let y = sprite.position.y - pose.position.y;
+            (index, x * x + y * y)
+        })
+        .collect();
+    order.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    order
+        .into_iter()
+        .flat_map(|(index, _)| {
+            project_sprite(
+                index,
+                &sprites[index],
+                pose,
+                z_buffer,
+                screen_width,
+                screen_height,
+                falloff,
+            )
+        })
+        .collect()
+}
+
+fn project_sprite(
+    index: usize,
+    sprite: &Sprite,
+    pose: &PlayerPose,
+    z_buffer: &[f32],
+    screen_width: u32,
+    screen_height: u32,
+    falloff: f32,
+) -> Vec<SpriteSlice> {
+    // Express the sprite in camera space, as a distance along the plane and along the
+    // direction, by inverting the matrix [plane direction]
+    let x = sprite.position.x - pose.position.x;
+    let y = sprite.position.y - pose.position.y;
+    let det = pose.plane.x * pose.direction.y - pose.direction.x * pose.plane.y;
+    if det == 0.0 {
+        return Vec::new();
+    }
+    let across = (pose.direction.y * x - pose.direction.x * y) / det;
+    let depth = (pose.plane.x * y - pose.plane.y * x) / det;
+    if depth < NEAR_PLANE {
+        return Vec::new();
+    }
+
+    // Same scale as the walls, a sprite with a scale of 1 is as tall as a wall, and the
+    // bottom of the sprite rests on the floor
+    let size = screen_height as f32 / depth * sprite.scale;
+    if !size.is_finite() || size < 1.0 {
+        return Vec::new();
+    }
+    let centre = (1.0 + across / depth) * screen_width as f32 / 2.0;
+    let left = centre - size / 2.0;
+    let bottom = screen_height as f32 / 2.0 * (1.0 + 1.0 / depth);
+    let top = bottom - size;
+
+    let visible_top = top.max(0.0);
+    let visible_bottom = bottom.min(screen_height as f32);
+    let pixel_top = visible_top.round() as u32;
+    let pixel_height = (visible_bottom.round().max(0.0) as u32).saturating_sub(pixel_top);
+    if pixel_height == 0 {
+        return Vec::new();
+    }
+    let tex_v = (visible_top - top) / size;
+    let tex_height = (visible_bottom - visible_top) / size;
+    let shade = 1.0 / (1.0 + depth * falloff);
+
+    // columns whose centres fall on the sprite
+    let first = (left - 0.5).ceil().max(0.0) as u32;
+    let last = ((left + size - 0.5).ceil().max(0.0) as u32).min(screen_width);
+    let mut slices = Vec::new();
+    let mut column = first;
+    while column < last {
+        if depth >= z_buffer.get(column as usize).copied().unwrap_or(0.0) {
+            column += 1;
+            continue;
+        }
+        let start = column;
+        while column < last && depth < z_buffer.get(column as usize).copied().unwrap_or(0.0) {
+            column += 1;
+        }
+        // the edge columns can reach half a pixel past the sprite, keep the UVs on the texture
+        let tex_u = ((start as f32 - left) / size).clamp(0.0, 1.0);
+        let tex_width = ((column - start) as f32 / size).min(1.0 - tex_u);
+        slices.push(SpriteSlice {
+            sprite: index,
+            texture_id: sprite.texture_id,
+            column: start,
+            width: column - start,
+            top: pixel_top,
+            height: pixel_height,
+            tex_u,
+            tex_v,
+            tex_width,
+            tex_height,
+            depth,
+            shade,
+        });
+    }
+    slices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    const SCREEN: u32 = 100;
+
+    // at the origin facing +x, with a 90 degree field of view
+    fn pose() -> PlayerPose {
+        PlayerPose::new(Vector2 { x: 0.0, y: 0.0 }, 0.0, 90.0)
+    }
+
+    fn sprite(x: f32, y: f32) -> Sprite {
+        Sprite::new(Vector2 { x, y }, 1)
+    }
+
+    fn project(sprites: &[Sprite], z_buffer: &[f32]) -> Vec<SpriteSlice> {
+        project_sprites(sprites, &pose(), z_buffer, SCREEN, SCREEN, 0.5)
+    }
+
+    // (sprite, first column, width) of each slice
+    fn runs(slices: &[SpriteSlice]) -> Vec<(usize, u32, u32)> {
+        slices
+            .iter()
+            .map(|slice| (slice.sprite, slice.column, slice.width))
+            .collect()
+    }
+
+    // walls at `distance` over `columns`, nothing anywhere else
+    fn with_wall(z_buffer: &mut [f32], columns: Range<usize>, distance: f32) {
+        z_buffer[columns].fill(distance);
+    }
+
+    #[test]
+    fn sprites_are_projected_like_walls() {
+        // straight ahead, 4 tiles away, so a quarter of the screen high and resting on the floor
+        let slices = project(&[sprite(4.0, 0.0)], &[f32::INFINITY; SCREEN as usize]);
+        assert_eq!(runs(&slices), [(0, 37, 25)]);
+        let slice = slices[0];
+        assert_eq!((slice.top, slice.height), (38, 25));
+        assert_eq!(slice.depth, 4.0);
+        assert!((slice.shade - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(
+            (slice.tex_u, slice.tex_v, slice.tex_height),
+            (0.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn sprites_are_drawn_back_to_front() {
+        let sprites = [sprite(2.0, 0.5), sprite(4.0, 0.0), sprite(3.0, -0.5)];
+        let slices = project(&sprites, &[f32::INFINITY; SCREEN as usize]);
+        let order: Vec<usize> = slices.iter().map(|slice| slice.sprite).collect();
+        assert_eq!(order, [1, 2, 0]);
+        assert!(slices.windows(2).all(|pair| pair[0].depth >= pair[1].depth));
+    }
+
+    #[test]
+    fn sprites_behind_or_beside_the_camera_are_culled() {
+        let sprites = [
+            sprite(-2.0, 0.0),
+            sprite(0.0, 1.0),
+            sprite(NEAR_PLANE * 0.5, 0.0),
+            // in front of the camera, but off the side of the screen
+            sprite(1.0, 3.0),
+        ];
+        assert!(project(&sprites, &[f32::INFINITY; SCREEN as usize]).is_empty());
+        // just past the near plane is still drawn, filling the screen
+        let close = project(
+            &[sprite(NEAR_PLANE * 2.0, 0.0)],
+            &[f32::INFINITY; SCREEN as usize],
+        );
+        assert_eq!(runs(&close), [(0, 0, SCREEN)]);
+        assert_eq!((close[0].top, close[0].height), (0, SCREEN));
+    }
+
+    #[test]
+    fn sprites_are_clipped_against_the_walls() {
+        // the near sprite covers columns 37 to 86, the far one 37 to 61
+        let sprites = [sprite(2.0, 0.5), sprite(4.0, 0.0)];
+        let mut z_buffer = [f32::INFINITY; SCREEN as usize];
+        // a wall between the two sprites hides the right of the far one
+        with_wall(&mut z_buffer, 55..62, 3.0);
+        // a pillar in front of both splits them in two
+        with_wall(&mut z_buffer, 45..50, 1.0);
+        // a wall in front of the near sprite hides half of it
+        with_wall(&mut z_buffer, 62..100, 1.5);
+        let slices = project(&sprites, &z_buffer);
+        assert_eq!(
+            runs(&slices),
+            [(1, 37, 8), (1, 50, 5), (0, 37, 8), (0, 50, 12)]
+        );
+        // each run starts the texture where it starts on the sprite
+        let near = &slices[2..];
+        assert_eq!(near[0].tex_u, 0.0);
+        assert!((near[0].tex_width - 8.0 / 50.0).abs() < 1e-6);
+        assert!((near[1].tex_u - 12.5 / 50.0).abs() < 1e-6);
+        assert!((near[1].tex_width - 12.0 / 50.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sprite_hidden_behind_a_wall_is_not_drawn() {
+        let mut z_buffer = [f32::INFINITY; SCREEN as usize];
+        with_wall(&mut z_buffer, 0..100, 3.0);
+        assert!(project(&[sprite(4.0, 0.0)], &z_buffer).is_empty());
+    }
+}
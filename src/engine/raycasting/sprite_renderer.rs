@@ -0,0 +1,217 @@
+use crate::engine::actors::entity::Entity2D;
+use crate::engine::advanced_types::batch::Batch2D;
+use crate::engine::advanced_types::screen::{create_pipeline_2d, Screen2D};
+use crate::engine::advanced_types::sprite_sheet::SpriteSheet;
+use crate::engine::config::DepthConfig;
+use crate::engine::pipeline::{PipelineCache, PipelineHandle};
+use crate::engine::primitives::vector::Vector2;
+use crate::engine::raycasting::ray_caster::PlayerPose;
+use crate::engine::raycasting::sprite::{project_sprites, Sprite};
+use crate::engine::raycasting::wall_renderer::WallShading;
+use crate::engine::texture::{Texture2D, TextureStore};
+use crate::engine::traits::update_entity::UpdateEntity;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// Sprite slices are drawn as Entity2D's in the same way as wall strips, with one Batch2D per
+// texture. Unlike walls the order matters, so each frame the slices are appended to their
+// batches back to front, and a list of runs records which range of which batch to draw next.
+// Textures registered as sprite sheets only show one frame of the texture per sprite, picked by
+// where the player is looking from.
+
+struct SpriteBatch {
+    batch: Batch2D,
+    // reused between frames, only the first `used` slices are drawn
+    slices: Vec<Entity2D>,
+    used: usize,
+}
+
+pub struct SpriteRenderer {
+    pipeline: PipelineHandle,
+    screen: Screen2D,
+    falloff: f32,
+    // keyed by texture id
+    batches: BTreeMap<u32, SpriteBatch>,
+    // keyed by texture id, textures without a sheet are drawn whole
+    sheets: BTreeMap<u32, SpriteSheet>,
+    // back to front, texture id and the range of entities in its batch
+    runs: Vec<(u32, Range<u32>)>,
+}
+
+impl SpriteRenderer {
+    /// Build the pipeline used to draw sprites, with one batch for each of the map's textures.
+    pub fn new(
+        textures: &TextureStore,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        depth: Option<&DepthConfig>,
+        pipelines: &mut PipelineCache,
+    ) -> Result<Self> {
+        let mut batches = BTreeMap::new();
+        for (id, texture) in textures.iter() {
+            batches.insert(
+                id,
+                SpriteBatch {
+                    batch: Batch2D::with_shared_texture(texture.clone()),
+                    slices: Vec::new(),
+                    used: 0,
+                },
+            );
+        }
+
+        let screen = Screen2D::new(device, config.width, config.height);
+        let pipeline = create_pipeline_2d(
+            device,
+            pipelines,
+            config.format,
+            texture_bind_group_layout,
+            screen.bind_group_layout(),
+            depth,
+        )?;
+
+        Ok(Self {
+            pipeline,
+            screen,
+            falloff: WallShading::default().falloff,
+            batches,
+            sheets: BTreeMap::new(),
+            runs: Vec::new(),
+        })
+    }
+
+    /// Rebuild the sprite slices for this frame. `z_buffer` holds the wall distance of every
+    /// screen column, see sprite::z_buffer.
+    pub fn update(
+        &mut self,
+        sprites: &[Sprite],
+        pose: &PlayerPose,
+        z_buffer: &[f32],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        for sprite_batch in self.batches.values_mut() {
+            sprite_batch.used = 0;
+        }
+        self.runs.clear();
+        let slices = project_sprites(
+            sprites,
+            pose,
+            z_buffer,
+            self.screen.width(),
+            self.screen.height(),
+            self.falloff,
+        );
+        for slice in slices {
+            // sprites with a texture id missing from the texture table are not drawn
+            let Some(sprite_batch) = self.batches.get_mut(&slice.texture_id) else {
+                continue;
+            };
+            if sprite_batch.used == sprite_batch.slices.len() {
+                sprite_batch.slices.push(Entity2D::new(
+                    slice.texture_id,
+                    Vector2 { x: 0, y: 0 },
+                    0.0,
+                    1.0,
+                    Vector2 { x: 0, y: 0 },
+                ));
+            }
+            // the slice's UVs cover the whole sprite, move them into its frame of the sheet
+            let (frame_offset, frame_size) = match self.sheets.get(&slice.texture_id) {
+                Some(sheet) => {
+                    let sprite = &sprites[slice.sprite];
+                    let angle = sheet.angle_index(sprite.position, sprite.facing, pose.position);
+                    sheet.frame_region(angle, sprite.frame)
+                }
+                None => (Vector2 { x: 0.0, y: 0.0 }, Vector2 { x: 1.0, y: 1.0 }),
+            };
+            let entity = &mut sprite_batch.slices[sprite_batch.used];
+            entity.set_position(slice.column, slice.top);
+            entity.set_scale_xy(slice.width as f32, slice.height as f32);
+            entity.set_tex_region(
+                Vector2 {
+                    x: frame_offset.x + slice.tex_u * frame_size.x,
+                    y: frame_offset.y + slice.tex_v * frame_size.y,
+                },
+                Vector2 {
+                    x: slice.tex_width * frame_size.x,
+                    y: slice.tex_height * frame_size.y,
+                },
+            );
+            entity.set_shade(slice.shade);
+            entity.update();
+
+            let index = sprite_batch.used as u32;
+            sprite_batch.used += 1;
+            // slices following on in the same batch can be drawn together
+            match self.runs.last_mut() {
+                Some((texture_id, range)) if *texture_id == slice.texture_id => {
+                    range.end = index + 1
+                }
+                _ => self.runs.push((slice.texture_id, index..index + 1)),
+            }
+        }
+        for sprite_batch in self.batches.values_mut() {
+            sprite_batch
+                .batch
+                .update(&sprite_batch.slices[..sprite_batch.used], device, queue);
+        }
+    }
+
+    /// Pick up the pipeline again if its shader has been reloaded, see PipelineHandle::refresh.
+    pub fn refresh_pipeline(&mut self, pipelines: &PipelineCache) {
+        self.pipeline.refresh(pipelines);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.runs.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(self.pipeline.pipeline());
+        render_pass.set_bind_group(1, self.screen.bind_group(), &[]);
+        for (texture_id, range) in &self.runs {
+            if let Some(sprite_batch) = self.batches.get(texture_id) {
+                sprite_batch.batch.draw_range(render_pass, range.clone());
+            }
+        }
+    }
+
+    /// Draw the texture as a sprite sheet rather than a single image.
+    pub fn set_sprite_sheet(&mut self, texture_id: u32, sheet: SpriteSheet) {
+        self.sheets.insert(texture_id, sheet);
+    }
+
+    pub fn remove_sprite_sheet(&mut self, texture_id: u32) -> Option<SpriteSheet> {
+        self.sheets.remove(&texture_id)
+    }
+
+    pub fn sprite_sheet(&self, texture_id: u32) -> Option<&SpriteSheet> {
+        self.sheets.get(&texture_id)
+    }
+
+    /// The texture loaded for a texture id, eg. to split it up with SpriteSheet::from_texture.
+    pub fn texture(&self, texture_id: u32) -> Option<&Texture2D> {
+        self.batches
+            .get(&texture_id)
+            .map(|sprite_batch| sprite_batch.batch.texture())
+    }
+
+    pub fn falloff(&self) -> f32 {
+        self.falloff
+    }
+
+    /// Distance shading, see WallShading
+    pub fn set_falloff(&mut self, falloff: f32) {
+        self.falloff = falloff;
+    }
+
+    pub fn screen(&self) -> &Screen2D {
+        &self.screen
+    }
+
+    /// Project sprites onto a screen of a new size, update must be given a z buffer as wide as it.
+    pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.screen.resize(queue, width, height);
+    }
+}
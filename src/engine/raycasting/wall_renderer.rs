@@ -0,0 +1,359 @@
+use crate::engine::actors::entity::Entity2D;
+use crate::engine::advanced_types::batch::Batch2D;
+use crate::engine::advanced_types::screen::{create_pipeline_2d, Screen2D};
+use crate::engine::config::DepthConfig;
+use crate::engine::pipeline::{PipelineCache, PipelineHandle};
+use crate::engine::primitives::vector::Vector2;
+use crate::engine::raycasting::ray_caster::RayHit;
+use crate::engine::texture::TextureStore;
+use crate::engine::traits::update_entity::UpdateEntity;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+// Walls are drawn as one textured strip per screen column. Each strip is an Entity2D one pixel
+// wide, stretched to the height of the wall and mapped onto a single column of the wall texture.
+// Strips are grouped into one Batch2D per texture, and the entities are kept between frames
+// so they don't have to be recreated every time the player moves.
+
+/// How walls darken, shade = 1 / (1 + distance * falloff).
+/// Walls facing north or south are further multiplied by side_shade to tell the faces apart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WallShading {
+    pub falloff: f32,
+    pub side_shade: f32,
+}
+
+impl Default for WallShading {
+    fn default() -> Self {
+        Self {
+            falloff: 0.15,
+            side_shade: 0.75,
+        }
+    }
+}
+
+/// The screen space placement of a single wall column, clipped to the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WallStrip {
+    pub column: u32,
+    // top pixel row and height in pixels
+    pub top: u32,
+    pub height: u32,
+    // UV of the top of the visible part of the strip, and how much of the texture height is visible
+    pub tex_u: f32,
+    pub tex_v: f32,
+    pub tex_height: f32,
+    pub shade: f32,
+}
+
+/// Work out where the wall for `hit` is drawn in `column`.
+/// Returns None if the wall is too far away to cover a single pixel.
+pub fn wall_strip(
+    hit: &RayHit,
+    column: u32,
+    screen_height: u32,
+    texture_width: u32,
+    shading: &WallShading,
+) -> Option<WallStrip> {
+    let line_height = hit.line_height(screen_height);
+    if !line_height.is_finite() || line_height < 1.0 {
+        return None;
+    }
+    // walls are centred on the horizon, anything off screen is clipped and the texture
+    // coordinates are moved to match so the texture doesn't squash
+    let top = (screen_height as f32 - line_height) / 2.0;
+    let visible_top = top.max(0.0);
+    let visible_bottom = (top + line_height).min(screen_height as f32);
+    let tex_v = (visible_top - top) / line_height;
+    let tex_height = (visible_bottom - visible_top) / line_height;
+
+    // sample the centre of a single texel column so neighbouring columns don't bleed in
+    let texel = ((hit.texture_u * texture_width as f32) as u32).min(texture_width.max(1) - 1);
+    let tex_u = (texel as f32 + 0.5) / texture_width as f32;
+
+    let mut shade = 1.0 / (1.0 + hit.distance * shading.falloff);
+    if !hit.side.is_x_side() {
+        shade *= shading.side_shade;
+    }
+
+    let top = visible_top.round() as u32;
+    let height = (visible_bottom.round() as u32).saturating_sub(top);
+    if height == 0 {
+        return None;
+    }
+    Some(WallStrip {
+        column,
+        top,
+        height,
+        tex_u,
+        tex_v,
+        tex_height,
+        shade,
+    })
+}
+
+struct WallBatch {
+    batch: Batch2D,
+    // reused between frames, only the first `used` strips are drawn
+    strips: Vec<Entity2D>,
+    used: usize,
+}
+
+pub struct WallRenderer {
+    pipeline: PipelineHandle,
+    screen: Screen2D,
+    shading: WallShading,
+    // keyed by texture id
+    batches: BTreeMap<u32, WallBatch>,
+}
+
+impl WallRenderer {
+    /// Build the pipeline used to draw strips, with one batch for each of the map's textures.
+    pub fn new(
+        textures: &TextureStore,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        depth: Option<&DepthConfig>,
+        pipelines: &mut PipelineCache,
+    ) -> Result<Self> {
+        let mut batches = BTreeMap::new();
+        for (id, texture) in textures.iter() {
+            batches.insert(
+                id,
+                WallBatch {
+                    batch: Batch2D::with_shared_texture(texture.clone()),
+                    strips: Vec::new(),
+                    used: 0,
+                },
+            );
+        }
+
+        let screen = Screen2D::new(device, config.width, config.height);
+        let pipeline = create_pipeline_2d(
+            device,
+            pipelines,
+            config.format,
+            texture_bind_group_layout,
+            screen.bind_group_layout(),
+            depth,
+        )?;
+
+        Ok(Self {
+            pipeline,
+            screen,
+            shading: WallShading::default(),
+            batches,
+        })
+    }
+
+    /// Rebuild the strips from one ray hit per screen column.
+    pub fn update(&mut self, hits: &[Option<RayHit>], device: &wgpu::Device, queue: &wgpu::Queue) {
+        for wall_batch in self.batches.values_mut() {
+            wall_batch.used = 0;
+        }
+        for (column, hit) in hits.iter().enumerate() {
+            let Some(hit) = hit else {
+                continue;
+            };
+            // walls with a texture id missing from the texture table are not drawn
+            let Some(wall_batch) = self.batches.get_mut(&hit.tile) else {
+                continue;
+            };
+            let texture_width = wall_batch.batch.texture().dimensions().width;
+            let Some(strip) = wall_strip(
+                hit,
+                column as u32,
+                self.screen.height(),
+                texture_width,
+                &self.shading,
+            ) else {
+                continue;
+            };
+            if wall_batch.used == wall_batch.strips.len() {
+                wall_batch.strips.push(Entity2D::new(
+                    hit.tile,
+                    Vector2 { x: 0, y: 0 },
+                    0.0,
+                    1.0,
+                    Vector2 { x: 0, y: 0 },
+                ));
+            }
+            let entity = &mut wall_batch.strips[wall_batch.used];
+            entity.set_position(strip.column, strip.top);
+            entity.set_scale_xy(1.0, strip.height as f32);
+            entity.set_tex_region(
+                Vector2 {
+                    x: strip.tex_u,
+                    y: strip.tex_v,
+                },
+                Vector2 {
+                    x: 0.0,
+                    y: strip.tex_height,
+                },
+            );
+            entity.set_shade(strip.shade);
+            entity.update();
+            wall_batch.used += 1;
+        }
+        for wall_batch in self.batches.values_mut() {
+            wall_batch
+                .batch
+                .update(&wall_batch.strips[..wall_batch.used], device, queue);
+        }
+    }
+
+    /// Pick up the pipeline again if its shader has been reloaded, see PipelineHandle::refresh.
+    pub fn refresh_pipeline(&mut self, pipelines: &PipelineCache) {
+        self.pipeline.refresh(pipelines);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(self.pipeline.pipeline());
+        render_pass.set_bind_group(1, self.screen.bind_group(), &[]);
+        for wall_batch in self.batches.values() {
+            wall_batch.batch.draw(render_pass);
+        }
+    }
+
+    pub fn shading(&self) -> WallShading {
+        self.shading
+    }
+
+    pub fn set_shading(&mut self, shading: WallShading) {
+        self.shading = shading;
+    }
+
+    pub fn screen(&self) -> &Screen2D {
+        &self.screen
+    }
+
+    /// Draw strips for a screen of a new size, update must be given one hit per column of it.
+    pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.screen.resize(queue, width, height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::raycasting::ray_caster::{RayCaster, WallSide};
+    use crate::engine::world::map::Map;
+
+    const SCREEN_HEIGHT: u32 = 100;
+    const TEXTURE_WIDTH: u32 = 4;
+
+    const ROOM: &str = "\
+[textures]
+1 = brick.png
+2 = floor.png
+
+[legend]
+# wall=1 floor=2 ceiling=2 solid
+. floor=2 ceiling=2
+P floor=2 ceiling=2 spawn=0
+
+[map]
+#####
+#...#
+#.P.#
+#...#
+#####
+";
+
+    fn hit(distance: f32) -> RayHit {
+        RayHit {
+            distance,
+            side: WallSide::West,
+            tile: 1,
+            map_x: 0,
+            map_y: 0,
+            texture_u: 0.5,
+        }
+    }
+
+    fn strip(hit: &RayHit, shading: &WallShading) -> Option<WallStrip> {
+        wall_strip(hit, 7, SCREEN_HEIGHT, TEXTURE_WIDTH, shading)
+    }
+
+    fn unshaded() -> WallShading {
+        WallShading {
+            falloff: 0.0,
+            side_shade: 0.75,
+        }
+    }
+
+    #[test]
+    fn height_is_inversely_proportional_to_distance() {
+        let near = strip(&hit(2.0), &unshaded()).unwrap();
+        assert_eq!((near.column, near.top, near.height), (7, 25, 50));
+        let far = strip(&hit(5.0), &unshaded()).unwrap();
+        assert_eq!((far.top, far.height), (40, 20));
+        // the whole texture is shown when the wall fits on screen
+        assert_eq!((far.tex_v, far.tex_height), (0.0, 1.0));
+        // a wall one tile away exactly fills the screen
+        let touching = strip(&hit(1.0), &unshaded()).unwrap();
+        assert_eq!((touching.top, touching.height), (0, SCREEN_HEIGHT));
+    }
+
+    #[test]
+    fn close_walls_are_clipped_to_the_screen() {
+        // twice the height of the screen, so only the middle half of the texture is visible
+        let clipped = strip(&hit(0.5), &unshaded()).unwrap();
+        assert_eq!((clipped.top, clipped.height), (0, SCREEN_HEIGHT));
+        assert!((clipped.tex_v - 0.25).abs() < 1e-6);
+        assert!((clipped.tex_height - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn distant_walls_are_not_drawn() {
+        assert_eq!(strip(&hit(1000.0), &unshaded()), None);
+        assert_eq!(strip(&hit(f32::INFINITY), &unshaded()), None);
+    }
+
+    #[test]
+    fn shading_darkens_with_distance() {
+        let shading = WallShading::default();
+        let shades: Vec<f32> = [0.5, 1.0, 2.0, 4.0]
+            .iter()
+            .map(|distance| strip(&hit(*distance), &shading).unwrap().shade)
+            .collect();
+        assert!(shades.windows(2).all(|pair| pair[0] > pair[1]));
+        let expected = 1.0 / (1.0 + 2.0 * shading.falloff);
+        assert!((shades[2] - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn texture_column_follows_each_side() {
+        let map = Map::from_ascii(ROOM).unwrap();
+        let caster = RayCaster::new(20.0);
+        let origin = Vector2 { x: 2.25, y: 2.75 };
+        // the faces read left to right as seen by the player, so the east and north faces are
+        // flipped compared with the map's axes
+        let cases = [
+            ((1.0, 0.0), WallSide::West, 3),
+            ((-1.0, 0.0), WallSide::East, 1),
+            ((0.0, 1.0), WallSide::North, 3),
+            ((0.0, -1.0), WallSide::South, 1),
+        ];
+        for ((x, y), side, texel) in cases {
+            let hit = caster.cast_ray(&map, origin, Vector2 { x, y }).unwrap();
+            assert_eq!(hit.side, side);
+            let strip = strip(&hit, &unshaded()).unwrap();
+            // the centre of the texel, so neighbouring columns don't bleed in
+            let tex_u = (texel as f32 + 0.5) / TEXTURE_WIDTH as f32;
+            assert!((strip.tex_u - tex_u).abs() < 1e-6, "{:?}", side);
+            // north and south faces are darker to tell them apart
+            let shade = if side.is_x_side() { 1.0 } else { 0.75 };
+            assert_eq!(strip.shade, shade);
+        }
+    }
+
+    #[test]
+    fn texture_column_stays_on_the_texture() {
+        let mut edge = hit(2.0);
+        edge.texture_u = 1.0;
+        let strip = strip(&edge, &unshaded()).unwrap();
+        assert!((strip.tex_u - 3.5 / TEXTURE_WIDTH as f32).abs() < 1e-6);
+    }
+}
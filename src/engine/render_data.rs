@@ -6,21 +6,33 @@ use super::traits::update_textures::UpdateTextures;
 use crate::engine::actors::entity::RawEntity2D;
 use crate::engine::advanced_types::batch::Batch2D;
 use crate::engine::advanced_types::camera::Camera3D;
-use crate::engine::primitives::quaternion::Quaternion;
+use crate::engine::config::{DepthConfig, EngineConfig, SurfaceErrorAction, SurfaceErrorPolicy};
+use crate::engine::pipeline::{PipelineBuilder, PipelineCache, PipelineHandle};
 use crate::engine::primitives::vector::Vector2;
-use crate::engine::primitives::vector::Vector3;
 use crate::engine::primitives::vertex::{Vertex2D, Vertex3D};
+use crate::engine::raycasting::floor_renderer::FloorRenderer;
+use crate::engine::raycasting::ray_caster::{PlayerPose, RayCaster};
+use crate::engine::raycasting::sprite::{self, Sprite};
+use crate::engine::raycasting::sprite_renderer::SpriteRenderer;
+use crate::engine::raycasting::wall_renderer::WallRenderer;
+use crate::engine::render_graph::{PassDescriptor, PassKind, RenderGraph, DEPTH, FRAME};
+use crate::engine::shader::ShaderRegistry;
 use crate::engine::texture;
-use crate::engine::texture::Texture2D;
+use crate::engine::texture::{DepthTexture, Texture2D, TextureStore};
+use crate::engine::time::Time;
+use crate::engine::world::map::Map;
+use crate::engine::world::player::{Interaction, Player, PlayerInput};
+use anyhow::{bail, Context, Result};
 use bytemuck;
 use image::error::EncodingError;
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::collections::HashSet;
 use wgpu::Face::Back;
-use wgpu::{util::DeviceExt, BindGroupLayout, RenderPassDescriptor, RenderPipelineDescriptor};
+use wgpu::{util::DeviceExt, BindGroupLayout, RenderPipelineDescriptor};
 use winit::event::DeviceEvent;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
-use winit::window::Window;
+use winit::window::{Fullscreen, Window};
 
 const VERTICES: &[Vertex3D] = &[
     // font face
@@ -64,44 +76,88 @@ const VERTICES: &[Vertex3D] = &[
     // Back face
 ];
 
+// Degrees the player turns per pixel of mouse movement
+const MOUSE_SENSITIVITY: f32 = 0.2;
+// Rays which travel further than this many tiles are not drawn
+const MAX_RAY_DISTANCE: f32 = 64.0;
+
 const INDICES: &[u16] = &[
     0, 1, 2, 0, 2, 3, 4, 5, 1, 4, 1, 0, 1, 5, 6, 1, 6, 2, 3, 2, 6, 3, 6, 7, 4, 0, 3, 4, 3, 7, 5, 4,
     7, 5, 7, 6,
 ];
 
+// Where frames end up. Windowed rendering presents to the surface, while headless rendering
+// draws into a texture that can be read back on the CPU, which lets the renderer run without a
+// display (CI, golden image tests).
+enum RenderTarget {
+    Surface {
+        surface: wgpu::Surface,
+        window: Window,
+    },
+    Offscreen {
+        texture: wgpu::Texture,
+    },
+}
+
 pub struct RenderData {
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
-    surface: wgpu::Surface,
-    window: Window,
-    pipeline: wgpu::RenderPipeline,
+    target: RenderTarget,
+    pipeline: PipelineHandle,
+    pipelines: PipelineCache,
+    shaders: ShaderRegistry,
+    // the passes a frame is drawn with, see RenderGraph
+    graph: RenderGraph,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
     texture: Texture2D,
     vert_buf: wgpu::Buffer,
     index_buf: wgpu::Buffer,
     camera: Camera3D,
     camera_controller: CameraController3D,
-    entity: Entity3D,
+    entities: Vec<Entity3D>,
     entity_buf: wgpu::Buffer,
+    map: Option<Map>,
+    ray_caster: RayCaster,
+    player: Player,
+    // the player is given this field of view whenever a map is loaded
+    player_fov: f32,
+    // movement keys currently held down, used to move the player each update
+    held_keys: HashSet<VirtualKeyCode>,
+    // timing of the current frame, render interpolates between updates using its alpha
+    time: Time,
+    wall_renderer: Option<WallRenderer>,
+    floor_renderer: Option<FloorRenderer>,
+    sprites: Vec<Sprite>,
+    sprite_renderer: Option<SpriteRenderer>,
+    // set once the window should close, see run_app
+    exit_requested: bool,
+    surface_errors: SurfaceErrorPolicy,
+    // depth testing of the 3D pipeline, the depth texture is recreated whenever the frames
+    // change size
+    depth: DepthConfig,
+    depth_texture: DepthTexture,
 }
 
 impl RenderData {
-    pub async fn new(window: Window) -> Self {
+    /// Render into `window` using the renderer settings from `engine_config`. Entities are drawn
+    /// with a plain white texture until one is set with set_texture.
+    pub async fn new(window: Window, engine_config: &EngineConfig) -> Result<Self> {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::all(),
             dx12_shader_compiler: wgpu::Dx12Compiler::default(),
         });
 
-        let surface = unsafe { instance.create_surface(&window).unwrap() };
+        let surface = unsafe { instance.create_surface(&window)? };
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
+                power_preference: engine_config.power_preference.into(),
                 force_fallback_adapter: false,
                 compatible_surface: Some(&surface),
             })
             .await
-            .unwrap();
+            .context("No adapter can render to the window")?;
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
@@ -111,8 +167,90 @@ impl RenderData {
                 },
                 None,
             )
+            .await?;
+        let surface_capabilities = surface.get_capabilities(&adapter);
+        let format = surface_capabilities.formats[0];
+        let size = window.inner_size();
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: engine_config.present_mode.into(),
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: Vec::new(),
+        };
+
+        surface.configure(&device, &config);
+
+        Self::build(
+            device,
+            queue,
+            config,
+            RenderTarget::Surface { surface, window },
+            engine_config,
+            None,
+        )
+    }
+
+    /// Create render data which draws into an offscreen texture instead of a window surface.
+    /// A fallback (software) adapter is requested so that no display or GPU is required.
+    /// Frames can be retrieved with `read_frame` after calling `render`.
+    pub async fn new_headless(width: u32, height: u32, texture_path: &str) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            dx12_shader_compiler: wgpu::Dx12Compiler::default(),
+        });
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::LowPower,
+                force_fallback_adapter: true,
+                compatible_surface: None,
+            })
             .await
-            .unwrap();
+            .context("No fallback adapter is available for headless rendering")?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    features: adapter.features(),
+                    limits: wgpu::Limits::downlevel_defaults(),
+                    label: None,
+                },
+                None,
+            )
+            .await?;
+        // The offscreen target takes the place of the surface, so it shares the same config.
+        // COPY_SRC is required to read the frame back.
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::AutoNoVsync,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: Vec::new(),
+        };
+        let texture = create_offscreen_texture(&device, &config);
+
+        Self::build(
+            device,
+            queue,
+            config,
+            RenderTarget::Offscreen { texture },
+            &EngineConfig::default(),
+            Some(texture_path),
+        )
+    }
+
+    fn build(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        target: RenderTarget,
+        engine_config: &EngineConfig,
+        texture_path: Option<&str>,
+    ) -> Result<Self> {
         // Multiple textures, all sharing bind group layout.....
         // each have separate bind group, bind group should be moved to Texture struct,
         // should be a function which provides a bind group layout with these parameters, or we create it here,
@@ -139,41 +277,24 @@ impl RenderData {
                 },
             ],
         });
-        let mut ran_vec = Vector3 {
-            x: 0.0 as f32,
-            y: 1.0 as f32,
-            z: 0.0 as f32,
+        let texture = match texture_path {
+            Some(texture_path) => {
+                Texture2D::new(texture_path, &queue, &device, &bind_group_layout)?
+            }
+            None => Texture2D::from_image(
+                image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255])),
+                &queue,
+                &device,
+                &bind_group_layout,
+            ),
         };
-        ran_vec.normalise();
-        let rot = 0.0;
-        let rotation = Quaternion::new(ran_vec, rot);
-
-        // TODO: 3D Entity Creation
-        let texture = Texture2D::new(
-            "src/assets/calamitas.png",
-            &queue,
-            &device,
-            &bind_group_layout,
-        )
-        .unwrap();
 
-        let entity = Entity3D::new(
-            None,
-            Vector3 {
-                x: 0.0,
-                y: 0.0,
-                z: 3.0,
-            },
-            1.0,
-            rotation,
-            Vec::from(VERTICES),
-            Vec::new(),
-        );
-
-        let entity_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index buf"),
-            contents: bytemuck::cast_slice(&[entity.to_raw()]),
+        // Room for one entity until some are set, see set_entities
+        let entity_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Entity buffer"),
+            size: std::mem::size_of::<RawEntity3D>() as wgpu::BufferAddress,
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
         });
 
         let vert_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -189,144 +310,484 @@ impl RenderData {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/shader.wgsl").into()),
-        });
-        let surface_capabilities = surface.get_capabilities(&adapter);
-        let format = surface_capabilities.formats[0];
-        let size = window.inner_size();
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::AutoNoVsync,
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            view_formats: Vec::new(),
-        };
-
-        surface.configure(&device, &config);
-
-        let camera = Camera3D::new(45.0, config.width, config.height, &device);
+        // the engine's own shaders are loaded from the shader directory so they can be edited
+        // while the engine runs, the copies built in are used if the files can't be loaded
+        let mut pipelines = PipelineCache::new();
+        let mut shaders = ShaderRegistry::new(&engine_config.shader_directory);
+        shaders.set_hot_reload(engine_config.hot_reload_shaders);
+        shaders
+            .preprocessor_mut()
+            .add_built_in("common.wgsl", include_str!("../shaders/common.wgsl"));
+        for (name, file_name, fallback) in [
+            (
+                "shader",
+                "shader.wgsl",
+                include_str!("../shaders/shader.wgsl"),
+            ),
+            (
+                "shader_2d",
+                "shader_2d.wgsl",
+                include_str!("../shaders/shader_2d.wgsl"),
+            ),
+            ("floor", "floor.wgsl", include_str!("../shaders/floor.wgsl")),
+        ] {
+            shaders.load_or(&device, &mut pipelines, name, file_name, fallback)?;
+        }
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+        let depth_texture = DepthTexture::new(
+            &device,
+            config.width,
+            config.height,
+            engine_config.depth.format.into(),
+        );
+        let camera = Camera3D::new(
+            engine_config.camera_fov,
+            config.width,
+            config.height,
+            &device,
+        );
         let camera_controller = CameraController3D::new();
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("pipeline layout"),
-            bind_group_layouts: &[&bind_group_layout, camera.bind_group_layout()],
-            push_constant_ranges: &[],
-        });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                // TODO: Add RawEntity3D descriptor
-                buffers: &[Vertex3D::descriptor(), RawEntity3D::descriptor()],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::all(),
-                })],
-            }),
-            multiview: None,
-        });
-        Self {
+        let pipeline = pipelines.get(
+            &device,
+            &PipelineBuilder::new("Render Pipeline", "shader", config.format)
+                .bind_group_layout(&bind_group_layout)
+                .bind_group_layout(camera.bind_group_layout())
+                .vertex_layout(Vertex3D::descriptor())
+                .vertex_layout(RawEntity3D::descriptor())
+                .cull_mode(Some(wgpu::Face::Back))
+                .depth(Some(&engine_config.depth)),
+        )?;
+        Ok(Self {
             device,
             queue,
             config,
             size,
-            surface,
-            window,
+            target,
             pipeline,
+            pipelines,
+            shaders,
+            graph: engine_graph(&engine_config.depth)?,
+            texture_bind_group_layout: bind_group_layout,
             texture,
             vert_buf,
             index_buf,
             camera,
             camera_controller,
-            entity,
+            entities: Vec::new(),
             entity_buf,
-        }
+            map: None,
+            ray_caster: RayCaster::new(MAX_RAY_DISTANCE),
+            player: Player::new(Vector2 { x: 0.0, y: 0.0 }, 0.0, engine_config.player_fov),
+            player_fov: engine_config.player_fov,
+            held_keys: HashSet::new(),
+            time: Time::default(),
+            wall_renderer: None,
+            floor_renderer: None,
+            sprites: Vec::new(),
+            sprite_renderer: None,
+            exit_requested: false,
+            surface_errors: engine_config.surface_errors,
+            depth: engine_config.depth,
+            depth_texture,
+        })
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let frame = self.surface.get_current_texture()?;
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    /// Draw a frame. If the surface can't give a texture to draw into, the surface error policy
+    /// decides whether the frame is skipped or an error is returned, see SurfaceErrorPolicy.
+    pub fn render(&mut self) -> Result<()> {
+        self.reload_shaders();
+        let (frame, view) = match &self.target {
+            RenderTarget::Surface { .. } => {
+                let Some(frame) = self.current_frame()? else {
+                    return Ok(());
+                };
+                let view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(frame), view)
+            }
+            RenderTarget::Offscreen { texture } => (
+                None,
+                texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
+
+        let pose = self.player.interpolated_pose(self.time.alpha());
+        if let (Some(map), Some(wall_renderer)) = (&self.map, &mut self.wall_renderer) {
+            let hits = self.ray_caster.cast(map, &pose, self.config.width);
+            wall_renderer.update(&hits, &self.device, &self.queue);
+            if let Some(sprite_renderer) = &mut self.sprite_renderer {
+                sprite_renderer.update(
+                    &self.sprites,
+                    &pose,
+                    &sprite::z_buffer(&hits),
+                    &self.device,
+                    &self.queue,
+                );
+            }
+        }
+        if let Some(floor_renderer) = &self.floor_renderer {
+            floor_renderer.update(&pose, &self.queue);
+        }
+        // entities may have been added through entities_mut since the buffer was made
+        let raw_entities: Vec<RawEntity3D> =
+            self.entities.iter().map(|entity| entity.to_raw()).collect();
+        let raw_bytes: &[u8] = bytemuck::cast_slice(&raw_entities);
+        if raw_bytes.len() as wgpu::BufferAddress > self.entity_buf.size() {
+            self.entity_buf = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Entity buffer"),
+                    contents: raw_bytes,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                });
+        }
+
+        if !self.entities.is_empty() {
+            self.queue.write_buffer(&self.entity_buf, 0, raw_bytes);
+        }
+        self.camera
+            .look(&self.camera_controller.build_transformation());
+        self.camera.update(&self.queue, &self.device);
+        self.graph
+            .prepare(&self.device, &self.queue, &self.config, &self.depth_texture)?;
 
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        // Make sure to change this to load, clearing is very expensive
-                        // ensure there are no gaps in the space to avoid residual pixels
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-
-            self.camera
-                .look(&self.camera_controller.build_transformation());
-            self.camera.update(&self.queue, &self.device);
+        for (descriptor, kind) in self.graph.ordered() {
+            let mut render_pass =
+                self.graph
+                    .begin(&mut encoder, descriptor, &view, &self.depth_texture);
+            match kind {
+                PassKind::World => self.draw_world(&mut render_pass),
+                PassKind::Sprites => {
+                    if let Some(sprite_renderer) = &self.sprite_renderer {
+                        sprite_renderer.draw(&mut render_pass);
+                    }
+                }
+                PassKind::Custom(pass) => pass.draw(&mut render_pass),
+            }
+        }
+        self.queue.submit(Some(encoder.finish()));
+        if let Some(frame) = frame {
+            frame.present();
+        }
+        Ok(())
+    }
 
-            let mut ran_vec = Vector3 {
-                x: 0.0 as f32,
-                y: 1.0 as f32,
-                z: 0.0 as f32,
-            };
-            ran_vec.normalise();
-            self.entity.set_axis(ran_vec);
-            self.entity.set_angle(self.entity.rotation().angle() - 0.25);
-            render_pass.set_pipeline(&self.pipeline);
-
-            self.queue.write_buffer(
-                &self.entity_buf,
-                0,
-                bytemuck::cast_slice(&[self.entity.to_raw()]),
-            );
-            // TODO: Implement 3D rendering renderpass
+    // With a map loaded the ray cast walls replace the 3D entities
+    fn draw_world<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some(wall_renderer) = &self.wall_renderer {
+            if let Some(floor_renderer) = &self.floor_renderer {
+                floor_renderer.draw(render_pass);
+            }
+            wall_renderer.draw(render_pass);
+        } else if !self.entities.is_empty() {
+            render_pass.set_pipeline(self.pipeline.pipeline());
             render_pass.set_bind_group(0, self.texture.bind_group(), &[]);
             render_pass.set_bind_group(1, self.camera.bind_group(), &[]);
             render_pass.set_vertex_buffer(0, self.vert_buf.slice(..));
             render_pass.set_vertex_buffer(1, self.entity_buf.slice(..));
             render_pass.set_index_buffer(self.index_buf.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+            render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..self.entities.len() as u32);
         }
+    }
+
+    /// Copy the last rendered frame of a headless render target back to the CPU.
+    pub fn read_frame(&self) -> Result<image::RgbaImage> {
+        let texture = match &self.target {
+            RenderTarget::Offscreen { texture } => texture,
+            RenderTarget::Surface { .. } => {
+                bail!("Frames can only be read back from headless render data")
+            }
+        };
+        let width = self.config.width;
+        let height = self.config.height;
+        // Rows in the copy buffer must be aligned to COPY_BYTES_PER_ROW_ALIGNMENT,
+        // so the padding has to be stripped off again when building the image.
+        let unpadded_bytes_per_row = 4 * width;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(alignment) * alignment;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame readback buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Frame readback encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
         self.queue.submit(Some(encoder.finish()));
-        frame.present();
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            // The receiver only goes away if read_frame has already returned
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+        image::RgbaImage::from_raw(width, height, pixels)
+            .context("Frame readback did not match the render target size")
+    }
+
+    /// Advance the scene by one fixed timestep, time.delta() seconds.
+    pub fn update(&mut self, time: &Time) {
+        self.time = *time;
+        let dt = time.delta();
+        let input = self.player_input();
+        if let Some(map) = &mut self.map {
+            map.update(dt);
+            self.player.update(&input, map, dt);
+        }
+    }
+
+    pub fn time(&self) -> &Time {
+        &self.time
+    }
+
+    /// Set the timing of the frame about to be rendered, after running its updates.
+    pub fn set_time(&mut self, time: Time) {
+        self.time = time;
+    }
+
+    /// Replace the entities drawn each frame. Every entity is drawn as an instance of the cube mesh.
+    pub fn set_entities(&mut self, entities: Vec<Entity3D>) {
+        // the old buffer is big enough to be kept when there's nothing to draw
+        if !entities.is_empty() {
+            let raw_entities: Vec<RawEntity3D> =
+                entities.iter().map(|entity| entity.to_raw()).collect();
+            self.entity_buf = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Entity buffer"),
+                    contents: bytemuck::cast_slice(&raw_entities),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                });
+        }
+        self.entities = entities;
+    }
+
+    pub fn entities(&self) -> &Vec<Entity3D> {
+        &self.entities
+    }
+
+    pub fn entities_mut(&mut self) -> &mut Vec<Entity3D> {
+        &mut self.entities
+    }
+
+    /// Replace the texture used to draw the entities.
+    pub fn set_texture(&mut self, texture_path: &str) -> Result<()> {
+        self.texture = Texture2D::new(
+            texture_path,
+            &self.queue,
+            &self.device,
+            &self.texture_bind_group_layout,
+        )?;
         Ok(())
     }
 
-    pub fn update(&mut self) {}
+    /// Load a map to be ray cast, loading the textures in its texture table.
+    /// The player is moved to the map's spawn point.
+    pub fn load_map(&mut self, map: Map) -> Result<()> {
+        // each texture is loaded once and shared by the walls, floors and sprites
+        let textures = TextureStore::load(
+            map.textures(),
+            &self.queue,
+            &self.device,
+            &self.texture_bind_group_layout,
+        )?;
+        // the ray cast view is drawn in screen space, in the order it should appear
+        let overlay = self.depth.overlay();
+        let wall_renderer = WallRenderer::new(
+            &textures,
+            &self.device,
+            &self.config,
+            &self.texture_bind_group_layout,
+            Some(&overlay),
+            &mut self.pipelines,
+        )?;
+        let floor_renderer = FloorRenderer::new(
+            &map,
+            &textures,
+            &self.device,
+            &self.queue,
+            &self.config,
+            Some(&overlay),
+            &mut self.pipelines,
+        )?;
+        let sprite_renderer = SpriteRenderer::new(
+            &textures,
+            &self.device,
+            &self.config,
+            &self.texture_bind_group_layout,
+            Some(&overlay),
+            &mut self.pipelines,
+        )?;
+        self.player = Player::from_spawn(map.spawn(), self.player_fov);
+        self.wall_renderer = Some(wall_renderer);
+        self.floor_renderer = Some(floor_renderer);
+        self.sprite_renderer = Some(sprite_renderer);
+        self.map = Some(map);
+        Ok(())
+    }
+
+    pub fn map(&self) -> Option<&Map> {
+        self.map.as_ref()
+    }
+
+    pub fn map_mut(&mut self) -> Option<&mut Map> {
+        self.map.as_mut()
+    }
+
+    pub fn player_pose(&self) -> PlayerPose {
+        self.player.pose()
+    }
+
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    pub fn player_mut(&mut self) -> &mut Player {
+        &mut self.player
+    }
+
+    /// Use whatever the player is looking at in the loaded map, see Player::interact.
+    pub fn interact(&mut self) -> Option<Interaction> {
+        let map = self.map.as_mut()?;
+        self.player.interact(map)
+    }
+
+    pub fn wall_renderer_mut(&mut self) -> Option<&mut WallRenderer> {
+        self.wall_renderer.as_mut()
+    }
+
+    pub fn floor_renderer_mut(&mut self) -> Option<&mut FloorRenderer> {
+        self.floor_renderer.as_mut()
+    }
+
+    pub fn sprite_renderer_mut(&mut self) -> Option<&mut SpriteRenderer> {
+        self.sprite_renderer.as_mut()
+    }
+
+    pub fn sprites(&self) -> &Vec<Sprite> {
+        &self.sprites
+    }
+
+    /// Sprites are drawn once a map is loaded, their texture ids refer to its texture table.
+    pub fn set_sprites(&mut self, sprites: Vec<Sprite>) {
+        self.sprites = sprites;
+    }
+
+    pub fn sprites_mut(&mut self) -> &mut Vec<Sprite> {
+        &mut self.sprites
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// The format of the frames being rendered, pipelines drawing to them have to use it.
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.config.format
+    }
+
+    /// The pipelines the engine draws with, apps can add their own shaders and pipelines to it.
+    pub fn pipelines_mut(&mut self) -> (&wgpu::Device, &mut PipelineCache) {
+        (&self.device, &mut self.pipelines)
+    }
+
+    /// Where the engine's shaders are loaded from, apps can load their own shaders through it
+    /// to have them hot reloaded too.
+    pub fn shaders_mut(&mut self) -> (&wgpu::Device, &mut ShaderRegistry, &mut PipelineCache) {
+        (&self.device, &mut self.shaders, &mut self.pipelines)
+    }
+
+    // reload any shaders that changed on disk and swap in the pipelines rebuilt with them
+    fn reload_shaders(&mut self) {
+        if self
+            .shaders
+            .poll(&self.device, &mut self.pipelines)
+            .is_empty()
+        {
+            return;
+        }
+        self.pipeline.refresh(&self.pipelines);
+        if let Some(wall_renderer) = &mut self.wall_renderer {
+            wall_renderer.refresh_pipeline(&self.pipelines);
+        }
+        if let Some(floor_renderer) = &mut self.floor_renderer {
+            floor_renderer.refresh_pipeline(&self.pipelines);
+        }
+        if let Some(sprite_renderer) = &mut self.sprite_renderer {
+            sprite_renderer.refresh_pipeline(&self.pipelines);
+        }
+    }
+
+    pub fn render_graph(&self) -> &RenderGraph {
+        &self.graph
+    }
+
+    /// The passes frames are drawn with, apps can add their own or change the engine's.
+    pub fn render_graph_mut(&mut self) -> &mut RenderGraph {
+        &mut self.graph
+    }
+
+    pub fn camera_controller_mut(&mut self) -> &mut CameraController3D {
+        &mut self.camera_controller
+    }
+
+    // WASD moves relative to where the player is facing, the arrow keys turn
+    fn player_input(&self) -> PlayerInput {
+        let axis = |positive: VirtualKeyCode, negative: VirtualKeyCode| {
+            self.held_keys.contains(&positive) as i32 as f32
+                - self.held_keys.contains(&negative) as i32 as f32
+        };
+        PlayerInput {
+            forward: axis(VirtualKeyCode::W, VirtualKeyCode::S),
+            strafe: axis(VirtualKeyCode::D, VirtualKeyCode::A),
+            turn: axis(VirtualKeyCode::Right, VirtualKeyCode::Left),
+        }
+    }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
         false
@@ -335,8 +796,14 @@ impl RenderData {
     pub fn device_event(&mut self, event: &DeviceEvent) {
         match event {
             DeviceEvent::MouseMotion { delta } => {
-                self.camera_controller
-                    .process_camera(delta.0 as f32 * 0.2, delta.1 as f32 * 0.2);
+                if self.map.is_some() {
+                    self.player.turn(delta.0 as f32 * MOUSE_SENSITIVITY);
+                } else {
+                    self.camera_controller.process_camera(
+                        delta.0 as f32 * MOUSE_SENSITIVITY,
+                        delta.1 as f32 * MOUSE_SENSITIVITY,
+                    );
+                }
             }
             _ => (),
         }
@@ -348,33 +815,206 @@ impl RenderData {
                 input:
                     KeyboardInput {
                         virtual_keycode: Some(key),
-                        state: ElementState::Pressed,
+                        state,
                         ..
                     },
                 ..
             } => {
-                if key == &VirtualKeyCode::W {
-                    self.camera_controller.process_keyboard(0.0, -0.1);
+                // With a map loaded the player is moved during update() for as long as keys are held
+                if self.map.is_some() {
+                    match state {
+                        ElementState::Pressed => self.held_keys.insert(*key),
+                        ElementState::Released => self.held_keys.remove(key),
+                    };
                 }
-                if key == &VirtualKeyCode::S {
-                    self.camera_controller.process_keyboard(0.0, 0.1);
+                if state == &ElementState::Pressed {
+                    if self.map.is_none() {
+                        if key == &VirtualKeyCode::W {
+                            self.camera_controller.process_keyboard(0.0, -0.1);
+                        }
+                        if key == &VirtualKeyCode::S {
+                            self.camera_controller.process_keyboard(0.0, 0.1);
+                        }
+                        if key == &VirtualKeyCode::D {
+                            self.camera_controller.process_keyboard(-0.1, 0.0);
+                        }
+                        if key == &VirtualKeyCode::A {
+                            self.camera_controller.process_keyboard(0.1, 0.0);
+                        }
+                    }
+                    if key == &VirtualKeyCode::E {
+                        self.interact();
+                    }
+                    if key == &VirtualKeyCode::F11 {
+                        self.toggle_fullscreen();
+                    }
+                    if key == &VirtualKeyCode::Escape {
+                        self.request_exit();
+                        return true;
+                    }
                 }
-                if key == &VirtualKeyCode::D {
-                    self.camera_controller.process_keyboard(-0.1, 0.0);
+            }
+            WindowEvent::Resized(new_size) => self.resize(*new_size),
+            // moving to a monitor with a different scale factor changes the window's size in
+            // physical pixels
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => self.resize(**new_inner_size),
+            _ => (),
+        }
+        false
+    }
+
+    /// Ask for the window to close once the current event has been handled.
+    pub fn request_exit(&mut self) {
+        self.exit_requested = true;
+    }
+
+    pub fn exit_requested(&self) -> bool {
+        self.exit_requested
+    }
+
+    // The surface texture to draw the next frame into, None if the frame should be skipped
+    fn current_frame(&self) -> Result<Option<wgpu::SurfaceTexture>> {
+        let RenderTarget::Surface { surface, .. } = &self.target else {
+            return Ok(None);
+        };
+        let mut reconfigured = false;
+        loop {
+            let error = match surface.get_current_texture() {
+                Ok(frame) => return Ok(Some(frame)),
+                Err(error) => error,
+            };
+            match self.surface_errors.action(&error) {
+                SurfaceErrorAction::Reconfigure if !reconfigured => {
+                    surface.configure(&self.device, &self.config);
+                    reconfigured = true;
                 }
-                if key == &VirtualKeyCode::A {
-                    self.camera_controller.process_keyboard(0.1, 0.0);
+                SurfaceErrorAction::Reconfigure | SurfaceErrorAction::SkipFrame => {
+                    log::warn!("Skipped a frame: {}", error);
+                    return Ok(None);
                 }
-                if key == &VirtualKeyCode::Escape {
-                    return true;
+                SurfaceErrorAction::Fail => {
+                    return Err(error).context("Could not get a surface texture to draw into")
                 }
             }
-            _ => (),
         }
-        false
     }
 
-    pub fn window(&self) -> &Window {
-        &self.window
+    pub fn depth(&self) -> DepthConfig {
+        self.depth
     }
+
+    pub fn depth_texture(&self) -> &DepthTexture {
+        &self.depth_texture
+    }
+
+    pub fn surface_errors(&self) -> SurfaceErrorPolicy {
+        self.surface_errors
+    }
+
+    pub fn set_surface_errors(&mut self, surface_errors: SurfaceErrorPolicy) {
+        self.surface_errors = surface_errors;
+    }
+
+    /// The size of the frames being rendered, in physical pixels.
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Reconfigure the surface (or recreate the offscreen target) and everything drawn to it for
+    /// frames of `new_size`. A zero size, which the window has while minimised, is ignored.
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width == 0 || new_size.height == 0 || new_size == self.size {
+            return;
+        }
+        self.size = new_size;
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        match &mut self.target {
+            RenderTarget::Surface { surface, .. } => surface.configure(&self.device, &self.config),
+            RenderTarget::Offscreen { texture } => {
+                *texture = create_offscreen_texture(&self.device, &self.config)
+            }
+        }
+        self.depth_texture = DepthTexture::new(
+            &self.device,
+            new_size.width,
+            new_size.height,
+            self.depth.format.into(),
+        );
+        self.camera.resize(new_size.width, new_size.height);
+        if let Some(wall_renderer) = &mut self.wall_renderer {
+            wall_renderer.resize(&self.queue, new_size.width, new_size.height);
+        }
+        if let Some(floor_renderer) = &mut self.floor_renderer {
+            floor_renderer.resize(new_size.width, new_size.height);
+        }
+        if let Some(sprite_renderer) = &mut self.sprite_renderer {
+            sprite_renderer.resize(&self.queue, new_size.width, new_size.height);
+        }
+    }
+
+    pub fn fullscreen(&self) -> bool {
+        self.window()
+            .is_some_and(|window| window.fullscreen().is_some())
+    }
+
+    /// Switch the window between borderless fullscreen on its current monitor and windowed.
+    /// The window is resized to match, which arrives as a Resized event.
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        if let Some(window) = self.window() {
+            window.set_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)));
+        }
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        self.set_fullscreen(!self.fullscreen());
+    }
+
+    /// The window being rendered to, or `None` for headless render data.
+    pub fn window(&self) -> Option<&Window> {
+        match &self.target {
+            RenderTarget::Surface { window, .. } => Some(window),
+            RenderTarget::Offscreen { .. } => None,
+        }
+    }
+}
+
+// The engine's passes: the world clears the frame and depth buffer, then the sprites are drawn
+// over it
+fn engine_graph(depth: &DepthConfig) -> Result<RenderGraph> {
+    let mut graph = RenderGraph::new();
+    graph.add_pass(
+        PassDescriptor::new("world")
+            .colour(FRAME, wgpu::LoadOp::Clear(wgpu::Color::BLACK), true)
+            .depth(DEPTH, wgpu::LoadOp::Clear(depth.clear_value), true),
+        PassKind::World,
+    )?;
+    graph.add_pass(
+        PassDescriptor::new("sprites")
+            .colour(FRAME, wgpu::LoadOp::Load, true)
+            .depth(DEPTH, wgpu::LoadOp::Load, false),
+        PassKind::Sprites,
+    )?;
+    Ok(graph)
+}
+
+// The texture headless frames are drawn into, sized and formatted to match `config`
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen target"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        view_formats: &[],
+    })
 }
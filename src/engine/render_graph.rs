@@ -0,0 +1,764 @@
+use crate::engine::texture::DepthTexture;
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+
+// A frame is drawn as a list of passes, each declaring the targets it draws into (with how they
+// are loaded and stored) and the targets it samples from. The graph works out the order to run
+// them in from that: passes drawing into the same target run in the order they were added, and
+// passes sampling a target run after every pass that draws into it. Targets other than the
+// frame and the depth buffer are transient, the graph creates their textures at the size of the
+// frame (or a scale of it) and recreates them when the frame changes size.
+//
+// The engine draws with a "world" pass and a "sprites" pass, apps add their own passes (UI,
+// post processing, ...) with a GraphPass that does the drawing, eg. to post process the world
+// both engine passes are moved off the frame into a target the post pass samples:
+//
+// graph.add_target("scene", TargetDescriptor::new())?;
+// graph.set_pass(
+//     PassDescriptor::new("world")
+//         .colour("scene", Clear(BLACK), true)
+//         .depth(DEPTH, Clear(1.0), true),
+// )?;
+// graph.set_pass(
+//     PassDescriptor::new("sprites")
+//         .colour("scene", Load, true)
+//         .depth(DEPTH, Load, false),
+// )?;
+// graph.add_pass(
+//     PassDescriptor::new("post").input("scene").colour(FRAME, Clear(BLACK), true),
+//     PassKind::Custom(Box::new(PostProcess::new())),
+// )?;
+
+/// The frame being drawn, the surface texture or the headless render target.
+pub const FRAME: &str = "frame";
+/// The engine's depth buffer, see DepthConfig.
+pub const DEPTH: &str = "depth";
+
+/// A transient target the graph creates the texture for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TargetDescriptor {
+    // None for the format of the frames, which the engine's pipelines are built for
+    format: Option<wgpu::TextureFormat>,
+    // the size of the target relative to the frames, eg. 0.5 for half resolution
+    scale: f32,
+}
+
+impl TargetDescriptor {
+    pub fn new() -> Self {
+        Self {
+            format: None,
+            scale: 1.0,
+        }
+    }
+
+    pub fn format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |length: u32| ((length as f32 * self.scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+}
+
+impl Default for TargetDescriptor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColourAttachment {
+    pub target: String,
+    pub load: wgpu::LoadOp<wgpu::Color>,
+    // whether what the pass draws is kept, for later passes or to be presented
+    pub store: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthAttachment {
+    pub target: String,
+    pub load: wgpu::LoadOp<f32>,
+    pub store: bool,
+}
+
+/// The targets a pass draws into and samples from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassDescriptor {
+    name: String,
+    colour: Vec<ColourAttachment>,
+    depth: Option<DepthAttachment>,
+    inputs: Vec<String>,
+}
+
+impl PassDescriptor {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            colour: Vec::new(),
+            depth: None,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Draw into `target`, replacing any colour attachment already drawing into it.
+    pub fn colour(mut self, target: &str, load: wgpu::LoadOp<wgpu::Color>, store: bool) -> Self {
+        self.colour.retain(|attachment| attachment.target != target);
+        self.colour.push(ColourAttachment {
+            target: target.to_string(),
+            load,
+            store,
+        });
+        self
+    }
+
+    pub fn depth(mut self, target: &str, load: wgpu::LoadOp<f32>, store: bool) -> Self {
+        self.depth = Some(DepthAttachment {
+            target: target.to_string(),
+            load,
+            store,
+        });
+        self
+    }
+
+    /// Sample `target`, the pass runs after every pass drawing into it.
+    pub fn input(mut self, target: &str) -> Self {
+        if !self.inputs.iter().any(|input| input == target) {
+            self.inputs.push(target.to_string());
+        }
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn colour_attachments(&self) -> &[ColourAttachment] {
+        &self.colour
+    }
+
+    pub fn depth_attachment(&self) -> Option<&DepthAttachment> {
+        self.depth.as_ref()
+    }
+
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> impl Iterator<Item = &str> {
+        self.colour
+            .iter()
+            .map(|attachment| attachment.target.as_str())
+            .chain(
+                self.depth
+                    .iter()
+                    .map(|attachment| attachment.target.as_str()),
+            )
+    }
+}
+
+/// What the graph gives a GraphPass to get ready for drawing with.
+pub struct PassContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    targets: &'a HashMap<String, Target>,
+    depth: &'a DepthTexture,
+    targets_changed: bool,
+}
+
+impl<'a> PassContext<'a> {
+    /// The texture of a target to sample from, the frame can't be sampled.
+    pub fn view(&self, target: &str) -> Option<&'a wgpu::TextureView> {
+        if target == DEPTH {
+            return Some(self.depth.view());
+        }
+        self.targets
+            .get(target)
+            .and_then(|target| target.texture.as_ref())
+            .map(|texture| &texture.view)
+    }
+
+    /// Whether any target's texture has been recreated since the last frame, bind groups
+    /// made with the old textures have to be made again.
+    pub fn targets_changed(&self) -> bool {
+        self.targets_changed
+    }
+}
+
+/// Draws an app's pass of the render graph.
+pub trait GraphPass {
+    /// Called every frame before any pass is drawn, eg. to write uniforms or bind inputs.
+    fn prepare(&mut self, _context: &PassContext) {}
+
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>);
+}
+
+/// What draws a pass.
+pub enum PassKind {
+    // the map, or the 3D entities when no map is loaded
+    World,
+    // the map's sprites
+    Sprites,
+    Custom(Box<dyn GraphPass>),
+}
+
+struct TransientTexture {
+    // kept alive with its view
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    size: (u32, u32),
+    format: wgpu::TextureFormat,
+}
+
+struct Target {
+    descriptor: TargetDescriptor,
+    // None until a pass uses the target
+    texture: Option<TransientTexture>,
+}
+
+#[derive(Default)]
+pub struct RenderGraph {
+    passes: Vec<(PassDescriptor, PassKind)>,
+    targets: HashMap<String, Target>,
+    // indices into passes in the order they run, None when it has to be worked out again
+    order: Option<Vec<usize>>,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a transient target, or change the descriptor of one already added.
+    pub fn add_target(&mut self, name: &str, descriptor: TargetDescriptor) -> Result<()> {
+        if name == FRAME || name == DEPTH {
+            bail!("{} is not a transient target", name);
+        }
+        match self.targets.get_mut(name) {
+            Some(target) => target.descriptor = descriptor,
+            None => {
+                self.targets.insert(
+                    name.to_string(),
+                    Target {
+                        descriptor,
+                        texture: None,
+                    },
+                );
+            }
+        }
+        self.order = None;
+        Ok(())
+    }
+
+    pub fn remove_target(&mut self, name: &str) -> Option<TargetDescriptor> {
+        self.order = None;
+        self.targets.remove(name).map(|target| target.descriptor)
+    }
+
+    pub fn add_pass(&mut self, descriptor: PassDescriptor, kind: PassKind) -> Result<()> {
+        if self.position(&descriptor.name).is_some() {
+            bail!(
+                "The render graph already has a pass called {}",
+                descriptor.name
+            );
+        }
+        self.passes.push((descriptor, kind));
+        self.order = None;
+        Ok(())
+    }
+
+    /// Change the targets of the pass with the same name, keeping what draws it.
+    pub fn set_pass(&mut self, descriptor: PassDescriptor) -> Result<()> {
+        let index = self
+            .position(&descriptor.name)
+            .with_context(|| format!("The render graph has no pass called {}", descriptor.name))?;
+        self.passes[index].0 = descriptor;
+        self.order = None;
+        Ok(())
+    }
+
+    pub fn remove_pass(&mut self, name: &str) -> Option<(PassDescriptor, PassKind)> {
+        let index = self.position(name)?;
+        self.order = None;
+        Some(self.passes.remove(index))
+    }
+
+    pub fn pass(&self, name: &str) -> Option<&PassDescriptor> {
+        self.position(name).map(|index| &self.passes[index].0)
+    }
+
+    /// The passes in the order they were added.
+    pub fn passes(&self) -> impl Iterator<Item = &PassDescriptor> {
+        self.passes.iter().map(|(descriptor, _)| descriptor)
+    }
+
+    /// Work out the order of the passes if they have changed, create the textures of the
+    /// targets they use at the current frame size, then let every GraphPass prepare.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        depth: &DepthTexture,
+    ) -> Result<()> {
+        if self.order.is_none() {
+            self.order = Some(self.sort()?);
+        }
+
+        let used: HashSet<&str> = self
+            .passes
+            .iter()
+            .flat_map(|(descriptor, _)| {
+                descriptor
+                    .outputs()
+                    .chain(descriptor.inputs.iter().map(String::as_str))
+            })
+            .collect();
+        let mut targets_changed = false;
+        for (name, target) in self.targets.iter_mut() {
+            if !used.contains(name.as_str()) {
+                target.texture = None;
+                continue;
+            }
+            let size = target.descriptor.size(config.width, config.height);
+            let format = target.descriptor.format.unwrap_or(config.format);
+            if target
+                .texture
+                .as_ref()
+                .is_some_and(|texture| texture.size == size && texture.format == format)
+            {
+                continue;
+            }
+            target.texture = Some(create_transient(device, name, size, format));
+            targets_changed = true;
+        }
+
+        let context = PassContext {
+            device,
+            queue,
+            targets: &self.targets,
+            depth,
+            targets_changed,
+        };
+        for (_, kind) in self.passes.iter_mut() {
+            if let PassKind::Custom(pass) = kind {
+                pass.prepare(&context);
+            }
+        }
+        Ok(())
+    }
+
+    /// The passes in the order they run, see prepare.
+    pub(crate) fn ordered(&self) -> impl Iterator<Item = &(PassDescriptor, PassKind)> {
+        self.order
+            .iter()
+            .flatten()
+            .map(|&index| &self.passes[index])
+    }
+
+    /// Begin a render pass drawing into the pass's targets.
+    pub(crate) fn begin<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        descriptor: &PassDescriptor,
+        frame: &'a wgpu::TextureView,
+        depth: &'a DepthTexture,
+    ) -> wgpu::RenderPass<'a> {
+        let colour_attachments: Vec<_> = descriptor
+            .colour
+            .iter()
+            .map(|attachment| {
+                Some(wgpu::RenderPassColorAttachment {
+                    view: self.view(&attachment.target, frame),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: attachment.load,
+                        store: attachment.store,
+                    },
+                })
+            })
+            .collect();
+        let depth_stencil_attachment = descriptor.depth.as_ref().map(|attachment| {
+            if attachment.target == DEPTH {
+                return depth.attachment(attachment.load, attachment.store);
+            }
+            wgpu::RenderPassDepthStencilAttachment {
+                view: self.view(&attachment.target, frame),
+                depth_ops: Some(wgpu::Operations {
+                    load: attachment.load,
+                    store: attachment.store,
+                }),
+                stencil_ops: None,
+            }
+        });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&descriptor.name),
+            color_attachments: &colour_attachments,
+            depth_stencil_attachment,
+        })
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.passes
+            .iter()
+            .position(|(descriptor, _)| descriptor.name == name)
+    }
+
+    // every target has been created by prepare, which checks they exist
+    fn view<'a>(&'a self, target: &str, frame: &'a wgpu::TextureView) -> &'a wgpu::TextureView {
+        if target == FRAME {
+            return frame;
+        }
+        &self.targets[target]
+            .texture
+            .as_ref()
+            .expect("render graph targets are created by prepare")
+            .view
+    }
+
+    // Order the passes so each runs after the passes it depends on, otherwise in the order
+    // they were added
+    fn sort(&self) -> Result<Vec<usize>> {
+        let mut writers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, (descriptor, _)) in self.passes.iter().enumerate() {
+            if descriptor.colour.is_empty() && descriptor.depth.is_none() {
+                bail!("Pass {} doesn't draw into any target", descriptor.name);
+            }
+            for target in descriptor
+                .outputs()
+                .chain(descriptor.inputs.iter().map(String::as_str))
+            {
+                if target != FRAME && target != DEPTH && !self.targets.contains_key(target) {
+                    bail!(
+                        "Pass {} uses target {}, which hasn't been added",
+                        descriptor.name,
+                        target
+                    );
+                }
+            }
+            for target in descriptor.outputs() {
+                writers.entry(target).or_default().push(index);
+            }
+        }
+
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); self.passes.len()];
+        for passes in writers.values() {
+            for pair in passes.windows(2) {
+                dependencies[pair[1]].push(pair[0]);
+            }
+        }
+        for (index, (descriptor, _)) in self.passes.iter().enumerate() {
+            for input in &descriptor.inputs {
+                if input == FRAME {
+                    bail!(
+                        "Pass {} can't sample the frame it is drawing",
+                        descriptor.name
+                    );
+                }
+                let passes = writers.get(input.as_str()).map_or(&[][..], Vec::as_slice);
+                if passes.contains(&index) {
+                    bail!(
+                        "Pass {} samples target {} while drawing into it",
+                        descriptor.name,
+                        input
+                    );
+                }
+                if passes.is_empty() && input != DEPTH {
+                    bail!(
+                        "Pass {} samples target {}, which no pass draws into",
+                        descriptor.name,
+                        input
+                    );
+                }
+                dependencies[index].extend_from_slice(passes);
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut done = vec![false; self.passes.len()];
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len()).find(|&index| {
+                !done[index]
+                    && dependencies[index]
+                        .iter()
+                        .all(|&dependency| done[dependency])
+            });
+            let Some(next) = next else {
+                let waiting: Vec<&str> = (0..self.passes.len())
+                    .filter(|&index| !done[index])
+                    .map(|index| self.passes[index].0.name.as_str())
+                    .collect();
+                bail!("Passes {} depend on each other", waiting.join(", "));
+            };
+            done[next] = true;
+            order.push(next);
+        }
+        Ok(order)
+    }
+}
+
+fn create_transient(
+    device: &wgpu::Device,
+    name: &str,
+    (width, height): (u32, u32),
+    format: wgpu::TextureFormat,
+) -> TransientTexture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(name),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    TransientTexture {
+        view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        _texture: texture,
+        size: (width, height),
+        format,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::LoadOp::{Clear, Load};
+
+    const BLACK: wgpu::Color = wgpu::Color::BLACK;
+
+    struct Nothing;
+
+    impl GraphPass for Nothing {
+        fn draw<'a>(&'a self, _render_pass: &mut wgpu::RenderPass<'a>) {}
+    }
+
+    fn custom() -> PassKind {
+        PassKind::Custom(Box::new(Nothing))
+    }
+
+    // the passes the engine starts with, see render_data::engine_graph
+    fn engine_graph() -> RenderGraph {
+        let mut graph = RenderGraph::new();
+        graph
+            .add_pass(
+                PassDescriptor::new("world")
+                    .colour(FRAME, Clear(BLACK), true)
+                    .depth(DEPTH, Clear(1.0), true),
+                PassKind::World,
+            )
+            .unwrap();
+        graph
+            .add_pass(
+                PassDescriptor::new("sprites")
+                    .colour(FRAME, Load, true)
+                    .depth(DEPTH, Load, false),
+                PassKind::Sprites,
+            )
+            .unwrap();
+        graph
+    }
+
+    fn order(graph: &RenderGraph) -> Vec<&str> {
+        graph
+            .sort()
+            .unwrap()
+            .into_iter()
+            .map(|index| graph.passes[index].0.name())
+            .collect()
+    }
+
+    fn error(graph: &RenderGraph) -> String {
+        graph.sort().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn post_processing_example_is_valid() {
+        // the example at the top of the file
+        let mut graph = engine_graph();
+        graph.add_target("scene", TargetDescriptor::new()).unwrap();
+        graph
+            .set_pass(
+                PassDescriptor::new("world")
+                    .colour("scene", Clear(BLACK), true)
+                    .depth(DEPTH, Clear(1.0), true),
+            )
+            .unwrap();
+        graph
+            .set_pass(
+                PassDescriptor::new("sprites")
+                    .colour("scene", Load, true)
+                    .depth(DEPTH, Load, false),
+            )
+            .unwrap();
+        graph
+            .add_pass(
+                PassDescriptor::new("post")
+                    .input("scene")
+                    .colour(FRAME, Clear(BLACK), true),
+                custom(),
+            )
+            .unwrap();
+        assert_eq!(order(&graph), ["world", "sprites", "post"]);
+    }
+
+    #[test]
+    fn passes_drawing_the_same_target_run_in_the_order_added() {
+        let mut graph = engine_graph();
+        graph
+            .add_pass(
+                PassDescriptor::new("ui").colour(FRAME, Load, true),
+                custom(),
+            )
+            .unwrap();
+        assert_eq!(order(&graph), ["world", "sprites", "ui"]);
+    }
+
+    #[test]
+    fn passes_run_after_the_passes_drawing_their_inputs() {
+        let mut graph = RenderGraph::new();
+        graph
+            .add_target("shadows", TargetDescriptor::new())
+            .unwrap();
+        graph
+            .add_pass(
+                PassDescriptor::new("lighting")
+                    .input("shadows")
+                    .colour(FRAME, Clear(BLACK), true),
+                custom(),
+            )
+            .unwrap();
+        graph
+            .add_pass(
+                PassDescriptor::new("shadow map").colour("shadows", Clear(BLACK), true),
+                custom(),
+            )
+            .unwrap();
+        assert_eq!(order(&graph), ["shadow map", "lighting"]);
+    }
+
+    #[test]
+    fn passes_depending_on_each_other_are_rejected() {
+        let mut graph = RenderGraph::new();
+        graph.add_target("a", TargetDescriptor::new()).unwrap();
+        graph.add_target("b", TargetDescriptor::new()).unwrap();
+        graph
+            .add_pass(
+                PassDescriptor::new("first")
+                    .input("a")
+                    .colour("b", Clear(BLACK), true),
+                custom(),
+            )
+            .unwrap();
+        graph
+            .add_pass(
+                PassDescriptor::new("second")
+                    .input("b")
+                    .colour("a", Clear(BLACK), true),
+                custom(),
+            )
+            .unwrap();
+        assert_eq!(error(&graph), "Passes first, second depend on each other");
+    }
+
+    #[test]
+    fn targets_must_be_added_and_drawn_before_use() {
+        let mut graph = RenderGraph::new();
+        graph
+            .add_pass(
+                PassDescriptor::new("glow").colour("bloom", Clear(BLACK), true),
+                custom(),
+            )
+            .unwrap();
+        assert_eq!(
+            error(&graph),
+            "Pass glow uses target bloom, which hasn't been added"
+        );
+
+        let mut graph = engine_graph();
+        graph.add_target("scene", TargetDescriptor::new()).unwrap();
+        graph
+            .add_pass(
+                PassDescriptor::new("post")
+                    .input("scene")
+                    .colour(FRAME, Load, true),
+                custom(),
+            )
+            .unwrap();
+        assert_eq!(
+            error(&graph),
+            "Pass post samples target scene, which no pass draws into"
+        );
+    }
+
+    #[test]
+    fn passes_cannot_sample_what_they_draw() {
+        let mut graph = RenderGraph::new();
+        graph
+            .add_pass(
+                PassDescriptor::new("feedback")
+                    .input(FRAME)
+                    .colour(FRAME, Load, true),
+                custom(),
+            )
+            .unwrap();
+        assert_eq!(
+            error(&graph),
+            "Pass feedback can't sample the frame it is drawing"
+        );
+
+        let mut graph = RenderGraph::new();
+        graph.add_target("scene", TargetDescriptor::new()).unwrap();
+        graph
+            .add_pass(
+                PassDescriptor::new("blur")
+                    .input("scene")
+                    .colour("scene", Load, true),
+                custom(),
+            )
+            .unwrap();
+        assert_eq!(
+            error(&graph),
+            "Pass blur samples target scene while drawing into it"
+        );
+    }
+
+    #[test]
+    fn passes_and_targets_are_checked_as_they_are_added() {
+        let mut graph = engine_graph();
+        assert!(graph
+            .add_pass(
+                PassDescriptor::new("world").colour(FRAME, Load, true),
+                custom()
+            )
+            .is_err());
+        assert!(graph
+            .set_pass(PassDescriptor::new("missing").colour(FRAME, Load, true))
+            .is_err());
+        assert!(graph.add_target(FRAME, TargetDescriptor::new()).is_err());
+        assert!(graph.add_target(DEPTH, TargetDescriptor::new()).is_err());
+
+        graph
+            .add_pass(PassDescriptor::new("empty"), custom())
+            .unwrap();
+        assert_eq!(error(&graph), "Pass empty doesn't draw into any target");
+    }
+
+    #[test]
+    fn targets_are_scaled_from_the_frame_size() {
+        assert_eq!(TargetDescriptor::new().size(800, 600), (800, 600));
+        assert_eq!(TargetDescriptor::new().scale(0.5).size(801, 3), (401, 2));
+        assert_eq!(TargetDescriptor::new().scale(0.001).size(100, 100), (1, 1));
+    }
+}
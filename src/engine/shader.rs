@@ -0,0 +1,395 @@
+use crate::engine::pipeline::PipelineCache;
+use crate::engine::preprocessor::{PreprocessedShader, Preprocessor};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// Shaders are loaded from WGSL files in a shader directory and added to a PipelineCache by name,
+// after going through the Preprocessor so they can include shared code.
+// Every shader is checked with naga before it is used, so a shader with a mistake in it never
+// reaches wgpu (which would panic). When a shader fails to load, the last version that did
+// compile stays in use and the error is logged with the line it happened on.
+//
+// With hot reloading on (the default in debug builds), poll checks the files for changes and
+// reloads any that changed, rebuilding the pipelines that use them.
+
+// How often poll looks at the files, checking every frame would be wasted work
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+struct ShaderFile {
+    path: PathBuf,
+    // every file the shader was last built from and when each was last modified, None if it
+    // couldn't be read
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ShaderFile {
+    fn changed(&self) -> bool {
+        self.files.iter().any(|(path, modified_at)| {
+            let current = modified(path);
+            current.is_some() && current != *modified_at
+        })
+    }
+
+    fn mark_read(&mut self) {
+        for (path, modified_at) in self.files.iter_mut() {
+            *modified_at = modified(path);
+        }
+    }
+
+    fn set_files(&mut self, shader: &PreprocessedShader) {
+        self.files = shader
+            .files()
+            .iter()
+            .map(|path| (path.clone(), modified(path)))
+            .collect();
+    }
+}
+
+pub struct ShaderRegistry {
+    directory: PathBuf,
+    hot_reload: bool,
+    preprocessor: Preprocessor,
+    // keyed by shader name
+    files: BTreeMap<String, ShaderFile>,
+    last_poll: Option<Instant>,
+}
+
+impl ShaderRegistry {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            hot_reload: cfg!(debug_assertions),
+            preprocessor: Preprocessor::new(),
+            files: BTreeMap::new(),
+            last_poll: None,
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn hot_reload(&self) -> bool {
+        self.hot_reload
+    }
+
+    pub fn set_hot_reload(&mut self, hot_reload: bool) {
+        self.hot_reload = hot_reload;
+    }
+
+    pub fn preprocessor(&self) -> &Preprocessor {
+        &self.preprocessor
+    }
+
+    /// Defines and built in includes changed here apply to shaders loaded afterwards.
+    pub fn preprocessor_mut(&mut self) -> &mut Preprocessor {
+        &mut self.preprocessor
+    }
+
+    /// Load `file_name` from the shader directory and add it to `pipelines` as `name`.
+    /// The file and everything it includes are watched for changes from then on, even if it
+    /// couldn't be loaded.
+    pub fn load(
+        &mut self,
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        name: &str,
+        file_name: &str,
+    ) -> Result<()> {
+        let path = self.directory.join(file_name);
+        let file = self
+            .files
+            .entry(name.to_string())
+            .or_insert_with(|| ShaderFile {
+                files: vec![(path.clone(), None)],
+                path,
+            });
+        file.mark_read();
+        let shader = self.preprocessor.process_file(&file.path)?;
+        file.set_files(&shader);
+        add_shader(device, pipelines, name, &shader)
+    }
+
+    /// Like load, but if the file can't be loaded `fallback` is used instead (eg. a copy of the
+    /// shader built into the executable) and the error is logged.
+    pub fn load_or(
+        &mut self,
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        name: &str,
+        file_name: &str,
+        fallback: &str,
+    ) -> Result<()> {
+        if let Err(error) = self.load(device, pipelines, name, file_name) {
+            log::warn!("{:?}", error.context("Using the built in shader instead"));
+            let shader = self
+                .preprocessor
+                .process(&self.directory.join(file_name), fallback)?;
+            add_shader(device, pipelines, name, &shader)?;
+        }
+        Ok(())
+    }
+
+    /// Reload every shader whose file, or a file it includes, has changed since it was last
+    /// loaded, returning the names of the shaders that were reloaded. Does nothing unless hot
+    /// reloading is on. Shaders that fail to reload keep their last good version and the error
+    /// is logged.
+    pub fn poll(&mut self, device: &wgpu::Device, pipelines: &mut PipelineCache) -> Vec<String> {
+        let now = Instant::now();
+        if !self.hot_reload
+            || self
+                .last_poll
+                .is_some_and(|last_poll| now.duration_since(last_poll) < POLL_INTERVAL)
+        {
+            return Vec::new();
+        }
+        self.last_poll = Some(now);
+
+        let mut reloaded = Vec::new();
+        for (name, file) in self.files.iter_mut() {
+            if !file.changed() {
+                continue;
+            }
+            file.mark_read();
+            let result = self
+                .preprocessor
+                .process_file(&file.path)
+                .and_then(|shader| {
+                    file.set_files(&shader);
+                    add_shader(device, pipelines, name, &shader)
+                });
+            match result {
+                Ok(()) => {
+                    log::info!("Reloaded shader {}", file.path.display());
+                    reloaded.push(name.clone());
+                }
+                Err(error) => log::error!("{:?}", error.context("Keeping the last good shader")),
+            }
+        }
+        reloaded
+    }
+}
+
+/// Parse and validate a preprocessed shader with naga. The error points at the file and line
+/// each problem came from, rather than at the preprocessed source.
+pub fn validate_wgsl(shader: &PreprocessedShader) -> Result<naga::Module> {
+    let source = shader.source();
+    let module = naga::front::wgsl::parse_str(source).map_err(|error| {
+        let labels = error
+            .labels()
+            .map(|(span, label)| (span, label.to_string()));
+        anyhow!(describe(shader, error.message().to_string(), labels))
+    })?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default(),
+    )
+    .validate(&module)
+    .map_err(|error| {
+        // the inner errors carry the detail, eg. which statement of a function is wrong
+        let mut message = error.as_inner().to_string();
+        let mut inner = std::error::Error::source(error.as_inner());
+        while let Some(source) = inner {
+            message += &format!(": {}", source);
+            inner = source.source();
+        }
+        anyhow!(describe(shader, message, error.spans().cloned()))
+    })?;
+    Ok(module)
+}
+
+// The message followed by where each label is in the original files, eg.
+// common.wgsl:12:5: invalid type
+//     let x: vec5<f32> = ...
+fn describe(
+    shader: &PreprocessedShader,
+    message: String,
+    labels: impl Iterator<Item = (naga::Span, String)>,
+) -> String {
+    let source = shader.source();
+    let mut description = message;
+    for (span, label) in labels {
+        let location = span.location(source);
+        let Some((path, line)) = shader.original_line(location.line_number) else {
+            continue;
+        };
+        description += &format!(
+            "\n{}:{}:{}: {}",
+            path.display(),
+            line,
+            location.line_position,
+            label
+        );
+        if let Some(text) = source.lines().nth(location.line_number as usize - 1) {
+            description += &format!("\n    {}", text.trim_end());
+        }
+    }
+    description
+}
+
+// Validate the source, then compile it and swap it into the cache
+fn add_shader(
+    device: &wgpu::Device,
+    pipelines: &mut PipelineCache,
+    name: &str,
+    shader: &PreprocessedShader,
+) -> Result<()> {
+    let path = shader.path();
+    validate_wgsl(shader).with_context(|| format!("Shader {} does not compile", path.display()))?;
+    // anything naga lets through that the device doesn't support is caught here, rather than
+    // by wgpu's default error handler, which panics
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(name),
+        source: wgpu::ShaderSource::Wgsl(shader.source().into()),
+    });
+    if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+        bail!(
+            "Shader {} was rejected by the device: {}",
+            path.display(),
+            error
+        );
+    }
+    pipelines.set_shader(device, name, module)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::pipeline::tests::headless_device;
+    use std::fs::File;
+
+    const VALID: &str = "
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(index), 0.0, 0.0, 1.0);
+}
+";
+
+    fn preprocess(source: &str) -> PreprocessedShader {
+        Preprocessor::new()
+            .process(Path::new("virtual/main.wgsl"), source)
+            .unwrap()
+    }
+
+    // a fresh directory for each test, so they can run side by side
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("shader-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    // move the modification time on, rather than waiting for the clock to tick
+    fn touch(path: &Path, seconds: u64) {
+        let time = SystemTime::now() + Duration::from_secs(seconds);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn valid_wgsl_gives_a_module() {
+        let module = validate_wgsl(&preprocess(VALID)).unwrap();
+        assert!(module
+            .entry_points
+            .iter()
+            .any(|entry| entry.name == "vs_main"));
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_line() {
+        let source = "fn main() {\n    let x: vec5<f32> = 1.0;\n}\n";
+        let message = validate_wgsl(&preprocess(source)).unwrap_err().to_string();
+        // vec5 isn't a type, so naga reads it as a variable and stops at the <
+        assert!(
+            message.contains("\nvirtual/main.wgsl:2:16: "),
+            "{}",
+            message
+        );
+        assert!(message.contains("let x: vec5<f32> = 1.0;"), "{}", message);
+    }
+
+    #[test]
+    fn validation_errors_point_at_the_line() {
+        // parses, but a float can't be returned as a u32
+        let source = "fn main() -> u32 {\n    return 1.0;\n}\n";
+        let message = validate_wgsl(&preprocess(source)).unwrap_err().to_string();
+        assert!(message.contains("\nvirtual/main.wgsl:2:"), "{}", message);
+        assert!(message.contains("return 1.0;"), "{}", message);
+    }
+
+    #[test]
+    fn files_are_changed_until_marked_read() {
+        let directory = temp_directory("tracking");
+        let path = directory.join("main.wgsl");
+        std::fs::write(&path, VALID).unwrap();
+        let mut file = ShaderFile {
+            files: vec![(path.clone(), None)],
+            path: path.clone(),
+        };
+        // never read, so it has changed
+        assert!(file.changed());
+        file.mark_read();
+        assert!(!file.changed());
+        touch(&path, 10);
+        assert!(file.changed());
+        file.mark_read();
+        assert!(!file.changed());
+        // a missing file isn't a change, there is nothing to reload
+        std::fs::remove_file(&path).unwrap();
+        assert!(!file.changed());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn poll_reloads_changed_shaders() {
+        let Some(device) = headless_device() else {
+            eprintln!("skipped, no fallback adapter is available");
+            return;
+        };
+        let directory = temp_directory("poll");
+        let path = directory.join("main.wgsl");
+        std::fs::write(&path, VALID).unwrap();
+        let mut pipelines = PipelineCache::new();
+        let mut registry = ShaderRegistry::new(&directory);
+        registry.set_hot_reload(true);
+        registry
+            .load(&device, &mut pipelines, "main", "main.wgsl")
+            .unwrap();
+        assert!(pipelines.shader("main").is_some());
+        assert!(registry.poll(&device, &mut pipelines).is_empty());
+
+        touch(&path, 10);
+        // too soon after the last poll to look at the files
+        assert!(registry.poll(&device, &mut pipelines).is_empty());
+        registry.last_poll = None;
+        assert_eq!(registry.poll(&device, &mut pipelines), ["main"]);
+        registry.last_poll = None;
+        assert!(registry.poll(&device, &mut pipelines).is_empty());
+
+        // a broken shader is marked read but not reloaded
+        std::fs::write(&path, "fn main( {").unwrap();
+        touch(&path, 20);
+        registry.last_poll = None;
+        assert!(registry.poll(&device, &mut pipelines).is_empty());
+        assert!(!registry.files["main"].changed());
+
+        registry.set_hot_reload(false);
+        touch(&path, 30);
+        registry.last_poll = None;
+        assert!(registry.poll(&device, &mut pipelines).is_empty());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
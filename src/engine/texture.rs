@@ -1,10 +1,10 @@
 use crate::engine::primitives::vertex::Vertex2D;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use rand::Rng;
+use std::collections::BTreeMap;
+use std::rc::Rc;
 use std::{collections::HashSet, fs::File, io, io::BufReader, io::Read, sync::Mutex};
 
-use image::GenericImageView;
-
 #[derive(Debug)]
 pub struct Texture2D {
     diffuse_texture: wgpu::Texture,
@@ -28,8 +28,22 @@ impl Texture2D {
         buf_reader.read_to_end(&mut byte_vec)?;
         let bytes = byte_vec.as_slice();
         let image = image::load_from_memory(bytes).unwrap();
-        let rgba_buffer = image.to_rgba8();
-        let dimensions = image.dimensions();
+        Ok(Texture2D::from_image(
+            image.to_rgba8(),
+            queue,
+            device,
+            bind_group_layout,
+        ))
+    }
+
+    /// Create a texture from an image already in memory.
+    pub fn from_image(
+        rgba_buffer: image::RgbaImage,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let dimensions = rgba_buffer.dimensions();
         let texture_dimensions = wgpu::Extent3d {
             width: dimensions.0,
             height: dimensions.1,
@@ -93,14 +107,14 @@ impl Texture2D {
                 },
             ],
         });
-        Ok(Self {
+        Self {
             diffuse_texture,
             sampler: texture_sampler,
             view: texture_view,
             bind_group,
             rgba_buffer,
             dimensions: texture_dimensions,
-        })
+        }
     }
 
     pub fn texture(&self) -> &wgpu::Texture {
@@ -127,3 +141,244 @@ impl Texture2D {
         self.dimensions
     }
 }
+
+/// Textures by id, shared between everything drawn with them so each image is only loaded and
+/// uploaded once, eg. the walls, sprites and floors of a map.
+#[derive(Clone, Debug, Default)]
+pub struct TextureStore {
+    textures: BTreeMap<u32, Rc<Texture2D>>,
+}
+
+impl TextureStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load every texture in a texture table of ids and paths, eg. Map::textures.
+    pub fn load(
+        paths: &BTreeMap<u32, String>,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let mut store = Self::new();
+        for (id, path) in paths {
+            let texture = Texture2D::new(path, queue, device, bind_group_layout)
+                .with_context(|| format!("Could not load texture {} ({})", id, path))?;
+            store.insert(*id, texture);
+        }
+        Ok(store)
+    }
+
+    pub fn insert(&mut self, id: u32, texture: Texture2D) {
+        self.textures.insert(id, Rc::new(texture));
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Rc<Texture2D>> {
+        self.textures.get(&id)
+    }
+
+    /// Every texture in order of id.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Rc<Texture2D>)> {
+        self.textures.iter().map(|(id, texture)| (*id, texture))
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+}
+
+/// A stack of equally sized textures which shaders select between by layer, used where the
+/// texture changes per fragment rather than per draw (eg. floor casting).
+#[derive(Debug)]
+pub struct Texture2DArray {
+    texture: wgpu::Texture,
+    sampler: wgpu::Sampler,
+    view: wgpu::TextureView,
+    dimensions: wgpu::Extent3d,
+}
+
+impl Texture2DArray {
+    /// Layers are created in the order of `images`. Every layer takes the size of the first image,
+    /// other images are resized to fit. With no images a transparent layer is created so the
+    /// array can still be bound.
+    pub fn new(images: &[image::RgbaImage], queue: &wgpu::Queue, device: &wgpu::Device) -> Self {
+        let empty = [image::RgbaImage::new(1, 1)];
+        let images = if images.is_empty() { &empty } else { images };
+        let (width, height) = images[0].dimensions();
+        // the GL backend treats textures with a single layer as plain 2D textures, which sample
+        // as black through an array view, so there are always at least two layers
+        let dimensions = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: images.len().max(2) as u32,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture Array"),
+            size: dimensions,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            view_formats: &[],
+        });
+
+        for (layer, image) in images.iter().enumerate() {
+            let resized;
+            let image = if image.dimensions() == (width, height) {
+                image
+            } else {
+                resized = image::imageops::resize(
+                    image,
+                    width,
+                    height,
+                    image::imageops::FilterType::Nearest,
+                );
+                &resized
+            };
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                image,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * width),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Texture Array Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            sampler,
+            view,
+            dimensions,
+        }
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    /// Width and height of every layer, with the number of layers as depth_or_array_layers
+    pub fn dimensions(&self) -> wgpu::Extent3d {
+        self.dimensions
+    }
+}
+
+/// The depth buffer of a render target, it has to be the same size as the target and is
+/// recreated along with it.
+#[derive(Debug)]
+pub struct DepthTexture {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    format: wgpu::TextureFormat,
+}
+
+impl DepthTexture {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            texture,
+            view,
+            format,
+        }
+    }
+
+    /// The attachment for a render pass drawing with this depth buffer. The stencil, for
+    /// formats which have one, is cleared to 0 when the depth is cleared.
+    pub fn attachment(
+        &self,
+        load: wgpu::LoadOp<f32>,
+        store: bool,
+    ) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        let stencil_load = match load {
+            wgpu::LoadOp::Clear(_) => wgpu::LoadOp::Clear(0),
+            wgpu::LoadOp::Load => wgpu::LoadOp::Load,
+        };
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(wgpu::Operations { load, store }),
+            stencil_ops: self
+                .format
+                .has_stencil_aspect()
+                .then_some(wgpu::Operations {
+                    load: stencil_load,
+                    store,
+                }),
+        }
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+}
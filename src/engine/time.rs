@@ -0,0 +1,232 @@
+use std::time::{Duration, Instant};
+
+// The simulation runs at a fixed rate, independent of how fast frames are drawn. Real time is
+// accumulated every frame and spent on as many fixed updates as fit into it, anything left over
+// carries on to the next frame. As updates and frames don't line up, rendering can blend the
+// last two updates together using alpha, the fraction of an update left in the accumulator.
+
+// Frames longer than this are cut short, so a stall (eg. dragging the window) doesn't leave a
+// backlog of updates that takes even longer to run
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+pub const DEFAULT_UPDATES_PER_SECOND: u32 = 60;
+
+/// Timing information for update and render code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Time {
+    delta: f32,
+    frame_delta: f32,
+    elapsed: f64,
+    frame_count: u64,
+    update_count: u64,
+    alpha: f32,
+}
+
+impl Time {
+    /// Seconds simulated by each update, the fixed timestep.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Real seconds since the last frame, after clamping long frames.
+    pub fn frame_delta(&self) -> f32 {
+        self.frame_delta
+    }
+
+    /// Seconds simulated since the loop started, a multiple of delta.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Frames started since the loop started.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Fixed updates run since the loop started.
+    pub fn update_count(&self) -> u64 {
+        self.update_count
+    }
+
+    /// How far between the last update and the next one the current frame is, from 0 to 1.
+    /// Used to interpolate between the previous and current state when rendering.
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            delta: 1.0 / DEFAULT_UPDATES_PER_SECOND as f32,
+            frame_delta: 0.0,
+            elapsed: 0.0,
+            frame_count: 0,
+            update_count: 0,
+            alpha: 0.0,
+        }
+    }
+}
+
+/// Decides how many fixed updates to run each frame. Each frame call begin_frame, then run an
+/// update for as long as update() returns true, then render.
+pub struct GameLoop {
+    time: Time,
+    timestep: Duration,
+    accumulator: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl GameLoop {
+    pub fn new(updates_per_second: u32) -> Self {
+        let timestep = Duration::from_secs(1) / updates_per_second.max(1);
+        Self {
+            time: Time {
+                delta: timestep.as_secs_f32(),
+                ..Time::default()
+            },
+            timestep,
+            accumulator: Duration::ZERO,
+            last_frame: None,
+        }
+    }
+
+    /// Add the real time since the last frame to the accumulator.
+    /// The first frame doesn't run any updates, as there is no previous frame to measure from.
+    pub fn begin_frame(&mut self, now: Instant) {
+        let frame_time = match self.last_frame {
+            Some(last_frame) => now
+                .saturating_duration_since(last_frame)
+                .min(MAX_FRAME_TIME),
+            None => Duration::ZERO,
+        };
+        self.last_frame = Some(now);
+        self.accumulator += frame_time;
+        self.time.frame_delta = frame_time.as_secs_f32();
+        self.time.frame_count += 1;
+        self.update_alpha();
+    }
+
+    /// Take one timestep from the accumulator, returns false once there isn't a whole one left.
+    pub fn update(&mut self) -> bool {
+        if self.accumulator < self.timestep {
+            return false;
+        }
+        self.accumulator -= self.timestep;
+        self.time.elapsed += self.timestep.as_secs_f64();
+        self.time.update_count += 1;
+        self.update_alpha();
+        true
+    }
+
+    pub fn time(&self) -> &Time {
+        &self.time
+    }
+
+    pub fn timestep(&self) -> Duration {
+        self.timestep
+    }
+
+    fn update_alpha(&mut self) {
+        self.time.alpha = self.accumulator.as_secs_f32() / self.timestep.as_secs_f32();
+    }
+}
+
+impl Default for GameLoop {
+    fn default() -> Self {
+        GameLoop::new(DEFAULT_UPDATES_PER_SECOND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // 50 updates per second is a whole number of milliseconds per update
+    const UPDATES_PER_SECOND: u32 = 50;
+
+    // Start a frame `milliseconds` after the last one, returning how many updates it ran
+    fn frame(game_loop: &mut GameLoop, now: &mut Instant, milliseconds: u64) -> u32 {
+        *now += Duration::from_millis(milliseconds);
+        game_loop.begin_frame(*now);
+        let mut updates = 0;
+        while game_loop.update() {
+            updates += 1;
+        }
+        updates
+    }
+
+    fn started() -> (GameLoop, Instant) {
+        let mut game_loop = GameLoop::new(UPDATES_PER_SECOND);
+        let mut now = Instant::now();
+        assert_eq!(frame(&mut game_loop, &mut now, 0), 0);
+        (game_loop, now)
+    }
+
+    #[test]
+    fn first_frame_runs_no_updates() {
+        let mut game_loop = GameLoop::new(UPDATES_PER_SECOND);
+        let mut now = Instant::now();
+        assert_eq!(frame(&mut game_loop, &mut now, 1000), 0);
+        assert_eq!(game_loop.time().frame_count(), 1);
+        assert_eq!(game_loop.time().alpha(), 0.0);
+    }
+
+    #[test]
+    fn leftover_time_carries_over() {
+        let (mut game_loop, mut now) = started();
+        assert_eq!(game_loop.timestep(), Duration::from_millis(20));
+        assert_eq!(frame(&mut game_loop, &mut now, 50), 2);
+        assert!((game_loop.time().alpha() - 0.5).abs() < 1e-5);
+        // the 10ms left over makes this 30ms frame worth two updates
+        assert_eq!(frame(&mut game_loop, &mut now, 30), 2);
+        assert_eq!(game_loop.time().alpha(), 0.0);
+        assert_eq!(frame(&mut game_loop, &mut now, 5), 0);
+        assert!((game_loop.time().alpha() - 0.25).abs() < 1e-5);
+        assert_eq!(game_loop.time().update_count(), 4);
+        assert!((game_loop.time().elapsed() - 0.08).abs() < 1e-9);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let (mut game_loop, mut now) = started();
+        // a two second stall only runs 250ms worth of updates
+        assert_eq!(frame(&mut game_loop, &mut now, 2000), 12);
+        assert_eq!(game_loop.time().frame_delta(), 0.25);
+        assert!((game_loop.time().alpha() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn alpha_rises_as_the_next_update_nears() {
+        let (mut game_loop, mut now) = started();
+        now += Duration::from_millis(15);
+        game_loop.begin_frame(now);
+        assert!((game_loop.time().alpha() - 0.75).abs() < 1e-5);
+        // alpha goes above 1 until the updates due have been run
+        now += Duration::from_millis(15);
+        game_loop.begin_frame(now);
+        assert!((game_loop.time().alpha() - 1.5).abs() < 1e-5);
+        assert!(game_loop.update());
+        assert!((game_loop.time().alpha() - 0.5).abs() < 1e-5);
+        assert!(!game_loop.update());
+    }
+
+    proptest! {
+        #[test]
+        fn updates_follow_clamped_real_time(frames in prop::collection::vec(0u64..400, 1..50)) {
+            let (mut game_loop, mut now) = started();
+            let mut updates = 0;
+            for milliseconds in &frames {
+                updates += frame(&mut game_loop, &mut now, *milliseconds);
+                let alpha = game_loop.time().alpha();
+                prop_assert!((0.0..1.0).contains(&alpha));
+            }
+            let total: u64 = frames.iter().map(|milliseconds| (*milliseconds).min(250)).sum();
+            prop_assert_eq!(updates as u64, total / 20);
+            prop_assert_eq!(game_loop.time().update_count(), updates as u64);
+            let elapsed = updates as f64 * game_loop.timestep().as_secs_f64();
+            prop_assert!((game_loop.time().elapsed() - elapsed).abs() < 1e-9);
+        }
+    }
+}
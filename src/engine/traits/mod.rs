@@ -1,2 +1,3 @@
+pub mod tile_map;
 pub mod update_entity;
 pub mod update_textures;
@@ -0,0 +1,29 @@
+use crate::engine::primitives::vector::Vector2;
+
+/// The part of a tile which stops rays, for walls that don't fill their whole tile such as doors
+/// and push walls part way through moving. In map coordinates, `min` and `max` may be equal on
+/// one axis for walls with no thickness.
+#[derive(Clone, Copy)]
+pub struct WallBounds {
+    pub min: Vector2<f32>,
+    pub max: Vector2<f32>,
+}
+
+/// A 2D grid of tiles which rays can be cast through.
+/// Coordinates are signed so rays leaving the grid can be checked without wrapping around.
+pub trait TileMap {
+    /// Whether (x, y) lies inside the grid.
+    fn contains(&self, x: i32, y: i32) -> bool;
+
+    /// Whether the tile at (x, y) stops rays.
+    fn is_solid(&self, x: i32, y: i32) -> bool;
+
+    /// The texture id used to draw the walls of the tile at (x, y).
+    fn wall_texture(&self, x: i32, y: i32) -> u32;
+
+    /// The wall within a tile that isn't solid, if it has one. Rays passing through the tile
+    /// without touching the bounds carry on.
+    fn partial_wall(&self, _x: i32, _y: i32) -> Option<WallBounds> {
+        None
+    }
+}
@@ -0,0 +1,253 @@
+use crate::engine::primitives::vector::Vector2;
+use crate::engine::traits::tile_map::WallBounds;
+use serde::{Deserialize, Serialize};
+
+// Doors are thin walls across the middle of their tile, recessed from the walls either side of
+// them. Opening a door slides it sideways into the wall until it is out of the way.
+// Push walls are secret walls that look like any other wall, but slide one tile away from the
+// player when pushed. While moving, a push wall covers part of the tile it came from and part of
+// the tile it is moving into.
+//
+// Both are part of the map and are saved with it, so a level can be saved part way through.
+
+// Tiles per second
+pub const DOOR_SPEED: f32 = 1.0;
+pub const PUSH_WALL_SPEED: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DoorState {
+    #[default]
+    Closed,
+    Opening,
+    Open,
+    Closing,
+}
+
+/// Which way a door runs across its tile. Doors are placed between the walls either side of them,
+/// so a door with walls to its left and right runs along X and is walked through along Y.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DoorAxis {
+    #[default]
+    X,
+    Y,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Door {
+    pub x: i32,
+    pub y: i32,
+    #[serde(default)]
+    pub axis: DoorAxis,
+    #[serde(default)]
+    pub state: DoorState,
+    // how far the door has slid into the wall, 0 is closed and 1 is open
+    #[serde(default)]
+    pub open: f32,
+}
+
+impl Door {
+    pub fn new(x: i32, y: i32, axis: DoorAxis) -> Self {
+        Self {
+            x,
+            y,
+            axis,
+            state: DoorState::Closed,
+            open: 0.0,
+        }
+    }
+
+    /// Start opening a closed door, or closing an open one. Doors that are already moving
+    /// turn around.
+    pub fn toggle(&mut self) {
+        self.state = match self.state {
+            DoorState::Closed | DoorState::Closing => DoorState::Opening,
+            DoorState::Open | DoorState::Opening => DoorState::Closing,
+        };
+    }
+
+    /// Move the door along by `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        match self.state {
+            DoorState::Opening => {
+                self.open += DOOR_SPEED * dt;
+                if self.open >= 1.0 {
+                    self.open = 1.0;
+                    self.state = DoorState::Open;
+                }
+            }
+            DoorState::Closing => {
+                self.open -= DOOR_SPEED * dt;
+                if self.open <= 0.0 {
+                    self.open = 0.0;
+                    self.state = DoorState::Closed;
+                }
+            }
+            DoorState::Closed | DoorState::Open => (),
+        }
+    }
+
+    /// The part of the tile covered by the door, None once it is fully open.
+    pub fn bounds(&self) -> Option<WallBounds> {
+        if self.open >= 1.0 {
+            return None;
+        }
+        let (x, y) = (self.x as f32, self.y as f32);
+        // the door slides towards +x or +y, the start of its bounds moves with it so the
+        // texture slides too
+        Some(match self.axis {
+            DoorAxis::X => WallBounds {
+                min: Vector2 {
+                    x: x + self.open,
+                    y: y + 0.5,
+                },
+                max: Vector2 {
+                    x: x + 1.0,
+                    y: y + 0.5,
+                },
+            },
+            DoorAxis::Y => WallBounds {
+                min: Vector2 {
+                    x: x + 0.5,
+                    y: y + self.open,
+                },
+                max: Vector2 {
+                    x: x + 0.5,
+                    y: y + 1.0,
+                },
+            },
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PushWall {
+    // the tile the wall is moving out of
+    pub x: i32,
+    pub y: i32,
+    // one tile along x or y
+    pub step_x: i32,
+    pub step_y: i32,
+    // texture id of the wall
+    pub wall: u32,
+    // how far the wall has moved towards the next tile, from 0 to 1
+    #[serde(default)]
+    pub offset: f32,
+}
+
+impl PushWall {
+    /// The tile the wall is moving into.
+    pub fn target(&self) -> (i32, i32) {
+        (self.x + self.step_x, self.y + self.step_y)
+    }
+
+    /// Whether the wall currently covers part of the tile at (x, y).
+    pub fn covers(&self, x: i32, y: i32) -> bool {
+        (x, y) == (self.x, self.y) || (x, y) == self.target()
+    }
+
+    pub fn bounds(&self) -> WallBounds {
+        let min = Vector2 {
+            x: self.x as f32 + self.step_x as f32 * self.offset,
+            y: self.y as f32 + self.step_y as f32 * self.offset,
+        };
+        WallBounds {
+            min,
+            max: Vector2 {
+                x: min.x + 1.0,
+                y: min.y + 1.0,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opening_door() -> Door {
+        let mut door = Door::new(2, 3, DoorAxis::X);
+        door.toggle();
+        door
+    }
+
+    #[test]
+    fn door_opens_at_door_speed() {
+        let mut door = opening_door();
+        assert_eq!(door.state, DoorState::Opening);
+        door.update(0.25 / DOOR_SPEED);
+        assert_eq!(door.open, 0.25);
+        assert_eq!(door.state, DoorState::Opening);
+        // the door slides towards +x, taking the start of its bounds with it
+        let bounds = door.bounds().unwrap();
+        assert_eq!((bounds.min.x, bounds.min.y), (2.25, 3.5));
+        assert_eq!((bounds.max.x, bounds.max.y), (3.0, 3.5));
+    }
+
+    #[test]
+    fn door_clamps_when_fully_open() {
+        let mut door = opening_door();
+        door.update(5.0 / DOOR_SPEED);
+        assert_eq!(door.open, 1.0);
+        assert_eq!(door.state, DoorState::Open);
+        assert!(door.bounds().is_none());
+        // open doors stay where they are until toggled
+        door.update(1.0);
+        assert_eq!((door.state, door.open), (DoorState::Open, 1.0));
+    }
+
+    #[test]
+    fn door_clamps_when_fully_closed() {
+        let mut door = opening_door();
+        door.update(5.0 / DOOR_SPEED);
+        door.toggle();
+        assert_eq!(door.state, DoorState::Closing);
+        door.update(5.0 / DOOR_SPEED);
+        assert_eq!(door.open, 0.0);
+        assert_eq!(door.state, DoorState::Closed);
+        door.update(1.0);
+        assert_eq!((door.state, door.open), (DoorState::Closed, 0.0));
+    }
+
+    #[test]
+    fn door_turns_around_mid_travel() {
+        let mut door = opening_door();
+        door.update(0.5 / DOOR_SPEED);
+        door.toggle();
+        assert_eq!(door.state, DoorState::Closing);
+        // it closes from where it had got to rather than jumping to either end
+        door.update(0.25 / DOOR_SPEED);
+        assert_eq!(door.open, 0.25);
+        door.toggle();
+        assert_eq!(door.state, DoorState::Opening);
+        door.update(0.25 / DOOR_SPEED);
+        assert_eq!(door.open, 0.5);
+    }
+
+    #[test]
+    fn door_along_y_slides_along_y() {
+        let mut door = Door::new(2, 3, DoorAxis::Y);
+        door.toggle();
+        door.update(0.5 / DOOR_SPEED);
+        let bounds = door.bounds().unwrap();
+        assert_eq!((bounds.min.x, bounds.min.y), (2.5, 3.5));
+        assert_eq!((bounds.max.x, bounds.max.y), (2.5, 4.0));
+    }
+
+    #[test]
+    fn push_wall_bounds_move_with_offset() {
+        let push_wall = PushWall {
+            x: 4,
+            y: 2,
+            step_x: 0,
+            step_y: -1,
+            wall: 3,
+            offset: 0.25,
+        };
+        assert_eq!(push_wall.target(), (4, 1));
+        assert!(push_wall.covers(4, 2) && push_wall.covers(4, 1));
+        assert!(!push_wall.covers(4, 3));
+        let bounds = push_wall.bounds();
+        assert_eq!((bounds.min.x, bounds.min.y), (4.0, 1.75));
+        assert_eq!((bounds.max.x, bounds.max.y), (5.0, 2.75));
+    }
+}
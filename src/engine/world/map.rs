@@ -0,0 +1,517 @@
+use crate::engine::traits::tile_map::{TileMap, WallBounds};
+use crate::engine::world::door::{Door, DoorAxis, DoorState, PushWall, PUSH_WALL_SPEED};
+use crate::engine::world::map_loader::{self, MapLoadError};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TileFlags {
+    // blocks rays and movement
+    pub solid: bool,
+    // a sliding door across the middle of the tile, see Door
+    pub door: bool,
+    // can be seen through, eg. windows and bars
+    pub transparent: bool,
+    // a secret wall which moves when pushed, see PushWall
+    pub push: bool,
+}
+
+/// A single cell of the map. Texture ids refer to the map's texture table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tile {
+    #[serde(default)]
+    pub wall: u32,
+    #[serde(default)]
+    pub floor: u32,
+    #[serde(default)]
+    pub ceiling: u32,
+    #[serde(default)]
+    pub flags: TileFlags,
+}
+
+/// Where the player starts, in tile units. Angle is in degrees, see PlayerPose::new.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub angle: f32,
+}
+
+/// A level made up of a grid of tiles, stored row by row.
+/// Maps can be loaded from an ASCII grid with a legend, or from JSON, see map_loader.
+/// Every tile flagged as a door has a Door, which keeps track of how far open it is.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "MapData")]
+pub struct Map {
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+    spawn: SpawnPoint,
+    textures: BTreeMap<u32, String>,
+    doors: Vec<Door>,
+    // push walls that are currently moving
+    push_walls: Vec<PushWall>,
+}
+
+// Maps are deserialised through this so that they are validated before use
+#[derive(Deserialize)]
+pub(crate) struct MapData {
+    pub width: u32,
+    pub height: u32,
+    pub tiles: Vec<Tile>,
+    pub spawn: SpawnPoint,
+    #[serde(default)]
+    pub textures: BTreeMap<u32, String>,
+    #[serde(default)]
+    pub doors: Vec<Door>,
+    #[serde(default)]
+    pub push_walls: Vec<PushWall>,
+}
+
+impl TryFrom<MapData> for Map {
+    type Error = String;
+
+    fn try_from(data: MapData) -> std::result::Result<Self, Self::Error> {
+        let mut map = Map::new(
+            data.width,
+            data.height,
+            data.tiles,
+            data.spawn,
+            data.textures,
+        )?;
+        map.restore_doors(data.doors)?;
+        map.restore_push_walls(data.push_walls)?;
+        Ok(map)
+    }
+}
+
+impl Map {
+    /// Fails if the number of tiles doesn't match the size, or the spawn point is outside the map.
+    pub fn new(
+        width: u32,
+        height: u32,
+        tiles: Vec<Tile>,
+        spawn: SpawnPoint,
+        textures: BTreeMap<u32, String>,
+    ) -> std::result::Result<Self, String> {
+        Map::check_tiles(width, height, &tiles)?;
+        Map::check_spawn(width, height, &spawn)?;
+        let mut map = Self {
+            width,
+            height,
+            tiles,
+            spawn,
+            textures,
+            doors: Vec::new(),
+            push_walls: Vec::new(),
+        };
+        map.doors = map.find_doors();
+        Ok(map)
+    }
+
+    // A closed door for every door tile. Doors run between the walls either side of them.
+    fn find_doors(&self) -> Vec<Door> {
+        let mut doors = Vec::new();
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                if !self.tile(x, y).is_some_and(|tile| tile.flags.door) {
+                    continue;
+                }
+                let axis = if self.is_solid(x - 1, y) && self.is_solid(x + 1, y) {
+                    DoorAxis::X
+                } else {
+                    DoorAxis::Y
+                };
+                doors.push(Door::new(x, y, axis));
+            }
+        }
+        doors
+    }
+
+    /// Replace the state of doors with saved ones. Every saved door must be on a door tile.
+    pub(crate) fn restore_doors(&mut self, doors: Vec<Door>) -> std::result::Result<(), String> {
+        for saved in doors {
+            let door = self
+                .door_mut(saved.x, saved.y)
+                .ok_or_else(|| format!("There is no door tile at ({}, {})", saved.x, saved.y))?;
+            *door = Door {
+                open: saved.open.clamp(0.0, 1.0),
+                ..saved
+            };
+        }
+        Ok(())
+    }
+
+    /// Restore push walls that were saved while moving.
+    pub(crate) fn restore_push_walls(
+        &mut self,
+        push_walls: Vec<PushWall>,
+    ) -> std::result::Result<(), String> {
+        for push_wall in &push_walls {
+            let (target_x, target_y) = push_wall.target();
+            if push_wall.step_x.abs() + push_wall.step_y.abs() != 1 {
+                return Err(format!(
+                    "The push wall at ({}, {}) must move one tile along x or y",
+                    push_wall.x, push_wall.y
+                ));
+            }
+            if !self.contains(push_wall.x, push_wall.y) || !self.contains(target_x, target_y) {
+                return Err(format!(
+                    "The push wall at ({}, {}) is outside the map",
+                    push_wall.x, push_wall.y
+                ));
+            }
+        }
+        self.push_walls = push_walls;
+        Ok(())
+    }
+
+    pub(crate) fn check_tiles(
+        width: u32,
+        height: u32,
+        tiles: &[Tile],
+    ) -> std::result::Result<(), String> {
+        // tiles are indexed with u32s, see index
+        let count = width
+            .checked_mul(height)
+            .ok_or_else(|| format!("A {}x{} map is too large", width, height))?;
+        if tiles.len() != count as usize {
+            return Err(format!(
+                "A {}x{} map needs {} tiles, found {}",
+                width,
+                height,
+                count,
+                tiles.len()
+            ));
+        }
+        // a push wall which isn't solid could be walked through, and push_wall ignores it
+        if let Some(index) = tiles
+            .iter()
+            .position(|tile| tile.flags.push && !tile.flags.solid)
+        {
+            return Err(format!(
+                "The push wall at ({}, {}) must also be solid",
+                index as u32 % width,
+                index as u32 / width
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_spawn(
+        width: u32,
+        height: u32,
+        spawn: &SpawnPoint,
+    ) -> std::result::Result<(), String> {
+        if spawn.x < 0.0 || spawn.y < 0.0 || spawn.x >= width as f32 || spawn.y >= height as f32 {
+            return Err(format!(
+                "Spawn point ({}, {}) is outside the map",
+                spawn.x, spawn.y
+            ));
+        }
+        Ok(())
+    }
+
+    /// Load a map from a file, `.json` files are read as JSON and anything else as an ASCII map.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read map {}", path.display()))?;
+        let map = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Map::from_json(&source),
+            _ => Map::from_ascii(&source),
+        };
+        map.with_context(|| format!("Could not load map {}", path.display()))
+    }
+
+    pub fn from_ascii(source: &str) -> std::result::Result<Self, MapLoadError> {
+        map_loader::parse_ascii(source)
+    }
+
+    pub fn from_json(source: &str) -> std::result::Result<Self, MapLoadError> {
+        map_loader::parse_json(source)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn tile(&self, x: i32, y: i32) -> Option<&Tile> {
+        self.index(x, y).map(|index| &self.tiles[index])
+    }
+
+    pub fn tile_mut(&mut self, x: i32, y: i32) -> Option<&mut Tile> {
+        self.index(x, y).map(|index| &mut self.tiles[index])
+    }
+
+    pub fn tiles(&self) -> &Vec<Tile> {
+        &self.tiles
+    }
+
+    pub fn spawn(&self) -> SpawnPoint {
+        self.spawn
+    }
+
+    pub fn set_spawn(&mut self, spawn: SpawnPoint) {
+        self.spawn = spawn;
+    }
+
+    /// Texture ids and the paths of the images they refer to.
+    pub fn textures(&self) -> &BTreeMap<u32, String> {
+        &self.textures
+    }
+
+    pub fn texture_path(&self, id: u32) -> Option<&str> {
+        self.textures.get(&id).map(|path| path.as_str())
+    }
+
+    pub fn doors(&self) -> &Vec<Door> {
+        &self.doors
+    }
+
+    pub fn door(&self, x: i32, y: i32) -> Option<&Door> {
+        self.doors.iter().find(|door| door.x == x && door.y == y)
+    }
+
+    pub fn door_mut(&mut self, x: i32, y: i32) -> Option<&mut Door> {
+        self.doors
+            .iter_mut()
+            .find(|door| door.x == x && door.y == y)
+    }
+
+    /// Open or close the door at (x, y), returning the state it is now in.
+    pub fn toggle_door(&mut self, x: i32, y: i32) -> Option<DoorState> {
+        let door = self.door_mut(x, y)?;
+        door.toggle();
+        Some(door.state)
+    }
+
+    /// Push walls that are currently moving.
+    pub fn push_walls(&self) -> &Vec<PushWall> {
+        &self.push_walls
+    }
+
+    /// The tile the push wall at (x, y) would move into if pushed along (step_x, step_y).
+    /// None if there is no push wall there, or the tile it would move into is blocked by the map.
+    /// The map doesn't know where the player or anything else is, so the caller has to check the
+    /// tile is free of them before pushing, see Player::interact.
+    pub fn push_target(&self, x: i32, y: i32, step_x: i32, step_y: i32) -> Option<(i32, i32)> {
+        if step_x.abs() + step_y.abs() != 1 {
+            return None;
+        }
+        let tile = self.tile(x, y)?;
+        if !tile.flags.push || !tile.flags.solid {
+            return None;
+        }
+        let (target_x, target_y) = (x + step_x, y + step_y);
+        let blocked = self
+            .tile(target_x, target_y)
+            .is_none_or(|target| target.flags.solid || target.flags.door || target.flags.push)
+            || self
+                .push_walls
+                .iter()
+                .any(|push_wall| push_wall.covers(target_x, target_y));
+        (!blocked).then_some((target_x, target_y))
+    }
+
+    /// Start the push wall at (x, y) moving one tile along (step_x, step_y).
+    /// Returns false if there is no push wall there, or the tile it would move into is blocked,
+    /// see push_target.
+    pub fn push_wall(&mut self, x: i32, y: i32, step_x: i32, step_y: i32) -> bool {
+        if self.push_target(x, y, step_x, step_y).is_none() {
+            return false;
+        }
+        let tile = *self.tile(x, y).unwrap();
+        // the tile the wall leaves is open straight away, the moving wall is hit as a partial wall
+        let source = self.tile_mut(x, y).unwrap();
+        source.flags.solid = false;
+        source.flags.push = false;
+        self.push_walls.push(PushWall {
+            x,
+            y,
+            step_x,
+            step_y,
+            wall: tile.wall,
+            offset: 0.0,
+        });
+        true
+    }
+
+    /// Move doors and push walls along by `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        for door in &mut self.doors {
+            door.update(dt);
+        }
+        let mut index = 0;
+        while index < self.push_walls.len() {
+            let push_wall = &mut self.push_walls[index];
+            push_wall.offset += PUSH_WALL_SPEED * dt;
+            if push_wall.offset < 1.0 {
+                index += 1;
+                continue;
+            }
+            // the wall has arrived, it becomes a normal wall again which can be pushed further
+            let push_wall = self.push_walls.remove(index);
+            let (target_x, target_y) = push_wall.target();
+            if let Some(target) = self.tile_mut(target_x, target_y) {
+                target.wall = push_wall.wall;
+                target.flags.solid = true;
+                target.flags.push = true;
+            }
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if self.contains(x, y) {
+            Some((y as u32 * self.width + x as u32) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl TileMap for Map {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    fn is_solid(&self, x: i32, y: i32) -> bool {
+        // Anything outside the map is treated as a wall, doors are partial walls even when
+        // flagged solid
+        self.tile(x, y)
+            .is_none_or(|tile| tile.flags.solid && !tile.flags.door)
+    }
+
+    fn wall_texture(&self, x: i32, y: i32) -> u32 {
+        if let Some(push_wall) = self
+            .push_walls
+            .iter()
+            .find(|push_wall| push_wall.covers(x, y))
+        {
+            return push_wall.wall;
+        }
+        self.tile(x, y).map_or(0, |tile| tile.wall)
+    }
+
+    fn partial_wall(&self, x: i32, y: i32) -> Option<WallBounds> {
+        if self.tile(x, y)?.flags.door {
+            return self.door(x, y)?.bounds();
+        }
+        self.push_walls
+            .iter()
+            .find(|push_wall| push_wall.covers(x, y))
+            .map(|push_wall| push_wall.bounds())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two push walls with a gap between them
+    const PUSH_MAP: &str = "\
+[textures]
+1 = brick.png
+2 = floor.png
+3 = secret.png
+
+[legend]
+# wall=1 floor=2 ceiling=2 solid
+. floor=2 ceiling=2
+S wall=3 floor=2 ceiling=2 solid push
+P floor=2 ceiling=2 spawn=0
+
+[map]
+######
+#PS.S#
+######
+";
+
+    fn push_map() -> Map {
+        Map::from_ascii(PUSH_MAP).unwrap()
+    }
+
+    #[test]
+    fn push_wall_needs_a_push_wall_and_a_single_step() {
+        let mut map = push_map();
+        assert!(!map.push_wall(0, 0, 1, 0));
+        assert!(!map.push_wall(3, 1, 1, 0));
+        assert!(!map.push_wall(2, 1, 1, 1));
+        assert!(!map.push_wall(2, 1, 0, 0));
+        assert!(map.push_walls().is_empty());
+    }
+
+    #[test]
+    fn blocked_push_wall_stays_put() {
+        let mut map = push_map();
+        // into the outer walls
+        assert!(!map.push_wall(2, 1, 0, -1));
+        assert!(!map.push_wall(2, 1, 0, 1));
+        assert!(!map.push_wall(4, 1, 1, 0));
+        assert!(map.push_walls().is_empty());
+        assert!(map.is_solid(2, 1));
+        // a moving wall blocks the tile it is moving into
+        assert!(map.push_wall(2, 1, 1, 0));
+        assert!(!map.push_wall(4, 1, -1, 0));
+        assert!(map.is_solid(4, 1));
+    }
+
+    #[test]
+    fn push_wall_moves_into_the_next_tile() {
+        let mut map = push_map();
+        assert!(map.push_wall(2, 1, 1, 0));
+        // the tile it leaves opens straight away, both tiles show the moving wall
+        assert!(!map.is_solid(2, 1));
+        assert_eq!(map.wall_texture(2, 1), 3);
+        assert_eq!(map.wall_texture(3, 1), 3);
+        map.update(0.5 / PUSH_WALL_SPEED);
+        assert_eq!(map.push_walls().len(), 1);
+        let bounds = map.partial_wall(3, 1).unwrap();
+        assert_eq!((bounds.min.x, bounds.max.x), (2.5, 3.5));
+    }
+
+    #[test]
+    fn push_wall_becomes_solid_when_it_arrives() {
+        let mut map = push_map();
+        assert!(map.push_wall(2, 1, 1, 0));
+        map.update(0.5 / PUSH_WALL_SPEED);
+        map.update(0.75 / PUSH_WALL_SPEED);
+        assert!(map.push_walls().is_empty());
+        let tile = *map.tile(3, 1).unwrap();
+        assert_eq!(tile.wall, 3);
+        assert!(tile.flags.solid && tile.flags.push);
+        assert!(map.is_solid(3, 1));
+        assert!(map.partial_wall(3, 1).is_none());
+        assert!(!map.is_solid(2, 1));
+        // it is blocked by the other push wall, but can be pushed back the way it came
+        assert!(!map.push_wall(3, 1, 1, 0));
+        assert!(map.push_wall(3, 1, -1, 0));
+    }
+
+    #[test]
+    fn update_moves_doors() {
+        let mut map = Map::from_ascii(&PUSH_MAP.replace("#PS.S#", "#P.D.#").replace(
+            "S wall=3 floor=2 ceiling=2 solid push",
+            "D wall=3 floor=2 ceiling=2 door",
+        ))
+        .unwrap();
+        assert_eq!(map.toggle_door(3, 1), Some(DoorState::Opening));
+        assert_eq!(map.toggle_door(2, 1), None);
+        map.update(2.0);
+        let door = map.door(3, 1).unwrap();
+        assert_eq!((door.state, door.open), (DoorState::Open, 1.0));
+        assert!(map.partial_wall(3, 1).is_none());
+    }
+}
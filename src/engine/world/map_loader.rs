@@ -0,0 +1,539 @@
+use crate::engine::world::map::{Map, MapData, SpawnPoint, Tile, TileFlags};
+use std::collections::{BTreeMap, HashMap};
+
+// ASCII maps are split into sections. [textures] and [legend] come first, [map] must come last
+// as every line after it is read as a row of the grid. Outside of [map], blank lines and lines
+// starting with // are ignored.
+//
+// [textures]
+// 1 = src/assets/brick.png
+// 2 = src/assets/floor.png
+// 3 = src/assets/door.png
+//
+// [legend]
+// # wall=1 floor=2 ceiling=2 solid
+// . floor=2 ceiling=2
+// D wall=3 floor=2 ceiling=2 door
+// S wall=1 floor=2 ceiling=2 solid push
+// P floor=2 ceiling=2 spawn=90
+//
+// [map]
+// ######
+// #P.D.#
+// ###S##
+// ###.##
+// ######
+//
+// Legend entries are a single character followed by texture ids (wall, floor, ceiling) and flags
+// (solid, door, transparent, push). spawn=<angle> marks the symbol where the player starts,
+// it must appear exactly once in the grid. Doors start closed and run between the walls either
+// side of them, push walls must also be solid.
+//
+// JSON maps are the serialised form of Map: width, height, a row by row array of tiles,
+// the spawn point, the texture table, and the state of doors and moving push walls.
+
+/// Why a map could not be loaded, and where. Lines and columns start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapLoadError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl MapLoadError {
+    pub fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for MapLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for MapLoadError {}
+
+impl From<serde_json::Error> for MapLoadError {
+    fn from(error: serde_json::Error) -> Self {
+        // serde_json reports the position of the error alongside the message, strip it off
+        // as it's kept in line and column instead
+        let message = error.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(index) => message[..index].to_string(),
+            None => message,
+        };
+        MapLoadError::new(error.line(), error.column(), message)
+    }
+}
+
+pub(crate) fn parse_json(source: &str) -> Result<Map, MapLoadError> {
+    let data: MapData = serde_json::from_str(source)?;
+    // The values have already been parsed, so point validation errors at their keys instead
+    Map::check_tiles(data.width, data.height, &data.tiles)
+        .map_err(|message| error_at_key(source, "tiles", message))?;
+    Map::check_spawn(data.width, data.height, &data.spawn)
+        .map_err(|message| error_at_key(source, "spawn", message))?;
+    let mut map = Map::new(
+        data.width,
+        data.height,
+        data.tiles,
+        data.spawn,
+        data.textures,
+    )
+    .expect("map was validated above");
+    map.restore_doors(data.doors)
+        .map_err(|message| error_at_key(source, "doors", message))?;
+    map.restore_push_walls(data.push_walls)
+        .map_err(|message| error_at_key(source, "push_walls", message))?;
+    Ok(map)
+}
+
+fn error_at_key(source: &str, key: &str, message: String) -> MapLoadError {
+    let offset = source.find(&format!("\"{}\"", key)).unwrap_or(0);
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let column = before[line_start..].chars().count() + 1;
+    MapLoadError::new(line, column, message)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    None,
+    Textures,
+    Legend,
+    Map,
+}
+
+struct LegendEntry {
+    tile: Tile,
+    // spawn angle, if this symbol is where the player starts
+    spawn: Option<f32>,
+}
+
+pub(crate) fn parse_ascii(source: &str) -> Result<Map, MapLoadError> {
+    let mut section = Section::None;
+    let mut textures = BTreeMap::new();
+    let mut legend: HashMap<char, LegendEntry> = HashMap::new();
+    let mut map_header_line = None;
+    let mut width = None;
+    let mut height = 0;
+    let mut tiles = Vec::new();
+    let mut spawn = None;
+    let mut last_line = 1;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        last_line = line_number;
+        let line = line.trim_end_matches('\r');
+
+        if section == Section::Map {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let row: Vec<char> = line.trim_end().chars().collect();
+            let row_width = *width.get_or_insert(row.len());
+            if row.len() != row_width {
+                return Err(MapLoadError::new(
+                    line_number,
+                    row.len().min(row_width) + 1,
+                    format!(
+                        "Row is {} tiles wide, but the first row is {} tiles wide",
+                        row.len(),
+                        row_width
+                    ),
+                ));
+            }
+            for (x, symbol) in row.iter().enumerate() {
+                let entry = legend.get(symbol).ok_or_else(|| {
+                    MapLoadError::new(
+                        line_number,
+                        x + 1,
+                        format!("Symbol '{}' is not in the legend", symbol),
+                    )
+                })?;
+                if let Some(angle) = entry.spawn {
+                    if spawn.is_some() {
+                        return Err(MapLoadError::new(
+                            line_number,
+                            x + 1,
+                            "The map has more than one spawn point",
+                        ));
+                    }
+                    spawn = Some(SpawnPoint {
+                        x: x as f32 + 0.5,
+                        y: height as f32 + 0.5,
+                        angle,
+                    });
+                }
+                tiles.push(entry.tile);
+            }
+            height += 1;
+            continue;
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with("//") {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            section = match &trimmed[1..trimmed.len() - 1] {
+                "textures" => Section::Textures,
+                "legend" => Section::Legend,
+                "map" => {
+                    map_header_line = Some(line_number);
+                    Section::Map
+                }
+                name => {
+                    return Err(MapLoadError::new(
+                        line_number,
+                        indent + 1,
+                        format!("Unknown section [{}]", name),
+                    ))
+                }
+            };
+            continue;
+        }
+
+        match section {
+            Section::None => {
+                return Err(MapLoadError::new(
+                    line_number,
+                    indent + 1,
+                    "Expected a section header such as [legend] or [map]",
+                ))
+            }
+            Section::Textures => {
+                let (id, path) = parse_texture(trimmed, line_number, indent)?;
+                textures.insert(id, path);
+            }
+            Section::Legend => {
+                let (symbol, entry) = parse_legend_entry(line, line_number, indent)?;
+                if legend.insert(symbol, entry).is_some() {
+                    return Err(MapLoadError::new(
+                        line_number,
+                        indent + 1,
+                        format!("Symbol '{}' is defined more than once", symbol),
+                    ));
+                }
+            }
+            Section::Map => unreachable!(),
+        }
+    }
+
+    let map_header_line = map_header_line
+        .ok_or_else(|| MapLoadError::new(last_line, 1, "The map has no [map] section"))?;
+    let width =
+        width.ok_or_else(|| MapLoadError::new(map_header_line, 1, "The [map] section is empty"))?;
+    let spawn =
+        spawn.ok_or_else(|| MapLoadError::new(map_header_line, 1, "The map has no spawn point"))?;
+    Map::new(width as u32, height, tiles, spawn, textures)
+        .map_err(|message| MapLoadError::new(map_header_line, 1, message))
+}
+
+fn parse_texture(
+    line: &str,
+    line_number: usize,
+    indent: usize,
+) -> Result<(u32, String), MapLoadError> {
+    let (id, path) = line.split_once('=').ok_or_else(|| {
+        MapLoadError::new(
+            line_number,
+            indent + 1,
+            "Expected a texture in the form <id> = <path>",
+        )
+    })?;
+    let id = id.trim().parse::<u32>().map_err(|_| {
+        MapLoadError::new(
+            line_number,
+            indent + 1,
+            format!("Texture id '{}' is not a whole number", id.trim()),
+        )
+    })?;
+    let path = path.trim();
+    if path.is_empty() {
+        return Err(MapLoadError::new(
+            line_number,
+            indent + line.len() + 1,
+            "Texture path is missing",
+        ));
+    }
+    Ok((id, path.to_string()))
+}
+
+fn parse_legend_entry(
+    line: &str,
+    line_number: usize,
+    indent: usize,
+) -> Result<(char, LegendEntry), MapLoadError> {
+    let symbol = line[indent..].chars().next().unwrap();
+    let mut tile = Tile::default();
+    let mut flags = TileFlags::default();
+    let mut spawn = None;
+    let mut push_column = None;
+
+    // Walk the attributes keeping track of their columns for error reporting
+    let mut column = indent + 2;
+    let attributes = &line[indent + symbol.len_utf8()..];
+    if !attributes.is_empty() && !attributes.starts_with(char::is_whitespace) {
+        return Err(MapLoadError::new(
+            line_number,
+            column,
+            "Legend symbols must be a single character followed by a space",
+        ));
+    }
+    let mut rest = attributes;
+    while !rest.is_empty() {
+        let skipped = rest.len() - rest.trim_start().len();
+        column += rest[..skipped].chars().count();
+        rest = &rest[skipped..];
+        if rest.is_empty() {
+            break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let attribute = &rest[..end];
+        match attribute.split_once('=') {
+            Some((key, value)) => {
+                let value_column = column + key.chars().count() + 1;
+                match key {
+                    "wall" => tile.wall = parse_id(value, line_number, value_column)?,
+                    "floor" => tile.floor = parse_id(value, line_number, value_column)?,
+                    "ceiling" => tile.ceiling = parse_id(value, line_number, value_column)?,
+                    "spawn" => {
+                        spawn = Some(value.parse::<f32>().map_err(|_| {
+                            MapLoadError::new(
+                                line_number,
+                                value_column,
+                                format!("Spawn angle '{}' is not a number", value),
+                            )
+                        })?)
+                    }
+                    _ => {
+                        return Err(MapLoadError::new(
+                            line_number,
+                            column,
+                            format!("Unknown legend attribute '{}'", key),
+                        ))
+                    }
+                }
+            }
+            None => match attribute {
+                "solid" => flags.solid = true,
+                "door" => flags.door = true,
+                "transparent" => flags.transparent = true,
+                "push" => {
+                    flags.push = true;
+                    push_column = Some(column);
+                }
+                // allow spawn on its own, facing along +x
+                "spawn" => spawn = Some(0.0),
+                _ => {
+                    return Err(MapLoadError::new(
+                        line_number,
+                        column,
+                        format!("Unknown legend flag '{}'", attribute),
+                    ))
+                }
+            },
+        }
+        column += attribute.chars().count();
+        rest = &rest[end..];
+    }
+    if let Some(column) = push_column.filter(|_| !flags.solid) {
+        return Err(MapLoadError::new(
+            line_number,
+            column,
+            "Push walls must also be solid",
+        ));
+    }
+    tile.flags = flags;
+    Ok((symbol, LegendEntry { tile, spawn }))
+}
+
+fn parse_id(value: &str, line_number: usize, column: usize) -> Result<u32, MapLoadError> {
+    value.parse::<u32>().map_err(|_| {
+        MapLoadError::new(
+            line_number,
+            column,
+            format!("Texture id '{}' is not a whole number", value),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::traits::tile_map::TileMap;
+
+    const ASCII_MAP: &str = "\
+[textures]
+1 = brick.png
+2 = floor.png
+3 = door.png
+
+[legend]
+# wall=1 floor=2 ceiling=2 solid
+. floor=2 ceiling=2
+D wall=3 floor=2 ceiling=2 door
+P floor=2 ceiling=2 spawn=90
+
+[map]
+#####
+#P.D#
+#####
+";
+
+    fn ascii_error(source: &str) -> MapLoadError {
+        parse_ascii(source).unwrap_err()
+    }
+
+    #[test]
+    fn parses_ascii_maps() {
+        let map = parse_ascii(ASCII_MAP).unwrap();
+        assert_eq!((map.width(), map.height()), (5, 3));
+        assert_eq!(
+            map.spawn(),
+            SpawnPoint {
+                x: 1.5,
+                y: 1.5,
+                angle: 90.0
+            }
+        );
+        assert_eq!(map.textures().len(), 3);
+        assert_eq!(map.texture_path(2), Some("floor.png"));
+        let wall = map.tile(0, 0).unwrap();
+        assert_eq!((wall.wall, wall.floor, wall.ceiling), (1, 2, 2));
+        assert!(wall.flags.solid && !wall.flags.door);
+        assert!(map.tile(3, 1).unwrap().flags.door);
+        assert!(map.door(3, 1).is_some());
+        assert!(!map.is_solid(2, 1));
+    }
+
+    #[test]
+    fn reports_symbols_missing_from_the_legend() {
+        let error = ascii_error(&ASCII_MAP.replace("#P.D#", "#P.X#"));
+        assert_eq!((error.line, error.column), (14, 4));
+        assert_eq!(error.message, "Symbol 'X' is not in the legend");
+    }
+
+    #[test]
+    fn reports_ragged_rows() {
+        let short = ascii_error(&ASCII_MAP.replace("#P.D#\n#####", "#P.D#\n####"));
+        assert_eq!((short.line, short.column), (15, 5));
+        let long = ascii_error(&ASCII_MAP.replace("#P.D#", "#P.D##"));
+        assert_eq!((long.line, long.column), (14, 6));
+        assert_eq!(
+            long.message,
+            "Row is 6 tiles wide, but the first row is 5 tiles wide"
+        );
+    }
+
+    #[test]
+    fn reports_a_second_spawn_point() {
+        let error = ascii_error(&ASCII_MAP.replace("#P.D#", "#PPD#"));
+        assert_eq!((error.line, error.column), (14, 3));
+        assert_eq!(error.message, "The map has more than one spawn point");
+    }
+
+    #[test]
+    fn reports_a_missing_spawn_point() {
+        let error = ascii_error(&ASCII_MAP.replace("#P.D#", "#..D#"));
+        assert_eq!((error.line, error.column), (12, 1));
+        assert_eq!(error.message, "The map has no spawn point");
+    }
+
+    #[test]
+    fn reports_a_missing_map_section() {
+        let source = &ASCII_MAP[..ASCII_MAP.find("\n\n[map]").unwrap()];
+        let error = ascii_error(source);
+        assert_eq!((error.line, error.column), (10, 1));
+        assert_eq!(error.message, "The map has no [map] section");
+    }
+
+    #[test]
+    fn reports_legend_errors_at_the_attribute() {
+        let flag = ascii_error(&ASCII_MAP.replace("ceiling=2 door", "ceiling=2 dor"));
+        assert_eq!((flag.line, flag.column), (9, 28));
+        assert_eq!(flag.message, "Unknown legend flag 'dor'");
+
+        let id = ascii_error(&ASCII_MAP.replace("# wall=1", "# wall=x"));
+        assert_eq!((id.line, id.column), (7, 8));
+        assert_eq!(id.message, "Texture id 'x' is not a whole number");
+
+        let duplicate = ascii_error(&ASCII_MAP.replace(". floor=2", "# floor=2"));
+        assert_eq!((duplicate.line, duplicate.column), (8, 1));
+    }
+
+    #[test]
+    fn reports_push_walls_which_are_not_solid() {
+        let source = ASCII_MAP
+            .replace("#P.D#", "#PSD#")
+            .replace("[map]", "S wall=1 floor=2 ceiling=2 push\n\n[map]");
+        let error = ascii_error(&source);
+        assert_eq!((error.line, error.column), (12, 28));
+        assert_eq!(error.message, "Push walls must also be solid");
+        assert!(parse_ascii(&source.replace("ceiling=2 push", "ceiling=2 solid push")).is_ok());
+
+        // JSON maps are checked the same way, the open tile at (2, 1) is made a push wall
+        let map = parse_ascii(ASCII_MAP).unwrap();
+        let mut json: serde_json::Value = serde_json::from_str(&map.to_json().unwrap()).unwrap();
+        json["tiles"][7]["flags"]["push"] = true.into();
+        let error = parse_json(&json.to_string()).unwrap_err();
+        assert_eq!(error.message, "The push wall at (2, 1) must also be solid");
+    }
+
+    #[test]
+    fn reports_lines_before_any_section() {
+        let error = ascii_error(&format!("  oops\n{}", ASCII_MAP));
+        assert_eq!((error.line, error.column), (1, 3));
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let map = parse_ascii(ASCII_MAP).unwrap();
+        let loaded = parse_json(&map.to_json().unwrap()).unwrap();
+        assert_eq!((loaded.width(), loaded.height()), (5, 3));
+        assert_eq!(loaded.tiles(), map.tiles());
+        assert_eq!(loaded.spawn(), map.spawn());
+        assert_eq!(loaded.textures(), map.textures());
+        assert_eq!(loaded.doors().len(), 1);
+    }
+
+    #[test]
+    fn reports_json_syntax_errors_where_they_are() {
+        let error = parse_json("{\n  \"width\": 1,\n  \"height\" 1\n}").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 12));
+        assert_eq!(error.message, "expected `:`");
+    }
+
+    #[test]
+    fn reports_json_validation_errors_at_their_key() {
+        let source = "{\n  \"width\": 2,\n  \"height\": 1,\n  \"tiles\": [{}],\n  \
+                      \"spawn\": { \"x\": 0.5, \"y\": 0.5 }\n}";
+        let error = parse_json(source).unwrap_err();
+        assert_eq!((error.line, error.column), (4, 3));
+        assert_eq!(error.message, "A 2x1 map needs 2 tiles, found 1");
+
+        let source = source.replace("[{}]", "[{}, {}]").replace("0.5 }", "3.0 }");
+        let error = parse_json(&source).unwrap_err();
+        assert_eq!((error.line, error.column), (5, 3));
+    }
+
+    #[test]
+    fn rejects_json_maps_too_large_to_index() {
+        let source =
+            r#"{"width": 65536, "height": 65536, "tiles": [], "spawn": {"x": 0.5, "y": 0.5}}"#;
+        let error = parse_json(source).unwrap_err();
+        assert_eq!(error.message, "A 65536x65536 map is too large");
+    }
+}
@@ -0,0 +1,4 @@
+pub mod door;
+pub mod map;
+pub mod map_loader;
+pub mod player;
@@ -0,0 +1,525 @@
+use crate::engine::primitives::vector::Vector2;
+use crate::engine::raycasting::ray_caster::{GridWalk, PlayerPose, WallSide};
+use crate::engine::traits::tile_map::{TileMap, WallBounds};
+use crate::engine::world::door::DoorState;
+use crate::engine::world::map::{Map, SpawnPoint};
+
+// The player is a circle which slides along walls rather than stopping dead when it touches
+// one. Each move is split into steps no longer than half the player's radius, so fast moves or
+// long frames can't tunnel through thin walls such as doors. Within a step the player moves along
+// x and then along y, being pushed out of any walls it overlaps after each.
+
+// How far away doors and push walls can be used from, in tiles
+const DEFAULT_REACH: f32 = 1.5;
+// In tiles
+const DEFAULT_RADIUS: f32 = 0.25;
+// Tiles per second and degrees per second
+const DEFAULT_MOVE_SPEED: f32 = 3.0;
+const DEFAULT_TURN_SPEED: f32 = 120.0;
+
+/// How the player wants to move this frame, relative to where they are facing.
+/// Each is from -1 to 1 and is scaled by the player's speeds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerInput {
+    // positive moves forwards
+    pub forward: f32,
+    // positive moves right
+    pub strafe: f32,
+    // positive turns right (clockwise looking down on the map)
+    pub turn: f32,
+}
+
+/// What happened when the player used whatever they were looking at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interaction {
+    // the door started opening or closing
+    Door { x: i32, y: i32, state: DoorState },
+    // the push wall started moving one tile away from the player
+    PushWall { x: i32, y: i32 },
+}
+
+pub struct Player {
+    position: Vector2<f32>,
+    // degrees, see PlayerPose::new
+    angle: f32,
+    // where the player was before the last update, for interpolating between updates
+    previous_position: Vector2<f32>,
+    previous_angle: f32,
+    fov: f32,
+    reach: f32,
+    radius: f32,
+    move_speed: f32,
+    turn_speed: f32,
+}
+
+impl Player {
+    pub fn new(position: Vector2<f32>, angle: f32, fov: f32) -> Self {
+        let angle = angle.rem_euclid(360.0);
+        Self {
+            position,
+            angle,
+            previous_position: position,
+            previous_angle: angle,
+            fov,
+            reach: DEFAULT_REACH,
+            radius: DEFAULT_RADIUS,
+            move_speed: DEFAULT_MOVE_SPEED,
+            turn_speed: DEFAULT_TURN_SPEED,
+        }
+    }
+
+    pub fn from_spawn(spawn: SpawnPoint, fov: f32) -> Self {
+        Player::new(
+            Vector2 {
+                x: spawn.x,
+                y: spawn.y,
+            },
+            spawn.angle,
+            fov,
+        )
+    }
+
+    /// The camera the map is ray cast from.
+    pub fn pose(&self) -> PlayerPose {
+        PlayerPose::new(self.position, self.angle, self.fov)
+    }
+
+    /// The camera part way between the last two updates, `alpha` is from 0 (the previous update)
+    /// to 1 (the latest), see Time::alpha.
+    pub fn interpolated_pose(&self, alpha: f32) -> PlayerPose {
+        let position = self.previous_position.lerp(self.position, alpha);
+        // turn the short way round
+        let turned = (self.angle - self.previous_angle + 180.0).rem_euclid(360.0) - 180.0;
+        PlayerPose::new(position, self.previous_angle + turned * alpha, self.fov)
+    }
+
+    pub fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+
+    /// Move straight to `position` without colliding or interpolating.
+    pub fn set_position(&mut self, position: Vector2<f32>) {
+        self.position = position;
+        self.previous_position = position;
+    }
+
+    pub fn angle(&self) -> f32 {
+        self.angle
+    }
+
+    /// Face `angle` straight away, without interpolating.
+    pub fn set_angle(&mut self, angle: f32) {
+        self.angle = angle.rem_euclid(360.0);
+        self.previous_angle = self.angle;
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov;
+    }
+
+    pub fn reach(&self) -> f32 {
+        self.reach
+    }
+
+    pub fn set_reach(&mut self, reach: f32) {
+        self.reach = reach;
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius;
+    }
+
+    pub fn move_speed(&self) -> f32 {
+        self.move_speed
+    }
+
+    pub fn set_move_speed(&mut self, move_speed: f32) {
+        self.move_speed = move_speed;
+    }
+
+    pub fn turn_speed(&self) -> f32 {
+        self.turn_speed
+    }
+
+    pub fn set_turn_speed(&mut self, turn_speed: f32) {
+        self.turn_speed = turn_speed;
+    }
+
+    /// Turn by `degrees` straight away, positive turns right. Used for mouse look, which
+    /// shouldn't wait for the next update.
+    pub fn turn(&mut self, degrees: f32) {
+        self.angle = (self.angle + degrees).rem_euclid(360.0);
+        self.previous_angle = (self.previous_angle + degrees).rem_euclid(360.0);
+    }
+
+    /// Turn and move the player for an update lasting `dt` seconds, colliding with the map.
+    pub fn update(&mut self, input: &PlayerInput, map: &impl TileMap, dt: f32) {
+        self.previous_position = self.position;
+        self.previous_angle = self.angle;
+        self.angle =
+            (self.angle + input.turn.clamp(-1.0, 1.0) * self.turn_speed * dt).rem_euclid(360.0);
+
+        let angle = self.angle.to_radians();
+        let forward = Vector2 {
+            x: angle.cos(),
+            y: angle.sin(),
+        };
+        let right = Vector2 {
+            x: -forward.y,
+            y: forward.x,
+        };
+        let mut velocity = forward * input.forward + right * input.strafe;
+        // moving diagonally isn't any faster
+        if velocity.magnitude() > 1.0 {
+            velocity.normalise();
+        }
+        let distance = self.move_speed * dt;
+        self.move_by(velocity * distance, map);
+    }
+
+    /// Move by `offset` tiles, sliding along any walls in the way.
+    pub fn move_by(&mut self, offset: Vector2<f32>, map: &impl TileMap) {
+        let length = offset.magnitude();
+        if length == 0.0 || !length.is_finite() {
+            return;
+        }
+        // a small floor on the step keeps tiny radii from taking forever
+        let max_step = (self.radius * 0.5).max(0.01);
+        let steps = (length / max_step).ceil().max(1.0) as u32;
+        let step = Vector2 {
+            x: offset.x / steps as f32,
+            y: offset.y / steps as f32,
+        };
+        for _ in 0..steps {
+            self.position.x += step.x;
+            self.position = push_out(map, self.position, self.radius);
+            self.position.y += step.y;
+            self.position = push_out(map, self.position, self.radius);
+        }
+    }
+
+    /// Use the door or push wall straight ahead, if there is one within reach.
+    /// Doors are opened or closed, push walls are pushed one tile away from the player.
+    pub fn interact(&self, map: &mut Map) -> Option<Interaction> {
+        let angle = self.angle.to_radians();
+        let direction = Vector2 {
+            x: angle.cos(),
+            y: angle.sin(),
+        };
+        // doors sit in the middle of their tile, so the player can be stood in front of a door
+        // while inside its tile
+        let walk = GridWalk::new(self.position, direction);
+        let (x, y) = walk.tile();
+        if map.tile(x, y).is_some_and(|tile| tile.flags.door) {
+            let state = map.toggle_door(x, y)?;
+            return Some(Interaction::Door { x, y, state });
+        }
+        // direction is a unit vector, so distances along the walk are in tiles
+        for step in walk {
+            if step.distance > self.reach || !map.contains(step.map_x, step.map_y) {
+                return None;
+            }
+            let tile = *map.tile(step.map_x, step.map_y)?;
+            if tile.flags.door {
+                let state = map.toggle_door(step.map_x, step.map_y)?;
+                return Some(Interaction::Door {
+                    x: step.map_x,
+                    y: step.map_y,
+                    state,
+                });
+            }
+            if map.is_solid(step.map_x, step.map_y) {
+                // push walls move away from the face the player is looking at
+                let (step_x, step_y) = match step.side {
+                    WallSide::West => (1, 0),
+                    WallSide::East => (-1, 0),
+                    WallSide::North => (0, 1),
+                    WallSide::South => (0, -1),
+                };
+                let (target_x, target_y) =
+                    map.push_target(step.map_x, step.map_y, step_x, step_y)?;
+                // the map doesn't know where the player is, so don't let a wall be pushed into
+                // a tile the player is standing in
+                let target = tile_bounds(target_x, target_y);
+                if (self.position - closest_point(self.position, &target)).magnitude() < self.radius
+                    || !map.push_wall(step.map_x, step.map_y, step_x, step_y)
+                {
+                    return None;
+                }
+                return Some(Interaction::PushWall {
+                    x: step.map_x,
+                    y: step.map_y,
+                });
+            }
+        }
+        None
+    }
+}
+
+// Push a circle out of every wall it overlaps. The nearest walls go first, otherwise sliding
+// along a straight wall catches on the corners where its tiles meet.
+fn push_out(map: &impl TileMap, mut position: Vector2<f32>, radius: f32) -> Vector2<f32> {
+    let min_x = (position.x - radius).floor() as i32;
+    let max_x = (position.x + radius).floor() as i32;
+    let min_y = (position.y - radius).floor() as i32;
+    let max_y = (position.y + radius).floor() as i32;
+    let mut walls: Vec<(f32, WallBounds)> = (min_y..=max_y)
+        .flat_map(|y| (min_x..=max_x).map(move |x| (x, y)))
+        .filter_map(|(x, y)| blocking_bounds(map, x, y))
+        .map(|bounds| {
+            (
+                (position - closest_point(position, &bounds)).magnitude(),
+                bounds,
+            )
+        })
+        .collect();
+    walls.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for (_, bounds) in walls {
+        position = push_out_of(position, radius, &bounds);
+    }
+    position
+}
+
+// The point of the wall nearest to `position`
+fn closest_point(position: Vector2<f32>, bounds: &WallBounds) -> Vector2<f32> {
+    Vector2 {
+        x: position.x.clamp(bounds.min.x, bounds.max.x),
+        y: position.y.clamp(bounds.min.y, bounds.max.y),
+    }
+}
+
+fn tile_bounds(x: i32, y: i32) -> WallBounds {
+    WallBounds {
+        min: Vector2 {
+            x: x as f32,
+            y: y as f32,
+        },
+        max: Vector2 {
+            x: x as f32 + 1.0,
+            y: y as f32 + 1.0,
+        },
+    }
+}
+
+fn blocking_bounds(map: &impl TileMap, x: i32, y: i32) -> Option<WallBounds> {
+    if map.is_solid(x, y) {
+        return Some(tile_bounds(x, y));
+    }
+    map.partial_wall(x, y)
+}
+
+fn push_out_of(position: Vector2<f32>, radius: f32, bounds: &WallBounds) -> Vector2<f32> {
+    let closest = closest_point(position, bounds);
+    let mut away = Vector2 {
+        x: position.x - closest.x,
+        y: position.y - closest.y,
+    };
+    let distance = away.magnitude();
+    if distance >= radius {
+        return position;
+    }
+    if distance > 0.0 {
+        away.normalise();
+        return Vector2 {
+            x: closest.x + away.x * radius,
+            y: closest.y + away.y * radius,
+        };
+    }
+    // the centre is inside the wall, leave through the nearest face
+    let exits = [
+        (position.x - bounds.min.x, -1.0, 0.0),
+        (bounds.max.x - position.x, 1.0, 0.0),
+        (position.y - bounds.min.y, 0.0, -1.0),
+        (bounds.max.y - position.y, 0.0, 1.0),
+    ];
+    let (depth, x, y) = exits
+        .into_iter()
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .unwrap();
+    Vector2 {
+        x: position.x + x * (depth + radius),
+        y: position.y + y * (depth + radius),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two rooms joined by a door running along x at (3, 3)
+    const ROOMS: &str = "\
+[textures]
+1 = brick.png
+2 = floor.png
+3 = door.png
+
+[legend]
+# wall=1 floor=2 ceiling=2 solid
+. floor=2 ceiling=2
+D wall=3 floor=2 ceiling=2 door
+P floor=2 ceiling=2 spawn=0
+
+[map]
+#######
+#.....#
+#P....#
+###D###
+#.....#
+#######
+";
+
+    // a corridor with a secret push wall at (3, 1)
+    const CORRIDOR: &str = "\
+[textures]
+1 = brick.png
+2 = floor.png
+
+[legend]
+# wall=1 floor=2 ceiling=2 solid
+S wall=1 floor=2 ceiling=2 solid push
+. floor=2 ceiling=2
+P floor=2 ceiling=2 spawn=0
+
+[map]
+#######
+#P.S..#
+#######
+";
+
+    const EPSILON: f32 = 1e-4;
+
+    fn rooms() -> Map {
+        Map::from_ascii(ROOMS).unwrap()
+    }
+
+    fn position(x: f32, y: f32) -> Vector2<f32> {
+        Vector2 { x, y }
+    }
+
+    #[test]
+    fn player_slides_along_walls() {
+        let map = rooms();
+        let mut player = Player::new(position(1.5, 1.5), 0.0, 90.0);
+        // moving diagonally into the top wall keeps going along it
+        player.move_by(position(1.0, -1.0), &map);
+        let expected = position(2.5, 1.0 + DEFAULT_RADIUS);
+        assert!(player.position().approx_eq(expected, EPSILON));
+    }
+
+    #[test]
+    fn push_out_of_leaves_through_the_nearest_face() {
+        let bounds = WallBounds {
+            min: position(1.0, 1.0),
+            max: position(2.0, 2.0),
+        };
+        // touching the corner, pushed away from it diagonally
+        let corner = push_out_of(position(0.9, 0.9), 0.25, &bounds);
+        assert!((corner - position(1.0, 1.0)).magnitude() - 0.25 < EPSILON);
+        assert!(corner.x < 1.0 && corner.y < 1.0);
+        // centre inside the wall, nearest the bottom face
+        let inside = push_out_of(position(1.4, 1.9), 0.25, &bounds);
+        assert!(inside.approx_eq(position(1.4, 2.25), EPSILON));
+        // far enough away already
+        assert_eq!(
+            push_out_of(position(0.5, 1.5), 0.25, &bounds),
+            position(0.5, 1.5)
+        );
+    }
+
+    #[test]
+    fn closed_door_stops_long_moves() {
+        let map = rooms();
+        let mut player = Player::new(position(3.5, 2.5), 90.0, 90.0);
+        // a five second frame would carry the player right through the door and the next room
+        let input = PlayerInput {
+            forward: 1.0,
+            ..Default::default()
+        };
+        player.update(&input, &map, 5.0);
+        assert!(player
+            .position()
+            .approx_eq(position(3.5, 3.5 - DEFAULT_RADIUS), EPSILON));
+    }
+
+    #[test]
+    fn open_door_can_be_walked_through() {
+        let mut map = rooms();
+        map.toggle_door(3, 3);
+        map.update(1.0 / crate::engine::world::door::DOOR_SPEED);
+        let mut player = Player::new(position(3.5, 2.5), 90.0, 90.0);
+        player.move_by(position(0.0, 1.5), &map);
+        assert!(player.position().approx_eq(position(3.5, 4.0), EPSILON));
+    }
+
+    #[test]
+    fn interact_toggles_doors_within_reach() {
+        let mut map = rooms();
+        let player = Player::new(position(3.5, 2.5), 90.0, 90.0);
+        assert_eq!(
+            player.interact(&mut map),
+            Some(Interaction::Door {
+                x: 3,
+                y: 3,
+                state: DoorState::Opening
+            })
+        );
+        assert_eq!(
+            player.interact(&mut map),
+            Some(Interaction::Door {
+                x: 3,
+                y: 3,
+                state: DoorState::Closing
+            })
+        );
+    }
+
+    #[test]
+    fn interact_ignores_doors_out_of_reach() {
+        let mut map = rooms();
+        // 1.8 tiles from the door's tile
+        let mut player = Player::new(position(3.5, 1.2), 90.0, 90.0);
+        assert_eq!(player.interact(&mut map), None);
+        assert_eq!(map.door(3, 3).unwrap().state, DoorState::Closed);
+        player.set_reach(2.0);
+        assert!(player.interact(&mut map).is_some());
+        // looking away from it
+        player.set_angle(0.0);
+        assert_eq!(player.interact(&mut map), None);
+    }
+
+    #[test]
+    fn interact_pushes_walls_away() {
+        let mut map = Map::from_ascii(CORRIDOR).unwrap();
+        let player = Player::new(position(2.5, 1.5), 0.0, 90.0);
+        assert_eq!(
+            player.interact(&mut map),
+            Some(Interaction::PushWall { x: 3, y: 1 })
+        );
+        let push_wall = map.push_walls()[0];
+        assert_eq!(push_wall.target(), (4, 1));
+        // already moving, so there is nothing more to push
+        assert_eq!(player.interact(&mut map), None);
+    }
+
+    #[test]
+    fn interact_does_not_push_walls_onto_the_player() {
+        let mut map = Map::from_ascii(CORRIDOR).unwrap();
+        // wide enough to reach past the wall into the tile it would move into
+        let mut player = Player::new(position(2.5, 1.5), 0.0, 90.0);
+        player.set_radius(1.6);
+        assert_eq!(map.push_target(3, 1, 1, 0), Some((4, 1)));
+        assert_eq!(player.interact(&mut map), None);
+        assert!(map.push_walls().is_empty());
+        assert!(map.tile(3, 1).unwrap().flags.push);
+        player.set_radius(1.4);
+        assert!(player.interact(&mut map).is_some());
+    }
+}
@@ -1,52 +1,53 @@
-use winit::{
-    dpi::{PhysicalSize, Size},
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+use anyhow::Result;
+
+pub mod engine;
+use engine::actors::entity::Entity3D;
+use engine::app::{run_app, App};
+use engine::config::EngineConfig;
+use engine::primitives::angle::Degrees;
+use engine::primitives::quaternion::Quaternion;
+use engine::primitives::vector::Vector3;
+use engine::render_data::RenderData;
+
+// Degrees per second the demo cube spins
+const CUBE_SPIN_SPEED: f32 = 15.0;
+const SPIN_AXIS: Vector3<f32> = Vector3 {
+    x: 0.0,
+    y: 1.0,
+    z: 0.0,
 };
 
-mod engine;
-use engine::render_data::{self, RenderData};
+// A textured cube spinning in front of the camera, shown when the engine is run on its own
+struct Demo;
+
+impl App for Demo {
+    fn init(render_data: &mut RenderData) -> Result<Self> {
+        render_data.set_texture("src/assets/calamitas.png")?;
+        render_data.set_entities(vec![Entity3D::new(
+            None,
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 3.0,
+            },
+            1.0,
+            Quaternion::identity(),
+            Vec::new(),
+            Vec::new(),
+        )]);
+        Ok(Demo)
+    }
 
-pub async fn run() {
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_inner_size(PhysicalSize::new(1280, 720))
-        .with_title("Strfive Caster")
-        .build(&event_loop)
-        .unwrap();
-    window
-        .set_cursor_grab(winit::window::CursorGrabMode::Confined)
-        .unwrap();
-    window.set_cursor_visible(false);
-    window.set_resizable(false);
-    let mut render_data = RenderData::new(window).await;
-    event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
-        match event {
-            Event::WindowEvent {
-                window_id,
-                ref event,
-            } if window_id == render_data.window().id() => {
-                match event {
-                    WindowEvent::CloseRequested => control_flow.set_exit(),
-                    _ => {}
-                }
-                if render_data.process_inputs(&event) {
-                    // This needs an extra match statement, as the window will close if the user is
-                    // not holding down a key or doing some other input
-                    *control_flow = ControlFlow::Exit;
-                }
-            }
-            Event::DeviceEvent { device_id, event } => render_data.device_event(&event),
-            Event::RedrawRequested(window_id) if window_id == render_data.window().id() => {
-                render_data.update();
-                render_data.render().unwrap();
-            }
-            Event::RedrawEventsCleared => {
-                render_data.window().request_redraw();
-            }
-            _ => (),
+    fn update(&mut self, render_data: &mut RenderData, dt: f32) {
+        for entity in render_data.entities_mut() {
+            entity.rotate(Quaternion::from_axis_angle(
+                SPIN_AXIS,
+                Degrees(-CUBE_SPIN_SPEED * dt),
+            ));
         }
-    })
+    }
+}
+
+pub fn run() -> Result<()> {
+    run_app::<Demo>(EngineConfig::default())
 }
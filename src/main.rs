@@ -1,4 +1,4 @@
 use effect_engine::run;
-fn main() {
-    pollster::block_on(run());
+fn main() -> anyhow::Result<()> {
+    run()
 }
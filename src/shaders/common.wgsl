@@ -0,0 +1,27 @@
+// Definitions shared by the engine's shaders, pulled in with #include "common.wgsl"
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+};
+
+// The transformation of an Entity3D, one column per location, see RawEntity3D
+struct EntityInput {
+    @location(2) transform_one: vec4<f32>,
+    @location(3) transform_two: vec4<f32>,
+    @location(4) transform_three: vec4<f32>,
+    @location(5) transform_four: vec4<f32>,
+};
+
+fn entity_transform(entity: EntityInput) -> mat4x4<f32> {
+    return mat4x4<f32>(
+        entity.transform_one,
+        entity.transform_two,
+        entity.transform_three,
+        entity.transform_four,
+    );
+}
+
+// How much light reaches something `distance` away, see WallShading
+fn distance_shade(distance: f32, falloff: f32) -> f32 {
+    return 1.0 / (1.0 + distance * falloff);
+}
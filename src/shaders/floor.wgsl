@@ -0,0 +1,67 @@
+// Floor and ceiling casting, see raycasting/floor_caster.rs for the CPU version of this shader.
+
+#include "common.wgsl"
+
+struct Floor {
+    position: vec2<f32>,
+    direction: vec2<f32>,
+    plane: vec2<f32>,
+    screen_size: vec2<f32>,
+    map_size: vec2<u32>,
+    falloff: f32,
+    padding: f32,
+};
+
+// Texture layers of the floor (x) and ceiling (y) of every tile, row by row
+struct Tiles {
+    layers: array<vec2<u32>>,
+};
+
+const NO_TEXTURE: u32 = 0xffffffffu;
+
+@group(0) @binding(0)
+var<uniform> floor_data: Floor;
+@group(0) @binding(1)
+var<storage, read> tiles: Tiles;
+@group(0) @binding(2)
+var t_textures: texture_2d_array<f32>;
+@group(0) @binding(3)
+var s_textures: sampler;
+
+// A single triangle which covers the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let x = f32((index << 1u) & 2u);
+    let y = f32(index & 2u);
+    return vec4<f32>(x * 2.0 - 1.0, y * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) pixel: vec4<f32>) -> @location(0) vec4<f32> {
+    // pixel.xy is the pixel centre, measured from the top left of the screen
+    let horizon = floor_data.screen_size.y / 2.0;
+    let offset = pixel.y - horizon;
+    if offset == 0.0 {
+        discard;
+    }
+    let distance = horizon / abs(offset);
+    let left = floor_data.direction - floor_data.plane;
+    let right = floor_data.direction + floor_data.plane;
+    let along = pixel.x / floor_data.screen_size.x;
+    let point = floor_data.position + distance * (left + (right - left) * along);
+
+    let cell = floor(point);
+    if cell.x < 0.0 || cell.y < 0.0 || cell.x >= f32(floor_data.map_size.x) || cell.y >= f32(floor_data.map_size.y) {
+        discard;
+    }
+    let tile = tiles.layers[u32(cell.y) * floor_data.map_size.x + u32(cell.x)];
+    // rows above the horizon show the ceiling
+    let layer = select(tile.x, tile.y, offset < 0.0);
+    if layer == NO_TEXTURE {
+        discard;
+    }
+
+    let colour = textureSampleLevel(t_textures, s_textures, point - cell, i32(layer), 0.0);
+    let shade = distance_shade(distance, floor_data.falloff);
+    return vec4<f32>(colour.rgb * shade, colour.a);
+}
@@ -1,14 +1,6 @@
 // Vertex shader
 
-// TODO: Implement Camera Struct
-
-// TODO: Convert to Entity3D
-struct EntityInput {
-    @location(2) transform_one: vec4<f32>,
-    @location(3) transform_two: vec4<f32>,
-    @location(4) transform_three: vec4<f32>,
-    @location(5) transform_four: vec4<f32>,
-};
+#include "common.wgsl"
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -20,28 +12,18 @@ struct VertexOutput {
     @location(0) tex_pos: vec2<f32>,
 };
 
-
-struct Camera {
-    proj_mat: mat4x4<f32>,
-}
-
-
 @group(1) @binding(0)
-var<uniform> camera_mat: mat4x4<f32>;
+var<uniform> camera: Camera;
+
 @vertex
 fn vs_main(
     model: VertexInput,
     entity: EntityInput,
 ) -> VertexOutput {
-    let transformation = mat4x4<f32>(
-        entity.transform_one,
-        entity.transform_two,
-        entity.transform_three,
-        entity.transform_four,
-    );
+    let transformation = entity_transform(entity);
     var out: VertexOutput;
     out.tex_pos = model.tex_pos;
-    out.clip_position = camera_mat * transformation * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * transformation * vec4<f32>(model.position, 1.0);
 
     return out;
 }
@@ -0,0 +1,67 @@
+// Vertex shader
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_pos: vec2<f32>,
+};
+
+struct EntityInput {
+    @location(2) position: vec2<u32>,
+    @location(3) rotation_one: vec2<f32>,
+    @location(4) rotation_two: vec2<f32>,
+    @location(5) scale_one: vec2<f32>,
+    @location(6) scale_two: vec2<f32>,
+    @location(7) origin: vec2<u32>,
+    @location(8) shade: f32,
+    @location(9) tex_offset: vec2<f32>,
+    @location(10) tex_size: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_pos: vec2<f32>,
+    @location(1) shade: f32,
+};
+
+// Screen size in pixels, the last two components are padding
+struct Screen {
+    size: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> screen: Screen;
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    entity: EntityInput,
+) -> VertexOutput {
+    let rotation = mat2x2<f32>(entity.rotation_one, entity.rotation_two);
+    let scale = mat2x2<f32>(entity.scale_one, entity.scale_two);
+    let origin = vec2<f32>(entity.origin);
+    // entity positions are in pixels from the top left of the screen
+    let local = scale * model.position;
+    let pixel = vec2<f32>(entity.position) + origin + rotation * (local - origin);
+    let ndc = vec2<f32>(
+        pixel.x / screen.size.x * 2.0 - 1.0,
+        1.0 - pixel.y / screen.size.y * 2.0,
+    );
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    // the quad's texture coordinates run from 0 to 1, map them onto the entity's region
+    out.tex_pos = entity.tex_offset + model.tex_pos * entity.tex_size;
+    out.shade = entity.shade;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let colour = textureSample(t_diffuse, s_diffuse, in.tex_pos);
+    return vec4<f32>(colour.rgb * in.shade, colour.a);
+}
@@ -0,0 +1,57 @@
+use effect_engine::engine::golden::{GoldenScene, GoldenTest, SceneEntity};
+use effect_engine::engine::primitives::angle::Degrees;
+use effect_engine::engine::primitives::quaternion::Quaternion;
+use effect_engine::engine::primitives::vector::{Vector2, Vector3};
+
+// Renders scenes headlessly and compares them with the references in tests/golden. After a
+// change that is meant to alter the output, check the new frames by eye and regenerate the
+// references with
+//
+//     UPDATE_GOLDEN=1 cargo test --test golden
+
+const REFERENCE_DIR: &str = "tests/golden";
+// a quadrant of red, green, blue and yellow, so it shows which way up each face is
+const TEXTURE: &str = "tests/golden/quadrants.png";
+
+// The demo cube three units in front of a camera at the origin
+fn cube_scene(rotation: Quaternion<f32>) -> GoldenScene {
+    GoldenScene {
+        width: 128,
+        height: 128,
+        camera_translation: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        camera_rotation: Vector2 { x: 0.0, y: 0.0 },
+        entities: vec![SceneEntity {
+            position: Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 3.0,
+            },
+            rotation,
+            scale: 1.0,
+        }],
+        texture_path: TEXTURE.to_string(),
+    }
+}
+
+#[test]
+fn cube_front() {
+    // the front face fills about half the height of the 45 degree field of view, upright
+    let frame = cube_scene(Quaternion::identity()).render().unwrap();
+    GoldenTest::new(REFERENCE_DIR)
+        .check("cube_front", &frame)
+        .unwrap();
+}
+
+#[test]
+fn cube_turned() {
+    // three faces show, which catches faces being culled the wrong way round
+    let rotation = Quaternion::from_euler(Degrees(30.0), Degrees(45.0), Degrees(0.0));
+    let frame = cube_scene(rotation).render().unwrap();
+    GoldenTest::new(REFERENCE_DIR)
+        .check("cube_turned", &frame)
+        .unwrap();
+}